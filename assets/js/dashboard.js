@@ -56,6 +56,10 @@ function parseLink() {
     if (match) {
         pkgInput.value = match[0];
         linkError.classList.add("hidden");
+    } else if (link.includes("://")) {
+        // 短链接之类的交给后端解析
+        pkgInput.value = "";
+        linkError.classList.add("hidden");
     } else {
         pkgInput.value = "";
         linkError.classList.remove("hidden");
@@ -74,6 +78,14 @@ function validateInputs() {
 
     let valid = true;
 
+    // 填了链接但是没解析出包名的, 交给后端解析
+    const linkInput = document.getElementById("linkInput").value.trim();
+    if (linkInput && !pkgInput && !appIdInput) {
+        pkgError.classList.add("hidden");
+        appIdError.classList.add("hidden");
+        return true;
+    }
+
     // 二选一必填
     if (!pkgInput && !appIdInput) {
         pkgError.textContent = "包名或 app_id 必须填写其中一个";
@@ -120,6 +132,7 @@ function validateInputs() {
 async function queryApp() {
     const pkgName = document.getElementById("pkgInput").value.trim();
     const appId = document.getElementById("appIdInput").value.trim();
+    const link = document.getElementById("linkInput").value.trim();
     const username = document.getElementById("usernameInput").value.trim();
     const remark = document.getElementById("remarkInput").value.trim();
     const resultArea = document.getElementById("resultArea");
@@ -135,6 +148,8 @@ async function queryApp() {
     } else if (appId) {
         const modifiedAppId = appId.startsWith('C') ? appId : 'C' + appId;
         submit_body.app_id = modifiedAppId;
+    } else if (link) {
+        submit_body.link = link;
     } else {
         alert("请输入包名或 app_id");
         return;
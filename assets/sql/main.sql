@@ -1,3 +1,30 @@
+-- 全文搜索的切分函数, 见 migrations/024_add_app_search
+CREATE OR REPLACE FUNCTION search_tokens(input TEXT) RETURNS TEXT
+LANGUAGE plpgsql IMMUTABLE PARALLEL SAFE AS $$
+DECLARE
+    term TEXT;
+    tokens TEXT[] := '{}';
+BEGIN
+    FOR term IN
+        SELECT m[1] FROM regexp_matches(
+            lower(coalesce(input, '')),
+            '([\u3400-\u4dbf\u4e00-\u9fff\uf900-\ufaff]+|[a-z0-9]+)',
+            'g'
+        ) AS m
+    LOOP
+        IF term ~ '^[a-z0-9]' OR char_length(term) = 1 THEN
+            tokens := array_append(tokens, term);
+        ELSE
+            FOR i IN 1 .. char_length(term) - 1 LOOP
+                tokens := array_append(tokens, substr(term, i, 2));
+            END LOOP;
+            tokens := array_append(tokens, right(term, 1));
+        END IF;
+    END LOOP;
+    RETURN array_to_string(tokens, ' ');
+END
+$$;
+
 CREATE TABLE app_info (
     app_id                  TEXT PRIMARY KEY,     -- 应用唯一ID（如 C1164531384803416384）
     alliance_app_id         TEXT,                 -- 联盟应用ID（如 1164531384803416384）
@@ -43,15 +70,33 @@ CREATE TABLE app_info (
     listed_at               TIMESTAMPTZ NOT NULL, -- 应用上架时间
     release_countries       TEXT[] NOT NULL DEFAULT '{}', -- 应用发布的国家/地区列表
     main_device_codes       TEXT[] NOT NULL DEFAULT '{}', -- 应用支持的主要设备类型
-    created_at              TIMESTAMPTZ NOT NULL DEFAULT now() -- 创建时间
+    content_hash            TEXT,                 -- 内容哈希（sha256，不含 created_at/listed_at/comment）
+    created_at              TIMESTAMPTZ NOT NULL DEFAULT now(), -- 创建时间
+    -- 全文搜索: 名称 A, 开发者/包名/分类/标签 B, 简介 C, 详细描述 D
+    search_document         TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', search_tokens(name)), 'A') ||
+        setweight(to_tsvector('simple', search_tokens(
+            developer_name || ' ' || coalesce(dev_en_name, '') || ' ' || pkg_name || ' ' ||
+            kind_name || ' ' || kind_type_name || ' ' || coalesce(tag_name, '')
+        )), 'B') ||
+        setweight(to_tsvector('simple', search_tokens(brief_desc)), 'C') ||
+        setweight(to_tsvector('simple', search_tokens(description)), 'D')
+    ) STORED
 );
 
 CREATE TABLE app_data_history (
     id              BIGSERIAL PRIMARY KEY,                     -- 主键ID
     app_id          TEXT NOT NULL REFERENCES app_info(app_id), -- 对应 app_info 的 app_id
     pkg_name        TEXT NOT NULL REFERENCES app_info(pkg_name) ON DELETE CASCADE, -- 对应 app_info 的 pkg_name
-    raw_json_data   JSONB NOT NULL DEFAULT '{}'::JSONB,        -- 原始应用数据JSON
-    created_at      TIMESTAMPTZ NOT NULL DEFAULT now()         -- 数据创建或记录时间
+    raw_json_data   JSONB,                                     -- 原始应用数据JSON（快照行）
+    snapshot_id     BIGINT REFERENCES app_data_history(id) ON DELETE CASCADE, -- 增量行对应的快照行
+    raw_json_patch  JSONB,                                     -- 相对快照的 JSON Patch（增量行）
+    content_hash    TEXT,                                      -- 完整数据的内容哈希（sha256）
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),        -- 数据创建或记录时间
+    CONSTRAINT app_data_history_snapshot_or_patch CHECK (
+        (raw_json_data IS NOT NULL AND snapshot_id IS NULL AND raw_json_patch IS NULL)
+        OR (raw_json_data IS NULL AND snapshot_id IS NOT NULL AND raw_json_patch IS NOT NULL)
+    )
 );
 
 CREATE TABLE app_rating_history (
@@ -59,6 +104,7 @@ CREATE TABLE app_rating_history (
     app_id          TEXT NOT NULL REFERENCES app_info(app_id), -- 对应 app_info 的 app_id
     pkg_name        TEXT NOT NULL REFERENCES app_info(pkg_name) ON DELETE CASCADE, -- 对应 app_info 的 pkg_name
     raw_json_rating JSONB NOT NULL DEFAULT '{}'::JSONB,        -- 原始评分数据JSON (原 raw_json_star)
+    content_hash    TEXT,                                      -- raw_json_rating 的内容哈希（sha256）
     created_at      TIMESTAMPTZ NOT NULL DEFAULT now()         -- 数据创建或记录时间
 );
 
@@ -84,6 +130,105 @@ CREATE TABLE substance_app_map (
     PRIMARY KEY (substance_id, app_id)
 );
 
+-- 记录上游接口返回数据的结构 (每个 key 见过的类型)
+CREATE TABLE schema_profile (
+    source          TEXT NOT NULL,                      -- 数据来源（appinfo / page-detail）
+    key             TEXT NOT NULL,                      -- 顶层 key
+    value_type      TEXT NOT NULL,                      -- JSON 类型（string / number / bool / null / array / object）
+    example_app_id  TEXT NOT NULL,                      -- 第一次见到这个类型的应用
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT now(), -- 第一次见到的时间
+    PRIMARY KEY (source, key, value_type)
+);
+
+-- 记录上游接口数据结构的变化
+CREATE TABLE schema_drift (
+    id              BIGSERIAL PRIMARY KEY,
+    source          TEXT NOT NULL,                      -- 数据来源（appinfo / page-detail）
+    key             TEXT NOT NULL,                      -- 变化的 key
+    change          TEXT NOT NULL,                      -- 变化类型（added / removed / type_changed）
+    old_type        TEXT NOT NULL,                      -- 之前见过的类型，多个用 | 分隔，新 key 为 missing
+    new_type        TEXT NOT NULL,                      -- 这次的类型，消失的 key 为 missing
+    example_app_id  TEXT NOT NULL,                      -- 第一次出现这个变化的应用
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (source, key, change, new_type)
+);
+
+-- 解析失败的原始数据隔离表
+CREATE TABLE app_quarantine (
+    id              BIGSERIAL PRIMARY KEY,
+    query_key       TEXT NOT NULL,                      -- 查询方式（app_id / pkg_name）
+    query_value     TEXT NOT NULL,                      -- 查询的值
+    raw_json_data   JSONB NOT NULL,                     -- 解析失败的原始数据
+    error           TEXT NOT NULL,                      -- 最近一次的解析错误
+    attempts        INTEGER NOT NULL DEFAULT 0,         -- 重新处理的次数
+    resolved_at     TIMESTAMPTZ,                        -- 重新处理成功的时间
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at      TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- 持久化的猜测任务, 用于断点续扫和查看进度
+CREATE TABLE guess_scan (
+    id              BIGSERIAL PRIMARY KEY,
+    mode            TEXT NOT NULL,                      -- 猜测方式（range / random / around_known / large / pattern）
+    definition      JSONB NOT NULL,                     -- 完整的任务定义
+    status          TEXT NOT NULL DEFAULT 'running',    -- 状态（running / finished / failed）
+    next_offset     BIGINT NOT NULL DEFAULT 0,          -- 已经完成的 ID 数量，恢复时从这里开始
+    total           BIGINT,                             -- ID 总数，无限的随机猜测为 NULL
+    hits            BIGINT NOT NULL DEFAULT 0,          -- 命中数量
+    requests        BIGINT NOT NULL DEFAULT 0,          -- 请求数量
+    errors          BIGINT NOT NULL DEFAULT 0,          -- 出错数量（不包含应用不存在）
+    skipped         BIGINT NOT NULL DEFAULT 0,          -- 因为已经确认不存在而跳过的数量
+    active_seconds  DOUBLE PRECISION NOT NULL DEFAULT 0, -- 实际运行的秒数
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    finished_at     TIMESTAMPTZ
+);
+
+-- 自适应猜测的密度模型, 记录每个桶的猜测结果
+CREATE TABLE guess_density (
+    prefix          TEXT NOT NULL,                      -- app_id 的非数字前缀（如 C）
+    bucket_size     BIGINT NOT NULL,                    -- 桶大小
+    bucket          BIGINT NOT NULL,                    -- 桶编号（数字部分 / 桶大小）
+    probes          BIGINT NOT NULL DEFAULT 0,          -- 猜测次数（不包含出错的）
+    hits            BIGINT NOT NULL DEFAULT 0,          -- 命中次数
+    scanned_to      BIGINT NOT NULL DEFAULT 0,          -- 桶内已经扫描到的位置
+    updated_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (prefix, bucket_size, bucket)
+);
+
+-- 猜过但是不存在的 ID 区间, 猜测时跳过
+CREATE TABLE guess_negative (
+    query_key       TEXT NOT NULL,                      -- 查询方式（app_id / pkg_name）
+    prefix          TEXT NOT NULL,                      -- 非数字前缀（如 C）
+    range_start     BIGINT NOT NULL,                    -- 区间开始（包含）
+    range_end       BIGINT NOT NULL,                    -- 区间结束（包含）
+    probed_at       TIMESTAMPTZ NOT NULL,               -- 区间里最早的猜测时间
+    PRIMARY KEY (query_key, prefix, range_start)
+);
+
+-- 猜测任务里请求出错的 ID, 任务最后再重试一遍
+CREATE TABLE guess_retry (
+    scan_id         BIGINT NOT NULL REFERENCES guess_scan(id) ON DELETE CASCADE, -- 对应 guess_scan 的 id
+    query_key       TEXT NOT NULL,                      -- 查询方式（app_id / pkg_name）
+    query_value     TEXT NOT NULL,                      -- 查询的值
+    attempts        INTEGER NOT NULL DEFAULT 1,         -- 出错的次数
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (scan_id, query_key, query_value)
+);
+
+-- 外部来源 (比如 nextmax) 发现的应用
+CREATE TABLE app_source (
+    app_id          TEXT NOT NULL REFERENCES app_info(app_id) ON DELETE CASCADE, -- 对应 app_info 的 app_id
+    source          TEXT NOT NULL,                      -- 来源名称（如 nextmax）
+    external_id     TEXT,                               -- 在来源里的 ID
+    metadata        JSONB,                              -- 来源提供的其他信息
+    discovered      BOOLEAN NOT NULL DEFAULT FALSE,     -- 是否是这个来源第一次把应用带进数据库
+    first_seen_at   TIMESTAMPTZ NOT NULL DEFAULT now(), -- 第一次在来源里看到
+    last_seen_at    TIMESTAMPTZ NOT NULL DEFAULT now(), -- 最近一次在来源里看到
+    PRIMARY KEY (app_id, source)
+);
+
 CREATE TABLE app_metrics (
     id                  BIGSERIAL PRIMARY KEY,                      -- 主键ID
     app_id              TEXT NOT NULL REFERENCES app_info(app_id),  -- 对应 app_info 的 app_id
@@ -104,9 +249,70 @@ CREATE TABLE app_metrics (
     compile_sdk_version INTEGER NOT NULL,                           -- 编译SDK版本（如 50100）
     min_hmos_api_level  INTEGER NOT NULL,                           -- 最低鸿蒙API等级（如 50001）
     api_release_type    TEXT NOT NULL,                              -- API发布类型（如 Release）
+    content_hash        TEXT,                                       -- 内容哈希（sha256，不含 id/created_at）
+    imported_from       TEXT,                                       -- 导入来源，NULL 表示实际抓取的数据
     created_at          TIMESTAMPTZ NOT NULL DEFAULT now()          -- 创建时间
 );
 
+CREATE TABLE app_metrics_hourly (
+    app_id              TEXT NOT NULL REFERENCES app_info(app_id) ON DELETE CASCADE, -- 对应 app_info 的 app_id
+    bucket              TIMESTAMPTZ NOT NULL,               -- 时间段的开始（整点）
+    samples             INTEGER NOT NULL,                   -- 时间段内原始数据条数
+    download_min        BIGINT NOT NULL,                    -- 时间段内最小下载量
+    download_max        BIGINT NOT NULL,                    -- 时间段内最大下载量
+    last_metric_id      BIGINT NOT NULL,                    -- 时间段内最后一条原始数据的 id（原始数据可能已经删除）
+    last_at             TIMESTAMPTZ NOT NULL,               -- 时间段内最后一条原始数据的时间
+    -- 以下是时间段内最后一条原始数据的内容, 和 app_metrics 一样
+    version             TEXT NOT NULL,
+    version_code        BIGINT NOT NULL,
+    size_bytes          BIGINT NOT NULL,
+    sha256              TEXT NOT NULL,
+    info_score          NUMERIC(3,1) NOT NULL,
+    info_rate_count     BIGINT NOT NULL,
+    download_count      BIGINT NOT NULL,
+    price               NUMERIC(10,2) NOT NULL,
+    release_date        BIGINT NOT NULL,
+    new_features        TEXT NOT NULL,
+    upgrade_msg         TEXT NOT NULL,
+    target_sdk          INTEGER NOT NULL,
+    minsdk              INTEGER NOT NULL,
+    compile_sdk_version INTEGER NOT NULL,
+    min_hmos_api_level  INTEGER NOT NULL,
+    api_release_type    TEXT NOT NULL,
+    imported_from       TEXT,
+    PRIMARY KEY (app_id, bucket)
+);
+
+-- 和小时汇总一样, bucket 是当天 0 点
+CREATE TABLE app_metrics_daily (LIKE app_metrics_hourly INCLUDING ALL);
+ALTER TABLE app_metrics_daily
+    ADD FOREIGN KEY (app_id) REFERENCES app_info(app_id) ON DELETE CASCADE;
+
+-- 汇总进度: 汇总到了哪一条原始数据
+CREATE TABLE metrics_rollup_state (
+    name                TEXT PRIMARY KEY,                   -- 固定为 app_metrics
+    last_metric_id      BIGINT NOT NULL DEFAULT 0,          -- 已经汇总到的 app_metrics.id
+    seen_metric_id      BIGINT,                             -- 上次运行时看到的最大 id, 下次运行汇总到这里
+    updated_at          TIMESTAMPTZ NOT NULL DEFAULT now()  -- 上次运行时间
+);
+
+-- 每个应用每天一行, 只有当天有指标或者评分数据的日子才有
+-- 下载量、版本、大小是当天结束时最新的指标, 评分是当天结束时最新的评分
+CREATE TABLE app_daily_stats (
+    app_id              TEXT NOT NULL REFERENCES app_info(app_id) ON DELETE CASCADE, -- 对应 app_info 的 app_id
+    day                 DATE NOT NULL,                      -- 日期
+    download_count      BIGINT NOT NULL,                    -- 当天结束时的下载量
+    download_delta      BIGINT,                             -- 和上一行相比增加的下载量, 第一行为 NULL
+    average_rating      NUMERIC(3,1),                       -- 当天结束时的平均评分, 没有评分为 NULL
+    rating_count        BIGINT,                             -- 当天结束时的评分人数
+    rating_count_delta  BIGINT,                             -- 和上一行相比增加的评分人数
+    version             TEXT NOT NULL,                      -- 当天结束时的版本号
+    size_bytes          BIGINT NOT NULL,                    -- 当天结束时的安装包大小
+    samples             INTEGER NOT NULL DEFAULT 0,         -- 当天的指标原始数据条数
+    updated_at          TIMESTAMPTZ NOT NULL DEFAULT now(), -- 这一行最后一次计算的时间
+    PRIMARY KEY (app_id, day)
+);
+
 CREATE TABLE app_rating (
     id                          BIGSERIAL PRIMARY KEY,                      -- 主键ID
     app_id                      TEXT NOT NULL REFERENCES app_info(app_id),  -- 对应 app_info 的 app_id
@@ -122,6 +328,7 @@ CREATE TABLE app_rating (
     only_star_count             INTEGER NOT NULL,                           -- 仅评分数量
     full_average_rating         NUMERIC(3,1) NOT NULL,                      -- 完整平均评分
     source_type                 TEXT NOT NULL,                              -- 评分来源类型
+    content_hash                TEXT,                                       -- 内容哈希（sha256，不含 id/created_at）
     created_at                  TIMESTAMPTZ NOT NULL DEFAULT now()          -- 创建时间
 );
 
@@ -239,12 +446,43 @@ SELECT ai.app_id,
           FROM app_rating
          ORDER BY app_rating.app_id, app_rating.created_at DESC NULLS LAST) ar ON ai.app_id = ar.app_id;
 
+-- 元服务（包名 com.atomicservice.*）的最新数据
+-- 元服务没有安装包，不包含 size_bytes / sha256，也没有评分
+CREATE VIEW atomic_service_latest AS
+SELECT app_id,
+   alliance_app_id,
+   pkg_name,
+   name,                           -- 服务卡片标题
+   icon_url,                       -- 服务卡片图标
+   brief_desc,                     -- 服务卡片简介
+   kind_name,                      -- 分类名称
+   tag_name,                       -- 标签名称
+   main_device_codes,              -- 支持的设备类型
+   dev_id,
+   developer_name,
+   dev_en_name,
+   description,
+   privacy_url,
+   detail_id,
+   version,
+   version_code,
+   info_score::float8 AS info_score,
+   info_rate_count,
+   download_count,
+   release_date,
+   listed_at,
+   created_at,
+   metrics_created_at
+  FROM app_latest_info
+ WHERE pkg_name LIKE 'com.atomicservice.%';
+
 -- 创建索引以提高查询性能
 CREATE INDEX idx_app_info_app_id ON app_info(app_id);
 CREATE INDEX idx_app_info_pkg_name ON app_info(pkg_name);
 CREATE INDEX idx_app_info_name ON app_info(name);
 CREATE INDEX idx_app_info_developer_name ON app_info(developer_name);
 CREATE INDEX idx_app_info_listed_at ON app_info(listed_at);
+CREATE INDEX idx_app_info_search_document ON app_info USING GIN (search_document);
 
 CREATE INDEX idx_app_metrics_app_id ON app_metrics(app_id);
 CREATE INDEX idx_app_metrics_version ON app_metrics(version);
@@ -253,13 +491,20 @@ CREATE INDEX idx_app_metrics_pkg_name ON app_metrics (pkg_name);
 
 CREATE INDEX idx_app_rating_app_id ON app_rating(app_id);
 CREATE INDEX idx_app_rating_pkg_name ON app_rating (pkg_name);
+CREATE INDEX idx_app_rating_created_at ON app_rating (created_at);
 
-CREATE INDEX idx_app_data_history_app_pkg_data ON app_data_history (app_id, pkg_name, raw_json_data);
+CREATE INDEX idx_app_data_history_snapshot_id ON app_data_history (snapshot_id) WHERE snapshot_id IS NOT NULL;
 CREATE INDEX idx_app_data_history_created_at ON app_data_history (created_at);
 
 CREATE INDEX idx_app_rating_history_app_pkg_rating ON app_rating_history (app_id, pkg_name, raw_json_rating);
 CREATE INDEX idx_app_rating_history_created_at ON app_rating_history (created_at);
 
+-- 变更检测：按 app_id 取最新一行的 content_hash，只走索引
+CREATE INDEX idx_app_data_history_app_id_created_at ON app_data_history (app_id, created_at DESC) INCLUDE (content_hash);
+CREATE INDEX idx_app_rating_history_app_id_created_at ON app_rating_history (app_id, created_at DESC) INCLUDE (content_hash);
+CREATE INDEX idx_app_metrics_app_id_created_at ON app_metrics (app_id, created_at DESC) INCLUDE (content_hash);
+CREATE INDEX idx_app_rating_app_id_created_at ON app_rating (app_id, created_at DESC) INCLUDE (content_hash);
+
 -- 1) substance_info
 -- 快速按主键已自然支持；补充常用查询字段索引
 CREATE INDEX idx_substance_info_name ON substance_info (name);
@@ -280,6 +525,24 @@ CREATE INDEX idx_substance_app_map_app_id ON substance_app_map (app_id);
 -- 如需按 app_id 按时间或其它维度排序/筛选，可考虑复合索引（示例：按 app_id 再按 substance_id）
 CREATE INDEX idx_substance_app_map_appid_substanceid ON substance_app_map (app_id, substance_id);
 
+-- 4) schema_drift
+CREATE INDEX idx_schema_drift_created_at ON schema_drift (created_at);
+
+-- 5) app_quarantine
+CREATE UNIQUE INDEX idx_app_quarantine_unresolved ON app_quarantine (query_key, query_value) WHERE resolved_at IS NULL;
+
+-- 6) guess_scan
+CREATE INDEX idx_guess_scan_status ON guess_scan (status, updated_at DESC);
+
+-- 7) guess_negative
+CREATE INDEX idx_guess_negative_probed_at ON guess_negative (probed_at);
+
+-- 8) app_source
+CREATE INDEX idx_app_source_discovered ON app_source (source) WHERE discovered;
+
+-- 9) app_daily_stats
+CREATE INDEX idx_app_daily_stats_day ON app_daily_stats (day);
+
 -- 1. 创建或替换一个函数，用于更新 app_info.listed_at
 CREATE OR REPLACE FUNCTION update_app_listed_at_on_metric_insert()
 RETURNS TRIGGER AS $$
@@ -1,3 +1,5 @@
+-- 001_add_star 执行后的检查, 只读, 迁移程序不会执行, 需要时手动运行
+
 -- 视图验证脚本：检查 app_latest_info 视图的正确性
 -- 执行此脚本验证视图是否正常工作
 
@@ -126,4 +128,4 @@ SELECT
         WHEN (SELECT COUNT(CASE WHEN info_score IS NOT NULL THEN 1 END) FROM app_latest_info) > 0
         THEN 'info_score数据正常'
         ELSE 'info_score数据异常'
-    END AS info_score_check;
\ No newline at end of file
+    END AS info_score_check;
@@ -0,0 +1,330 @@
+-- 001_add_star: 重命名 app_metrics 的字段并添加页面评分, app_raw 拆成 raw_json_data / raw_json_star
+
+-- 迁移脚本：重命名app_metrics表中的字段并添加页面评分相关字段
+-- 执行顺序：先添加新字段，然后迁移数据，最后删除旧字段
+
+-- 1. 首先添加新的info_score和info_rate_count字段
+ALTER TABLE app_metrics
+ADD COLUMN info_score NUMERIC(3,1),
+ADD COLUMN info_rate_count BIGINT;
+
+-- 2. 将hot_score和rate_num的数据迁移到新字段
+UPDATE app_metrics
+SET info_score = hot_score::numeric,
+    info_rate_count = rate_num::bigint;
+
+-- 3. 添加页面评分相关的新字段
+ALTER TABLE app_metrics
+ADD COLUMN page_average_rating NUMERIC(3,1),
+ADD COLUMN page_star_1_rating_count INTEGER,
+ADD COLUMN page_star_2_rating_count INTEGER,
+ADD COLUMN page_star_3_rating_count INTEGER,
+ADD COLUMN page_star_4_rating_count INTEGER,
+ADD COLUMN page_star_5_rating_count INTEGER,
+ADD COLUMN page_my_star_rating INTEGER,
+ADD COLUMN page_total_star_rating_count INTEGER,
+ADD COLUMN page_only_star_count INTEGER,
+ADD COLUMN page_full_average_rating NUMERIC(3,1),
+ADD COLUMN page_source_type TEXT;
+
+-- 4. 修改app_raw表结构，添加raw_json_star字段
+ALTER TABLE app_raw
+ADD COLUMN raw_json_star JSONB;
+
+-- 5. 将现有的raw_json数据复制到raw_json_data字段（如果需要）
+-- 注意：这一步需要根据现有数据的结构来决定是否需要
+-- 如果现有raw_json字段包含的是应用数据，可以执行：
+-- UPDATE app_raw SET raw_json_data = raw_json;
+
+-- 7. 更新视图以反映字段名称更改
+DROP VIEW IF EXISTS app_latest_info;
+
+-- 6. 删除旧的hot_score和rate_num字段
+ALTER TABLE app_metrics
+DROP COLUMN hot_score,
+DROP COLUMN rate_num;
+
+
+CREATE OR REPLACE VIEW app_latest_info AS
+SELECT
+    ai.*,
+    am.version,
+    am.version_code,
+    am.size_bytes,
+    am.sha256,
+    am.info_score,
+    am.info_rate_count,
+    am.download_count,
+    am.price,
+    am.release_date,
+    am.new_features,
+    am.upgrade_msg,
+    am.target_sdk,
+    am.minsdk,
+    am.compile_sdk_version,
+    am.min_hmos_api_level,
+    am.api_release_type,
+    am.page_average_rating,
+    am.page_star_1_rating_count,
+    am.page_star_2_rating_count,
+    am.page_star_3_rating_count,
+    am.page_star_4_rating_count,
+    am.page_star_5_rating_count,
+    am.page_my_star_rating,
+    am.page_total_star_rating_count,
+    am.page_only_star_count,
+    am.page_full_average_rating,
+    am.page_source_type,
+    am.created_at as metrics_created_at
+FROM app_info ai
+LEFT JOIN (
+    SELECT DISTINCT ON (app_id) *
+    FROM app_metrics
+    ORDER BY app_id, release_date DESC NULLS LAST
+) am ON ai.app_id = am.app_id;
+
+-- 8. 重新创建索引
+CREATE INDEX IF NOT EXISTS idx_app_info_app_id ON app_info(app_id);
+CREATE INDEX IF NOT EXISTS idx_app_raw_app_id ON app_raw(app_id);
+CREATE INDEX IF NOT EXISTS idx_app_metrics_app_id ON app_metrics(app_id);
+CREATE INDEX IF NOT EXISTS idx_app_metrics_version ON app_metrics(version);
+CREATE INDEX IF NOT EXISTS idx_app_metrics_download_count ON app_metrics(download_count);
+
+-- 9. 迁移完成提示
+COMMENT ON TABLE app_metrics IS '应用指标表，包含版本、评分、下载量等信息';
+
+-- 迁移脚本：修改app_raw表结构以支持两个JSON字段
+-- 执行顺序：先添加新字段，然后迁移数据，最后删除旧字段
+
+-- 1. 首先添加raw_json_data和raw_json_star字段
+ALTER TABLE app_raw 
+ADD COLUMN raw_json_data JSONB,
+ADD COLUMN raw_json_star JSONB;
+
+-- 2. 将现有的raw_json数据迁移到raw_json_data字段
+-- 注意：这里假设现有的raw_json字段包含的是应用数据
+UPDATE app_raw 
+SET raw_json_data = COALESCE(raw_json, '{}'::JSONB),
+    raw_json_star = '{}'::JSONB;
+
+-- 3. 删除旧的raw_json字段
+ALTER TABLE app_raw 
+DROP COLUMN raw_json;
+
+-- 4. 设置所有列为NOT NULL并添加默认值
+ALTER TABLE app_raw 
+ALTER COLUMN app_id SET NOT NULL,
+ALTER COLUMN raw_json_data SET NOT NULL,
+ALTER COLUMN raw_json_data SET DEFAULT '{}'::JSONB,
+ALTER COLUMN raw_json_star SET NOT NULL,
+ALTER COLUMN raw_json_star SET DEFAULT '{}'::JSONB,
+ALTER COLUMN created_at SET NOT NULL;
+
+-- 5. 处理可能存在的NULL值（确保数据完整性）
+UPDATE app_raw 
+SET app_id = COALESCE(app_id, 'unknown')
+WHERE app_id IS NULL;
+
+UPDATE app_raw 
+SET created_at = COALESCE(created_at, now())
+WHERE created_at IS NULL;
+
+-- 6. 重新创建索引
+DROP INDEX IF EXISTS idx_app_raw_app_id;
+CREATE INDEX idx_app_raw_app_id ON app_raw(app_id);
+
+-- 迁移脚本：为app_raw表设置NOT NULL约束和默认值
+-- 注意：此脚本用于处理现有数据的NOT NULL约束和默认值设置
+
+-- 1. 设置app_id列为NOT NULL并处理现有NULL值
+UPDATE app_raw 
+SET app_id = 'unknown'
+WHERE app_id IS NULL;
+
+ALTER TABLE app_raw 
+ALTER COLUMN app_id SET NOT NULL;
+
+-- 2. 设置raw_json_data列为NOT NULL并添加默认值
+UPDATE app_raw 
+SET raw_json_data = '{}'::JSONB
+WHERE raw_json_data IS NULL;
+
+ALTER TABLE app_raw 
+ALTER COLUMN raw_json_data SET NOT NULL,
+ALTER COLUMN raw_json_data SET DEFAULT '{}'::JSONB;
+
+-- 3. 设置raw_json_star列为NOT NULL并添加默认值
+UPDATE app_raw 
+SET raw_json_star = '{}'::JSONB
+WHERE raw_json_star IS NULL;
+
+ALTER TABLE app_raw 
+ALTER COLUMN raw_json_star SET NOT NULL,
+ALTER COLUMN raw_json_star SET DEFAULT '{}'::JSONB;
+
+-- 4. 设置created_at列为NOT NULL并处理现有NULL值
+UPDATE app_raw 
+SET created_at = now()
+WHERE created_at IS NULL;
+
+ALTER TABLE app_raw 
+ALTER COLUMN created_at SET NOT NULL;
+
+-- 5. 验证所有约束都已正确设置
+-- 查询确认没有NULL值存在
+SELECT 
+    COUNT(*) FILTER (WHERE app_id IS NULL) as null_app_id,
+    COUNT(*) FILTER (WHERE raw_json_data IS NULL) as null_raw_json_data,
+    COUNT(*) FILTER (WHERE raw_json_star IS NULL) as null_raw_json_star,
+    COUNT(*) FILTER (WHERE created_at IS NULL) as null_created_at
+FROM app_raw;
+
+-- 6. 重新创建索引以确保性能
+DROP INDEX IF EXISTS idx_app_raw_app_id;
+CREATE INDEX idx_app_raw_app_id ON app_raw(app_id);
+
+-- 视图修复脚本：修正 app_latest_info 视图中的字段顺序问题
+-- 执行此脚本修复视图字段映射错误
+
+-- 1. 首先删除现有视图
+DROP VIEW IF EXISTS app_latest_info;
+
+-- 2. 重新创建视图，确保字段顺序正确
+CREATE OR REPLACE VIEW app_latest_info AS
+SELECT
+    ai.app_id,
+    ai.alliance_app_id,
+    ai.name,
+    ai.pkg_name,
+    ai.dev_id,
+    ai.developer_name,
+    ai.dev_en_name,
+    ai.supplier,
+    ai.kind_id,
+    ai.kind_name,
+    ai.tag_name,
+    ai.kind_type_id,
+    ai.kind_type_name,
+    ai.icon_url,
+    ai.brief_desc,
+    ai.description,
+    ai.privacy_url,
+    ai.ctype,
+    ai.detail_id,
+    ai.app_level,
+    ai.jocat_id,
+    ai.iap,
+    ai.hms,
+    ai.tariff_type,
+    ai.packing_type,
+    ai.order_app,
+    ai.denpend_gms,
+    ai.denpend_hms,
+    ai.force_update,
+    ai.img_tag,
+    ai.is_pay,
+    ai.is_disciplined,
+    ai.is_shelves,
+    ai.submit_type,
+    ai.delete_archive,
+    ai.charging,
+    ai.button_grey,
+    ai.app_gift,
+    ai.free_days,
+    ai.pay_install_type,
+    am.version,
+    am.version_code,
+    am.size_bytes,
+    am.sha256,
+    am.info_score,
+    am.info_rate_count,
+    am.download_count,
+    am.price,
+    am.release_date,
+    am.new_features,
+    am.upgrade_msg,
+    am.target_sdk,
+    am.minsdk,
+    am.compile_sdk_version,
+    am.min_hmos_api_level,
+    am.api_release_type,
+    am.page_average_rating,
+    am.page_star_1_rating_count,
+    am.page_star_2_rating_count,
+    am.page_star_3_rating_count,
+    am.page_star_4_rating_count,
+    am.page_star_5_rating_count,
+    am.page_my_star_rating,
+    am.page_total_star_rating_count,
+    am.page_only_star_count,
+    am.page_full_average_rating,
+    am.page_source_type,
+    am.created_at AS metrics_created_at
+FROM app_info ai
+LEFT JOIN (
+    SELECT DISTINCT ON (app_id) 
+        id,
+        app_id,
+        version,
+        version_code,
+        size_bytes,
+        sha256,
+        info_score,
+        info_rate_count,
+        download_count,
+        price,
+        release_date,
+        new_features,
+        upgrade_msg,
+        target_sdk,
+        minsdk,
+        compile_sdk_version,
+        min_hmos_api_level,
+        api_release_type,
+        page_average_rating,
+        page_star_1_rating_count,
+        page_star_2_rating_count,
+        page_star_3_rating_count,
+        page_star_4_rating_count,
+        page_star_5_rating_count,
+        page_my_star_rating,
+        page_total_star_rating_count,
+        page_only_star_count,
+        page_full_average_rating,
+        page_source_type,
+        created_at
+    FROM app_metrics
+    ORDER BY app_id, release_date DESC NULLS LAST
+) am ON ai.app_id = am.app_id;
+
+-- 3. 设置视图所有者
+ALTER VIEW app_latest_info OWNER TO srdown;
+
+-- 4. 验证修复结果
+COMMENT ON VIEW app_latest_info IS '修复后的应用最新信息视图，字段顺序已正确对齐';
+
+-- 5. 验证脚本
+DO $$
+BEGIN
+    -- 检查视图是否存在
+    IF EXISTS (SELECT 1 FROM information_schema.views WHERE table_name = 'app_latest_info') THEN
+        RAISE NOTICE '✅ 视图创建成功';
+    ELSE
+        RAISE EXCEPTION '❌ 视图创建失败';
+    END IF;
+
+    -- 检查字段数量
+    IF (SELECT COUNT(*) FROM information_schema.columns WHERE table_name = 'app_latest_info') >= 60 THEN
+        RAISE NOTICE '✅ 字段数量正常';
+    ELSE
+        RAISE WARNING '⚠️ 字段数量可能异常';
+    END IF;
+END $$;
+
+-- 6. 执行简单查询测试
+SELECT 
+    '视图修复完成，测试查询中...' AS status,
+    COUNT(*) AS record_count
+FROM app_latest_info 
+WHERE info_score IS NOT NULL 
+LIMIT 1;
@@ -1,5 +1,6 @@
+-- 002_split_tables 执行后的检查, 只读, 迁移程序不会执行, 需要时手动运行
+
 -- 数据完整性验证脚本
--- 执行顺序：在 001_create_app_rating_and_migrate_data.sql 之后执行
 -- 目的：验证迁移后的数据完整性和约束有效性
 
 -- 1. 检查 app_rating 表的 NOT NULL 约束
@@ -181,4 +182,4 @@ BEGIN
     RAISE NOTICE '所有验证项目均已通过 ✅';
     RAISE NOTICE '迁移成功完成！';
     RAISE NOTICE '========================================';
-END $$;
\ No newline at end of file
+END $$;
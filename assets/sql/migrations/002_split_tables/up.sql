@@ -1,3 +1,5 @@
+-- 002_split_tables: 把评分字段从 app_metrics 拆到 app_rating
+
 -- 迁移脚本：拆分 app_metrics 表，创建 app_rating 表并迁移数据
 -- 执行顺序：此脚本应在停机维护期间执行
 -- 注意：此迁移是单向的，不包含回滚逻辑
@@ -199,4 +201,4 @@ BEGIN
     RAISE NOTICE '- app_metrics 表总记录数: %', total_metrics_records;
     RAISE NOTICE '- 迁移到 app_rating 表的记录数: %', migrated_records;
     RAISE NOTICE '- 跳过迁移的记录数（包含NULL数据）: %', skipped_records;
-END $$;
\ No newline at end of file
+END $$;
@@ -1,3 +1,5 @@
+-- 003_remove_duplicate_app_raw: 删除 app_raw 里重复的记录
+
 -- 如果表已存在，先删除可能存在的重复数据
 DELETE FROM app_raw
 WHERE id IN (
@@ -1,5 +1,6 @@
+-- 004_add_created_at_to_app_info: 为 app_info 添加 created_at
+
 -- 迁移脚本：为 app_info 表添加 created_at 列
--- 执行顺序：004_add_created_at_to_app_info/001_add_created_at_to_app_info.sql
 
 -- 1. 为 app_info 表添加 created_at 列，默认值为当前时间戳
 ALTER TABLE app_info 
@@ -153,4 +154,4 @@ BEGIN
         (SELECT COUNT(*) FROM app_info WHERE created_at != now());
     RAISE NOTICE '% 条记录使用了默认当前时间', 
         (SELECT COUNT(*) FROM app_info WHERE created_at = now());
-END $$;
\ No newline at end of file
+END $$;
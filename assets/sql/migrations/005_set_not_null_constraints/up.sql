@@ -1,5 +1,6 @@
+-- 005_set_not_null_constraints: 为各个表设置 NOT NULL 约束
+
 -- 迁移脚本：为数据库表设置 NOT NULL 约束
--- 执行顺序：005_set_not_null_constraints/001_set_not_null_constraints.sql
 
 -- 1. 为 app_info 表设置 NOT NULL 约束
 ALTER TABLE app_info 
@@ -79,4 +80,4 @@ BEGIN
     RAISE NOTICE '- app_info 表：为 32 个字段添加了 NOT NULL 约束';
     RAISE NOTICE '- app_metrics 表：为 16 个字段添加了 NOT NULL 约束';
     RAISE NOTICE '- app_rating 表：为 11 个字段添加了 NOT NULL 约束';
-END $$;
\ No newline at end of file
+END $$;
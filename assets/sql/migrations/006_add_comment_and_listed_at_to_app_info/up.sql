@@ -1,5 +1,6 @@
+-- 006_add_comment_and_listed_at_to_app_info: 为 app_info 添加 comment 和 listed_at
+
 -- 迁移脚本：为 app_info 表添加 comment 字段和 listed_at 字段
--- 执行顺序：006_add_comment_and_listed_at_to_app_info/001_add_comment_to_app_info.sql
 
 -- 为 app_info 表添加 comment 列，类型为 JSONB，允许为 NULL
 ALTER TABLE app_info
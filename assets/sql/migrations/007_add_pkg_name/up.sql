@@ -1,3 +1,5 @@
+-- 007_add_pkg_name: pkg_name 加上唯一约束, 各个表通过 pkg_name 关联 app_info
+
 -- 迁移开始
 
 -- 1. 在 app_info 表中为 pkg_name 列添加唯一约束
@@ -1,3 +1,15 @@
+-- 008_add_device_and_country_fields: 为 app_info 添加 release_countries 和 main_device_codes
+
+-- 1. 向 app_info 表添加 release_countries 字段
+-- 这个字段存储应用发布的国家/地区列表，是一个文本数组。
+ALTER TABLE app_info ADD COLUMN release_countries TEXT[] NOT NULL DEFAULT '{}';
+COMMENT ON COLUMN app_info.release_countries IS '应用发布的国家/地区列表';
+
+-- 2. 向 app_info 表添加 main_device_codes 字段
+-- 这个字段存储应用支持的主要设备类型，是一个文本数组。
+ALTER TABLE app_info ADD COLUMN main_device_codes TEXT[] NOT NULL DEFAULT '{}';
+COMMENT ON COLUMN app_info.main_device_codes IS '应用支持的主要设备类型';
+
 DROP VIEW IF EXISTS app_latest_info;
 
 -- 更新 app_latest_info 视图以包含新增的 release_countries 和 main_device_codes 字段
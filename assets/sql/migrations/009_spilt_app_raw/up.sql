@@ -1,3 +1,5 @@
+-- 009_spilt_app_raw: 把 app_raw 拆成 app_data_history 和 app_rating_history
+
 -- 1. 创建新的 app_data_history 表 (移除原始的 UNIQUE 约束，避免索引过大问题)
 CREATE TABLE app_data_history (
     id              BIGSERIAL PRIMARY KEY,
@@ -69,4 +71,4 @@ CREATE INDEX idx_app_data_history_created_at ON app_data_history (created_at);
 CREATE INDEX idx_app_rating_history_app_pkg ON app_rating_history (app_id, pkg_name);
 CREATE INDEX idx_app_rating_history_created_at ON app_rating_history (created_at);
 -- 同理，如果需要基于 raw_json_rating 的字段查询，考虑 Gin 索引：
--- CREATE INDEX idx_app_rating_history_jsonb_rating_gin ON app_rating_history USING GIN (raw_json_rating);
\ No newline at end of file
+-- CREATE INDEX idx_app_rating_history_jsonb_rating_gin ON app_rating_history USING GIN (raw_json_rating);
@@ -0,0 +1,22 @@
+-- 011_add_content_hash: 添加 content_hash 用于变更检测
+
+-- 给各个表加上 content_hash 字段，用于变更检测
+-- 哈希由程序计算（sha256），旧数据保持 NULL，
+-- 程序遇到没有哈希的最新记录时会退回完整比较并顺手补上
+ALTER TABLE app_info ADD COLUMN IF NOT EXISTS content_hash TEXT;
+ALTER TABLE app_metrics ADD COLUMN IF NOT EXISTS content_hash TEXT;
+ALTER TABLE app_rating ADD COLUMN IF NOT EXISTS content_hash TEXT;
+ALTER TABLE app_data_history ADD COLUMN IF NOT EXISTS content_hash TEXT;
+ALTER TABLE app_rating_history ADD COLUMN IF NOT EXISTS content_hash TEXT;
+
+COMMENT ON COLUMN app_info.content_hash IS '内容哈希（sha256，不含 created_at/listed_at/comment）';
+COMMENT ON COLUMN app_metrics.content_hash IS '内容哈希（sha256，不含 id/created_at）';
+COMMENT ON COLUMN app_rating.content_hash IS '内容哈希（sha256，不含 id/created_at）';
+COMMENT ON COLUMN app_data_history.content_hash IS 'raw_json_data 的内容哈希（sha256）';
+COMMENT ON COLUMN app_rating_history.content_hash IS 'raw_json_rating 的内容哈希（sha256）';
+
+-- 变更检测：按 app_id 取最新一行的 content_hash，只走索引
+CREATE INDEX IF NOT EXISTS idx_app_data_history_app_id_created_at ON app_data_history (app_id, created_at DESC) INCLUDE (content_hash);
+CREATE INDEX IF NOT EXISTS idx_app_rating_history_app_id_created_at ON app_rating_history (app_id, created_at DESC) INCLUDE (content_hash);
+CREATE INDEX IF NOT EXISTS idx_app_metrics_app_id_created_at ON app_metrics (app_id, created_at DESC) INCLUDE (content_hash);
+CREATE INDEX IF NOT EXISTS idx_app_rating_app_id_created_at ON app_rating (app_id, created_at DESC) INCLUDE (content_hash);
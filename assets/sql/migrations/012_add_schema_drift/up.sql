@@ -0,0 +1,26 @@
+-- 012_add_schema_drift: 添加 schema_profile 和 schema_drift
+
+-- 记录上游接口返回数据的结构 (每个 key 见过的类型)
+CREATE TABLE IF NOT EXISTS schema_profile (
+    source          TEXT NOT NULL,                      -- 数据来源（appinfo / page-detail）
+    key             TEXT NOT NULL,                      -- 顶层 key
+    value_type      TEXT NOT NULL,                      -- JSON 类型（string / number / bool / null / array / object）
+    example_app_id  TEXT NOT NULL,                      -- 第一次见到这个类型的应用
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT now(), -- 第一次见到的时间
+    PRIMARY KEY (source, key, value_type)
+);
+
+-- 记录上游接口数据结构的变化
+CREATE TABLE IF NOT EXISTS schema_drift (
+    id              BIGSERIAL PRIMARY KEY,
+    source          TEXT NOT NULL,                      -- 数据来源（appinfo / page-detail）
+    key             TEXT NOT NULL,                      -- 变化的 key
+    change          TEXT NOT NULL,                      -- 变化类型（added / removed / type_changed）
+    old_type        TEXT NOT NULL,                      -- 之前见过的类型，多个用 | 分隔，新 key 为 missing
+    new_type        TEXT NOT NULL,                      -- 这次的类型，消失的 key 为 missing
+    example_app_id  TEXT NOT NULL,                      -- 第一次出现这个变化的应用
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (source, key, change, new_type)
+);
+
+CREATE INDEX IF NOT EXISTS idx_schema_drift_created_at ON schema_drift (created_at);
@@ -0,0 +1,19 @@
+-- 013_add_quarantine: 添加解析失败数据的隔离表 app_quarantine
+
+-- 解析失败的原始数据隔离表
+CREATE TABLE IF NOT EXISTS app_quarantine (
+    id              BIGSERIAL PRIMARY KEY,
+    query_key       TEXT NOT NULL,                      -- 查询方式（app_id / pkg_name）
+    query_value     TEXT NOT NULL,                      -- 查询的值
+    raw_json_data   JSONB NOT NULL,                     -- 解析失败的原始数据
+    error           TEXT NOT NULL,                      -- 最近一次的解析错误
+    attempts        INTEGER NOT NULL DEFAULT 0,         -- 重新处理的次数
+    resolved_at     TIMESTAMPTZ,                        -- 重新处理成功的时间
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at      TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- 同一个应用只保留一条未处理的记录
+CREATE UNIQUE INDEX IF NOT EXISTS idx_app_quarantine_unresolved
+    ON app_quarantine (query_key, query_value)
+    WHERE resolved_at IS NULL;
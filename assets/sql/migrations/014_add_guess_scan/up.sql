@@ -0,0 +1,20 @@
+-- 014_add_guess_scan: 添加猜测任务表 guess_scan
+
+-- 持久化的猜测任务, 用于断点续扫和查看进度
+CREATE TABLE IF NOT EXISTS guess_scan (
+    id              BIGSERIAL PRIMARY KEY,
+    mode            TEXT NOT NULL,                      -- 猜测方式（range / random / around_known / large / pattern）
+    definition      JSONB NOT NULL,                     -- 完整的任务定义
+    status          TEXT NOT NULL DEFAULT 'running',    -- 状态（running / finished / failed）
+    next_offset     BIGINT NOT NULL DEFAULT 0,          -- 已经完成的 ID 数量，恢复时从这里开始
+    total           BIGINT,                             -- ID 总数，无限的随机猜测为 NULL
+    hits            BIGINT NOT NULL DEFAULT 0,          -- 命中数量
+    requests        BIGINT NOT NULL DEFAULT 0,          -- 请求数量
+    errors          BIGINT NOT NULL DEFAULT 0,          -- 出错数量（不包含应用不存在）
+    active_seconds  DOUBLE PRECISION NOT NULL DEFAULT 0, -- 实际运行的秒数
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    finished_at     TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_guess_scan_status ON guess_scan (status, updated_at DESC);
@@ -0,0 +1,13 @@
+-- 015_add_guess_density: 添加猜测密度模型表 guess_density
+
+-- 自适应猜测的密度模型, 记录每个桶的猜测结果
+CREATE TABLE IF NOT EXISTS guess_density (
+    prefix          TEXT NOT NULL,                      -- app_id 的非数字前缀（如 C）
+    bucket_size     BIGINT NOT NULL,                    -- 桶大小
+    bucket          BIGINT NOT NULL,                    -- 桶编号（数字部分 / 桶大小）
+    probes          BIGINT NOT NULL DEFAULT 0,          -- 猜测次数（不包含出错的）
+    hits            BIGINT NOT NULL DEFAULT 0,          -- 命中次数
+    scanned_to      BIGINT NOT NULL DEFAULT 0,          -- 桶内已经扫描到的位置
+    updated_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (prefix, bucket_size, bucket)
+);
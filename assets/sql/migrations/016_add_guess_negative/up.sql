@@ -0,0 +1,16 @@
+-- 016_add_guess_negative: 添加猜测负缓存 guess_negative, guess_scan 记录跳过数量
+
+-- 猜过但是不存在的 ID 区间, 猜测时跳过
+CREATE TABLE IF NOT EXISTS guess_negative (
+    query_key       TEXT NOT NULL,                      -- 查询方式（app_id / pkg_name）
+    prefix          TEXT NOT NULL,                      -- 非数字前缀（如 C）
+    range_start     BIGINT NOT NULL,                    -- 区间开始（包含）
+    range_end       BIGINT NOT NULL,                    -- 区间结束（包含）
+    probed_at       TIMESTAMPTZ NOT NULL,               -- 区间里最早的猜测时间
+    PRIMARY KEY (query_key, prefix, range_start)
+);
+
+CREATE INDEX IF NOT EXISTS idx_guess_negative_probed_at ON guess_negative (probed_at);
+
+-- 记录猜测任务因为缓存而跳过的 ID 数量
+ALTER TABLE guess_scan ADD COLUMN IF NOT EXISTS skipped BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,33 @@
+-- 017_add_atomic_service: 添加元服务视图 atomic_service_latest
+
+-- 元服务（包名 com.atomicservice.*）的最新数据
+-- 元服务没有安装包，不包含 size_bytes / sha256，也没有评分
+DROP VIEW IF EXISTS atomic_service_latest;
+
+CREATE VIEW atomic_service_latest AS
+SELECT app_id,
+   alliance_app_id,
+   pkg_name,
+   name,                           -- 服务卡片标题
+   icon_url,                       -- 服务卡片图标
+   brief_desc,                     -- 服务卡片简介
+   kind_name,                      -- 分类名称
+   tag_name,                       -- 标签名称
+   main_device_codes,              -- 支持的设备类型
+   dev_id,
+   developer_name,
+   dev_en_name,
+   description,
+   privacy_url,
+   detail_id,
+   version,
+   version_code,
+   info_score::float8 AS info_score,
+   info_rate_count,
+   download_count,
+   release_date,
+   listed_at,
+   created_at,
+   metrics_created_at
+  FROM app_latest_info
+ WHERE pkg_name LIKE 'com.atomicservice.%';
@@ -0,0 +1,4 @@
+-- 018_add_imported_metrics: app_metrics 标记外部导入的历史指标
+
+-- 标记从外部导入的历史指标, NULL 表示实际抓取到的数据
+ALTER TABLE app_metrics ADD COLUMN IF NOT EXISTS imported_from TEXT;
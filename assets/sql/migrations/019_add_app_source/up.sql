@@ -0,0 +1,15 @@
+-- 019_add_app_source: 添加外部来源表 app_source
+
+-- 外部来源 (比如 nextmax) 发现的应用
+CREATE TABLE IF NOT EXISTS app_source (
+    app_id          TEXT NOT NULL REFERENCES app_info(app_id) ON DELETE CASCADE, -- 对应 app_info 的 app_id
+    source          TEXT NOT NULL,                      -- 来源名称（如 nextmax）
+    external_id     TEXT,                               -- 在来源里的 ID
+    metadata        JSONB,                              -- 来源提供的其他信息
+    discovered      BOOLEAN NOT NULL DEFAULT FALSE,     -- 是否是这个来源第一次把应用带进数据库
+    first_seen_at   TIMESTAMPTZ NOT NULL DEFAULT now(), -- 第一次在来源里看到
+    last_seen_at    TIMESTAMPTZ NOT NULL DEFAULT now(), -- 最近一次在来源里看到
+    PRIMARY KEY (app_id, source)
+);
+
+CREATE INDEX IF NOT EXISTS idx_app_source_discovered ON app_source (source) WHERE discovered;
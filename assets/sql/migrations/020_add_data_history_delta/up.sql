@@ -0,0 +1,23 @@
+-- 020_add_data_history_delta: app_data_history 改成定期完整快照 + JSON Patch 增量
+-- 快照行: raw_json_data 是完整数据, snapshot_id / raw_json_patch 为 NULL
+-- 增量行: raw_json_data 为 NULL, raw_json_patch 是相对 snapshot_id 那一行的 RFC 6902 JSON Patch
+-- 已有的数据都是快照, 用 `compact_history` 转换成增量
+
+ALTER TABLE app_data_history ALTER COLUMN raw_json_data DROP NOT NULL;
+ALTER TABLE app_data_history ALTER COLUMN raw_json_data DROP DEFAULT;
+ALTER TABLE app_data_history
+    ADD COLUMN IF NOT EXISTS snapshot_id BIGINT REFERENCES app_data_history(id) ON DELETE CASCADE;
+ALTER TABLE app_data_history ADD COLUMN IF NOT EXISTS raw_json_patch JSONB;
+ALTER TABLE app_data_history ADD CONSTRAINT app_data_history_snapshot_or_patch CHECK (
+    (raw_json_data IS NOT NULL AND snapshot_id IS NULL AND raw_json_patch IS NULL)
+    OR (raw_json_data IS NULL AND snapshot_id IS NOT NULL AND raw_json_patch IS NOT NULL)
+);
+
+COMMENT ON COLUMN app_data_history.snapshot_id IS '增量行对应的快照行';
+COMMENT ON COLUMN app_data_history.raw_json_patch IS '相对快照的 JSON Patch';
+
+CREATE INDEX IF NOT EXISTS idx_app_data_history_snapshot_id
+    ON app_data_history (snapshot_id) WHERE snapshot_id IS NOT NULL;
+
+-- 变更检测已经改用 content_hash, 这个索引把整个 JSON 放进了 btree, 删掉省空间
+DROP INDEX IF EXISTS idx_app_data_history_app_pkg_data;
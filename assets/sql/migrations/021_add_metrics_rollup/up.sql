@@ -0,0 +1,44 @@
+-- 021_add_metrics_rollup: 添加指标的小时/天汇总表, 原始数据可以按保留策略删除
+
+-- 汇总表每个应用每个时间段一行, 记录下载量范围和时间段内最后一条原始数据
+CREATE TABLE IF NOT EXISTS app_metrics_hourly (
+    app_id              TEXT NOT NULL REFERENCES app_info(app_id) ON DELETE CASCADE, -- 对应 app_info 的 app_id
+    bucket              TIMESTAMPTZ NOT NULL,               -- 时间段的开始（整点）
+    samples             INTEGER NOT NULL,                   -- 时间段内原始数据条数
+    download_min        BIGINT NOT NULL,                    -- 时间段内最小下载量
+    download_max        BIGINT NOT NULL,                    -- 时间段内最大下载量
+    last_metric_id      BIGINT NOT NULL,                    -- 时间段内最后一条原始数据的 id（原始数据可能已经删除）
+    last_at             TIMESTAMPTZ NOT NULL,               -- 时间段内最后一条原始数据的时间
+    -- 以下是时间段内最后一条原始数据的内容, 和 app_metrics 一样
+    version             TEXT NOT NULL,
+    version_code        BIGINT NOT NULL,
+    size_bytes          BIGINT NOT NULL,
+    sha256              TEXT NOT NULL,
+    info_score          NUMERIC(3,1) NOT NULL,
+    info_rate_count     BIGINT NOT NULL,
+    download_count      BIGINT NOT NULL,
+    price               NUMERIC(10,2) NOT NULL,
+    release_date        BIGINT NOT NULL,
+    new_features        TEXT NOT NULL,
+    upgrade_msg         TEXT NOT NULL,
+    target_sdk          INTEGER NOT NULL,
+    minsdk              INTEGER NOT NULL,
+    compile_sdk_version INTEGER NOT NULL,
+    min_hmos_api_level  INTEGER NOT NULL,
+    api_release_type    TEXT NOT NULL,
+    imported_from       TEXT,
+    PRIMARY KEY (app_id, bucket)
+);
+
+-- 和小时汇总一样, bucket 是当天 0 点
+CREATE TABLE IF NOT EXISTS app_metrics_daily (LIKE app_metrics_hourly INCLUDING ALL);
+ALTER TABLE app_metrics_daily
+    ADD FOREIGN KEY (app_id) REFERENCES app_info(app_id) ON DELETE CASCADE;
+
+-- 汇总进度: 汇总到了哪一条原始数据
+CREATE TABLE IF NOT EXISTS metrics_rollup_state (
+    name                TEXT PRIMARY KEY,                   -- 固定为 app_metrics
+    last_metric_id      BIGINT NOT NULL DEFAULT 0,          -- 已经汇总到的 app_metrics.id
+    seen_metric_id      BIGINT,                             -- 上次运行时看到的最大 id, 下次运行汇总到这里
+    updated_at          TIMESTAMPTZ NOT NULL DEFAULT now()  -- 上次运行时间
+);
@@ -0,0 +1,20 @@
+-- 022_add_app_daily_stats: 添加每个应用每天的统计表 app_daily_stats
+
+-- 每个应用每天一行, 只有当天有指标或者评分数据的日子才有
+-- 下载量、版本、大小是当天结束时最新的指标, 评分是当天结束时最新的评分
+CREATE TABLE IF NOT EXISTS app_daily_stats (
+    app_id              TEXT NOT NULL REFERENCES app_info(app_id) ON DELETE CASCADE, -- 对应 app_info 的 app_id
+    day                 DATE NOT NULL,                      -- 日期
+    download_count      BIGINT NOT NULL,                    -- 当天结束时的下载量
+    download_delta      BIGINT,                             -- 和上一行相比增加的下载量, 第一行为 NULL
+    average_rating      NUMERIC(3,1),                       -- 当天结束时的平均评分, 没有评分为 NULL
+    rating_count        BIGINT,                             -- 当天结束时的评分人数
+    rating_count_delta  BIGINT,                             -- 和上一行相比增加的评分人数
+    version             TEXT NOT NULL,                      -- 当天结束时的版本号
+    size_bytes          BIGINT NOT NULL,                    -- 当天结束时的安装包大小
+    samples             INTEGER NOT NULL DEFAULT 0,         -- 当天的指标原始数据条数
+    updated_at          TIMESTAMPTZ NOT NULL DEFAULT now(), -- 这一行最后一次计算的时间
+    PRIMARY KEY (app_id, day)
+);
+
+CREATE INDEX IF NOT EXISTS idx_app_daily_stats_day ON app_daily_stats (day);
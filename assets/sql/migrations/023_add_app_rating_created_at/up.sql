@@ -0,0 +1,3 @@
+-- 023_add_app_rating_created_at: 评分增长排行和每日统计按时间范围扫描 app_rating
+
+CREATE INDEX IF NOT EXISTS idx_app_rating_created_at ON app_rating (created_at);
@@ -0,0 +1,44 @@
+-- 024_add_app_search: 应用全文搜索
+--
+-- 中文没有分词, 连续的汉字切成相邻两个字的 bigram, 最后一个字再单独作为一个词,
+-- 这样一个字的搜索也能用前缀匹配找到; 英文和数字按单词切分.
+-- 程序里 src/db/search.rs 的 search_tokens 按同样的规则切分搜索词, 修改时两边要一起改
+
+CREATE OR REPLACE FUNCTION search_tokens(input TEXT) RETURNS TEXT
+LANGUAGE plpgsql IMMUTABLE PARALLEL SAFE AS $$
+DECLARE
+    term TEXT;
+    tokens TEXT[] := '{}';
+BEGIN
+    FOR term IN
+        SELECT m[1] FROM regexp_matches(
+            lower(coalesce(input, '')),
+            '([\u3400-\u4dbf\u4e00-\u9fff\uf900-\ufaff]+|[a-z0-9]+)',
+            'g'
+        ) AS m
+    LOOP
+        IF term ~ '^[a-z0-9]' OR char_length(term) = 1 THEN
+            tokens := array_append(tokens, term);
+        ELSE
+            FOR i IN 1 .. char_length(term) - 1 LOOP
+                tokens := array_append(tokens, substr(term, i, 2));
+            END LOOP;
+            tokens := array_append(tokens, right(term, 1));
+        END IF;
+    END LOOP;
+    RETURN array_to_string(tokens, ' ');
+END
+$$;
+
+-- 名称权重最高, 其次是开发者、包名、分类和标签, 然后是简介, 最后是详细描述
+ALTER TABLE app_info ADD COLUMN IF NOT EXISTS search_document TSVECTOR GENERATED ALWAYS AS (
+    setweight(to_tsvector('simple', search_tokens(name)), 'A') ||
+    setweight(to_tsvector('simple', search_tokens(
+        developer_name || ' ' || coalesce(dev_en_name, '') || ' ' || pkg_name || ' ' ||
+        kind_name || ' ' || kind_type_name || ' ' || coalesce(tag_name, '')
+    )), 'B') ||
+    setweight(to_tsvector('simple', search_tokens(brief_desc)), 'C') ||
+    setweight(to_tsvector('simple', search_tokens(description)), 'D')
+) STORED;
+
+CREATE INDEX IF NOT EXISTS idx_app_info_search_document ON app_info USING GIN (search_document);
@@ -0,0 +1,12 @@
+-- 025_add_guess_retry: 添加猜测任务的重试列表 guess_retry
+
+-- 请求出错的 ID, 断点照常往后走, 任务最后再重试一遍
+CREATE TABLE IF NOT EXISTS guess_retry (
+    scan_id         BIGINT NOT NULL REFERENCES guess_scan(id) ON DELETE CASCADE, -- 对应 guess_scan 的 id
+    query_key       TEXT NOT NULL,                      -- 查询方式（app_id / pkg_name）
+    query_value     TEXT NOT NULL,                      -- 查询的值
+    attempts        INTEGER NOT NULL DEFAULT 1,         -- 出错的次数
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (scan_id, query_key, query_value)
+);
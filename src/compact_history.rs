@@ -0,0 +1,119 @@
+pub mod config;
+pub mod db;
+pub mod model;
+pub mod server;
+pub mod sync;
+pub mod utils;
+
+use anyhow::Context;
+use clap::Parser;
+use colored::Colorize;
+use tracing::{Level, event};
+
+use crate::db::history::CompactReport;
+
+/// 把 app_data_history 里的完整数据整理成快照 + 增量
+///
+/// 例如: `compact_history --dry-run` 先看看能省多少空间, 再去掉 `--dry-run` 真正改写
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// 只整理这些应用, 不填就是全部
+    #[arg(long = "app")]
+    apps: Vec<String>,
+    /// 只统计, 不改写
+    #[arg(long)]
+    dry_run: bool,
+    /// 日志等级, 由 utils::init_log 处理
+    #[arg(short, action = clap::ArgAction::Count, hide = true)]
+    v: u8,
+    #[arg(short, hide = true)]
+    d: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    utils::init_log();
+    let cli = Cli::parse();
+
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()
+        .with_context(|| "无法创建 tokio runtime")?;
+    rt.block_on(async_main(cli))
+}
+
+fn format_bytes(bytes: u64) -> String {
+    format!("{:.2} MiB", bytes as f64 / 1024.0 / 1024.0)
+}
+
+async fn async_main(cli: Cli) -> anyhow::Result<()> {
+    let config = config::Config::load().with_context(|| "无法加载配置文件")?;
+    let db = db::Database::new(config.database_url(), 2).await?;
+
+    let apps = if cli.apps.is_empty() {
+        db.get_data_history_app_ids().await?
+    } else {
+        cli.apps
+    };
+    println!("共 {} 个应用需要整理", apps.len());
+
+    let mut total = CompactReport::default();
+    let mut failed = 0;
+    for (i, app_id) in apps.iter().enumerate() {
+        match db.compact_data_history(app_id, cli.dry_run).await {
+            Ok(report) => total += report,
+            Err(e) => {
+                failed += 1;
+                event!(Level::WARN, "整理 {app_id} 失败, 已回滚: {e:#}");
+                println!("{}", format!("整理 {app_id} 失败: {e:#}").on_red());
+            }
+        }
+        if (i + 1) % 100 == 0 {
+            println!(
+                "已整理 {}/{} 个应用, 改写 {} 行",
+                i + 1,
+                apps.len(),
+                total.updated
+            );
+        }
+    }
+
+    println!(
+        "{}",
+        format!(
+            "{}{} 个应用, {} 行: {} 个快照, {} 个增量, {} 行需要改写{}",
+            if cli.dry_run { "[dry run] " } else { "" },
+            total.apps,
+            total.rows,
+            total.snapshots,
+            total.deltas,
+            total.updated,
+            if failed > 0 {
+                format!(", {failed} 个应用失败")
+            } else {
+                String::new()
+            }
+        )
+        .bright_green()
+    );
+    println!(
+        "{}",
+        format!(
+            "JSON 数据 {} -> {}, 节省 {} ({:.1}%)",
+            format_bytes(total.bytes_before),
+            format_bytes(total.bytes_after),
+            format_bytes(total.saved_bytes().max(0) as u64),
+            total.saved_percent()
+        )
+        .bright_green()
+    );
+    if !cli.dry_run && total.updated > 0 {
+        println!(
+            "{}",
+            "表文件不会马上变小, 需要运行 `VACUUM FULL app_data_history` 才能把空间还给系统"
+                .yellow()
+        );
+    }
+    Ok(())
+}
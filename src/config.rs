@@ -13,6 +13,9 @@ pub fn get_config() -> &'static Config {
 pub struct DatabaseConfig {
     pub url: String,
     pub max_connect: u32,
+    /// 启动服务时自动执行待执行的迁移
+    #[serde(default)]
+    pub auto_migrate: bool,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -37,12 +40,70 @@ pub struct ServeConfig {
     pub port: u16,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct SourcesConfig {
+    /// 启用的外部来源, 见 sync::source::build_sources
+    #[serde(default)]
+    pub enabled: Vec<String>,
+    /// 外部来源的抓取间隔 (秒)
+    #[serde(default = "default_sources_interval")]
+    pub interval_seconds: u64,
+}
+
+fn default_sources_interval() -> u64 {
+    86400
+}
+
+impl Default for SourcesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: Vec::new(),
+            interval_seconds: default_sources_interval(),
+        }
+    }
+}
+
+/// app_metrics 的保留策略
+///
+/// 原始数据保留 `raw_days` 天, 小时汇总保留 `hourly_days` 天, 天汇总一直保留.
+/// 每个应用最新的一条原始数据和导入的数据不会删除
+#[derive(Debug, Deserialize, Clone)]
+pub struct RetentionConfig {
+    /// 原始数据保留天数, 不填就一直保留
+    #[serde(default)]
+    pub raw_days: Option<u32>,
+    /// 小时汇总保留天数, 不填就一直保留
+    #[serde(default)]
+    pub hourly_days: Option<u32>,
+    /// 汇总和清理的间隔 (秒)
+    #[serde(default = "default_retention_interval")]
+    pub interval_seconds: u64,
+}
+
+fn default_retention_interval() -> u64 {
+    3600
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            raw_days: None,
+            hourly_days: None,
+            interval_seconds: default_retention_interval(),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
     pub database: DatabaseConfig,
     pub app: AppConfig,
     pub api: ApiConfig,
     pub serve: ServeConfig,
+    #[serde(default)]
+    pub sources: SourcesConfig,
+    #[serde(default)]
+    pub retention: RetentionConfig,
 }
 
 impl Config {
@@ -64,6 +125,10 @@ impl Config {
         self.database.max_connect
     }
 
+    pub fn auto_migrate(&self) -> bool {
+        self.database.auto_migrate
+    }
+
     pub fn packages(&self) -> &[String] {
         &self.app.packages
     }
@@ -91,4 +156,16 @@ impl Config {
     pub fn serve_port(&self) -> u16 {
         self.serve.port
     }
+
+    pub fn enabled_sources(&self) -> &[String] {
+        &self.sources.enabled
+    }
+
+    pub fn sources_interval(&self) -> u64 {
+        self.sources.interval_seconds
+    }
+
+    pub fn retention(&self) -> &RetentionConfig {
+        &self.retention
+    }
 }
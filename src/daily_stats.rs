@@ -0,0 +1,74 @@
+pub mod config;
+pub mod db;
+pub mod model;
+pub mod server;
+pub mod sync;
+pub mod utils;
+
+use anyhow::Context;
+use clap::Parser;
+use colored::Colorize;
+
+use crate::db::rollup::RollupReport;
+
+/// 汇总指标并计算每日统计 app_daily_stats
+///
+/// 例如: `daily_stats --all` 从第一条数据开始回填, `daily_stats --since 2025-01-01` 重新计算这一天之后的
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// 从这一天开始重新计算, 不填就只算最近两天
+    #[arg(long, value_parser = parse_day, conflicts_with = "all")]
+    since: Option<chrono::NaiveDate>,
+    /// 从第一条数据开始全部重新计算
+    #[arg(long)]
+    all: bool,
+    /// 日志等级, 由 utils::init_log 处理
+    #[arg(short, action = clap::ArgAction::Count, hide = true)]
+    v: u8,
+    #[arg(short, hide = true)]
+    d: bool,
+}
+
+fn parse_day(value: &str) -> Result<chrono::NaiveDate, String> {
+    utils::parse_datetime(value)
+        .map(|time| time.date_naive())
+        .ok_or_else(|| format!("无法解析日期 {value}"))
+}
+
+fn main() -> anyhow::Result<()> {
+    utils::init_log();
+    let cli = Cli::parse();
+
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()
+        .with_context(|| "无法创建 tokio runtime")?;
+    rt.block_on(async_main(cli))
+}
+
+async fn async_main(cli: Cli) -> anyhow::Result<()> {
+    let config = config::Config::load().with_context(|| "无法加载配置文件")?;
+    let db = db::Database::new(config.database_url(), 2).await?;
+
+    // 每日统计从天汇总计算, 先把还没汇总的原始数据汇总了
+    let mut report = RollupReport::default();
+    db.rollup_metrics(&mut report).await?;
+    println!(
+        "汇总了 id {}..{} 的原始数据: 小时汇总 {} 行, 天汇总 {} 行",
+        report.from_id, report.to_id, report.hourly, report.daily
+    );
+
+    let since = if cli.all {
+        Some(chrono::NaiveDate::MIN)
+    } else {
+        cli.since
+    };
+    let written = db.refresh_daily_stats(since).await?;
+    println!(
+        "{}",
+        format!("每日统计计算完成, 写入 {written} 行").bright_green()
+    );
+    Ok(())
+}
@@ -0,0 +1,154 @@
+//! 每个应用每天的统计 app_daily_stats
+//!
+//! 由 app_metrics_daily (见 [`super::rollup`]) 和 app_rating 计算, 只有当天有指标或者评分数据的日子才有一行,
+//! 每一行是当天结束时的最新数据, delta 是和上一行相比的变化.
+//! worker 每次汇总之后重新计算最近两天, 也可以用 `daily_stats` 从任意一天开始回填
+
+use anyhow::Result;
+use chrono::{Duration, Local, NaiveDate};
+use serde::Serialize;
+use sqlx::FromRow;
+use tracing::{Level, event};
+
+use crate::db::Database;
+
+/// 回填时一个事务计算这么多天
+const REFRESH_CHUNK_DAYS: i64 = 31;
+
+/// app_daily_stats 里的一行
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct AppDailyStats {
+    pub app_id: String,
+    pub day: NaiveDate,
+    pub download_count: i64,
+    /// 和上一行相比增加的下载量, 第一行为 None
+    pub download_delta: Option<i64>,
+    pub average_rating: Option<f64>,
+    pub rating_count: Option<i64>,
+    pub rating_count_delta: Option<i64>,
+    pub version: String,
+    pub size_bytes: i64,
+    /// 当天的指标原始数据条数
+    pub samples: i32,
+}
+
+impl Database {
+    /// 重新计算 `since` 到今天每天的统计, 返回写入的行数
+    ///
+    /// `since` 不填就从表里最后一天的前一天开始, 表是空的就从第一条数据开始
+    pub async fn refresh_daily_stats(&self, since: Option<NaiveDate>) -> Result<u64> {
+        const UPSERT: &str = r#"
+            WITH days AS (
+                SELECT app_id, bucket::date AS day, samples FROM app_metrics_daily
+                WHERE bucket >= $1::date AND bucket < $2::date
+                UNION ALL
+                SELECT DISTINCT app_id, created_at::date, 0 FROM app_rating
+                WHERE created_at >= $1::date AND created_at < $2::date
+            ), grouped AS (
+                SELECT app_id, day, sum(samples)::integer AS samples
+                FROM days GROUP BY app_id, day
+            )
+            INSERT INTO app_daily_stats (app_id, day, download_count, average_rating,
+                rating_count, version, size_bytes, samples, updated_at)
+            SELECT g.app_id, g.day, m.download_count, r.average_rating,
+                r.total_star_rating_count, m.version, m.size_bytes, g.samples, now()
+            FROM grouped g
+            JOIN LATERAL (
+                SELECT download_count, version, size_bytes FROM app_metrics_daily
+                WHERE app_id = g.app_id AND bucket < g.day + 1
+                ORDER BY bucket DESC LIMIT 1
+            ) m ON TRUE
+            LEFT JOIN LATERAL (
+                SELECT average_rating, total_star_rating_count FROM app_rating
+                WHERE app_id = g.app_id AND created_at < g.day + 1
+                ORDER BY created_at DESC LIMIT 1
+            ) r ON TRUE
+            ON CONFLICT (app_id, day) DO UPDATE SET
+                download_count = EXCLUDED.download_count,
+                average_rating = EXCLUDED.average_rating,
+                rating_count = EXCLUDED.rating_count,
+                version = EXCLUDED.version,
+                size_bytes = EXCLUDED.size_bytes,
+                samples = EXCLUDED.samples,
+                updated_at = now()
+        "#;
+        const UPDATE_DELTA: &str = r#"
+            UPDATE app_daily_stats s SET (download_delta, rating_count_delta) = (
+                SELECT s.download_count - p.download_count, s.rating_count - p.rating_count
+                FROM app_daily_stats p
+                WHERE p.app_id = s.app_id AND p.day < s.day
+                ORDER BY p.day DESC LIMIT 1
+            )
+            WHERE s.day >= $1 AND s.day < $2
+        "#;
+
+        let (last_day, first_day): (Option<NaiveDate>, Option<NaiveDate>) = sqlx::query_as(
+            "SELECT (SELECT max(day) FROM app_daily_stats), \
+             (SELECT min(bucket)::date FROM app_metrics_daily)",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        let Some(first_day) = first_day else {
+            return Ok(0);
+        };
+        // 前一天可能在上次计算之后才汇总完, 所以要重新算一次
+        let since = since
+            .or(last_day.map(|day| day - Duration::days(1)))
+            .unwrap_or(first_day)
+            .max(first_day);
+        let end = Local::now().date_naive() + Duration::days(1);
+
+        let mut written = 0;
+        let mut start = since;
+        while start < end {
+            let chunk_end = (start + Duration::days(REFRESH_CHUNK_DAYS)).min(end);
+            let mut tx = self.pool.begin().await?;
+            written += sqlx::query(UPSERT)
+                .bind(start)
+                .bind(chunk_end)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            sqlx::query(UPDATE_DELTA)
+                .bind(start)
+                .bind(chunk_end)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            event!(Level::DEBUG, "计算了 {start} 到 {chunk_end} 的每日统计");
+            start = chunk_end;
+        }
+        event!(
+            Level::INFO,
+            "每日统计计算完成: 从 {since} 开始, 写入 {written} 行"
+        );
+        Ok(written)
+    }
+
+    /// 获取应用每天的统计, 按日期排列
+    pub async fn get_app_daily_stats(
+        &self,
+        pkg_name: &str,
+        since: Option<NaiveDate>,
+        until: Option<NaiveDate>,
+    ) -> Result<Vec<AppDailyStats>> {
+        const QUERY: &str = r#"
+            SELECT s.app_id, s.day, s.download_count, s.download_delta,
+                s.average_rating::float8 AS average_rating, s.rating_count, s.rating_count_delta,
+                s.version, s.size_bytes, s.samples
+            FROM app_daily_stats s
+            JOIN app_info ai ON ai.app_id = s.app_id
+            WHERE ai.pkg_name = $1
+                AND ($2::date IS NULL OR s.day >= $2)
+                AND ($3::date IS NULL OR s.day <= $3)
+            ORDER BY s.day
+        "#;
+
+        Ok(sqlx::query_as(QUERY)
+            .bind(pkg_name)
+            .bind(since)
+            .bind(until)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+}
@@ -0,0 +1,453 @@
+//! 应用列表的筛选条件
+//!
+//! [`AppFilter`] 是一棵条件树, 用 `all` / `any` 组合, 编译成参数化的 SQL 条件, 查询 app_latest_info.
+//! 字段都是枚举, 只有列名会拼进 SQL, 值全部作为参数绑定
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Deserializer, Serialize};
+use sqlx::{Postgres, QueryBuilder};
+
+/// 一个筛选最多的条件数量, 防止构造特别大的查询
+pub const MAX_CONDITIONS: usize = 64;
+
+/// 最多嵌套的层数
+pub const MAX_DEPTH: usize = 8;
+
+/// 可以搜索的文字字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextField {
+    Name,
+    PkgName,
+    AppId,
+    DeveloperName,
+    #[serde(alias = "developer_en_name")]
+    DevEnName,
+}
+
+impl TextField {
+    /// 查询参数 `search_key` 的值, 兼容以前的 `developer_en_name`
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "name" => Some(Self::Name),
+            "pkg_name" => Some(Self::PkgName),
+            "app_id" => Some(Self::AppId),
+            "developer_name" => Some(Self::DeveloperName),
+            "dev_en_name" | "developer_en_name" => Some(Self::DevEnName),
+            _ => None,
+        }
+    }
+
+    fn column(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::PkgName => "pkg_name",
+            Self::AppId => "app_id",
+            Self::DeveloperName => "developer_name",
+            Self::DevEnName => "dev_en_name",
+        }
+    }
+}
+
+/// 可以按范围筛选的数值字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NumericField {
+    DownloadCount,
+    SizeBytes,
+    AverageRating,
+    /// total_star_rating_count
+    RatingCount,
+    /// price 是 NUMERIC(10,2), 直接和参数比较
+    Price,
+    TargetSdk,
+    Minsdk,
+    CompileSdkVersion,
+    MinHmosApiLevel,
+}
+
+impl NumericField {
+    fn expr(&self) -> &'static str {
+        match self {
+            Self::DownloadCount => "download_count",
+            Self::SizeBytes => "size_bytes",
+            Self::AverageRating => "average_rating",
+            Self::RatingCount => "total_star_rating_count",
+            Self::Price => "price",
+            Self::TargetSdk => "target_sdk",
+            Self::Minsdk => "minsdk",
+            Self::CompileSdkVersion => "compile_sdk_version",
+            Self::MinHmosApiLevel => "min_hmos_api_level",
+        }
+    }
+}
+
+/// 可以按时间范围筛选的字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateField {
+    ListedAt,
+    CreatedAt,
+    MetricsCreatedAt,
+}
+
+impl DateField {
+    fn column(&self) -> &'static str {
+        match self {
+            Self::ListedAt => "listed_at",
+            Self::CreatedAt => "created_at",
+            Self::MetricsCreatedAt => "metrics_created_at",
+        }
+    }
+}
+
+/// 数组字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArrayField {
+    MainDeviceCodes,
+    ReleaseCountries,
+}
+
+impl ArrayField {
+    fn column(&self) -> &'static str {
+        match self {
+            Self::MainDeviceCodes => "main_device_codes",
+            Self::ReleaseCountries => "release_countries",
+        }
+    }
+}
+
+fn deserialize_datetime<'de, D>(deserializer: D) -> Result<Option<DateTime<Local>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(value) => crate::utils::parse_datetime(&value)
+            .map(Some)
+            .ok_or_else(|| serde::de::Error::custom(format!("无法解析时间 {value}"))),
+        None => Ok(None),
+    }
+}
+
+/// 筛选条件
+///
+/// JSON 例子: `{"all": [{"range": {"field": "download_count", "min": 10000}},
+/// {"any": [{"kind": "游戏"}, {"tag": "棋牌"}]}]}`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AppFilter {
+    /// 所有条件都满足, 没有条件时总是满足
+    All(Vec<AppFilter>),
+    /// 任意一个条件满足, 没有条件时总是不满足
+    Any(Vec<AppFilter>),
+    /// 文字匹配, 默认模糊匹配 (不区分大小写, 包含就算)
+    Text {
+        field: TextField,
+        value: String,
+        #[serde(default)]
+        exact: bool,
+    },
+    /// 数值范围, 包含两端
+    Range {
+        field: NumericField,
+        #[serde(default)]
+        min: Option<f64>,
+        #[serde(default)]
+        max: Option<f64>,
+    },
+    /// 时间范围, 包含 since, 不包含 until
+    DateRange {
+        field: DateField,
+        #[serde(default, deserialize_with = "deserialize_datetime")]
+        since: Option<DateTime<Local>>,
+        #[serde(default, deserialize_with = "deserialize_datetime")]
+        until: Option<DateTime<Local>>,
+    },
+    /// 数组包含所有给出的值
+    Contains {
+        field: ArrayField,
+        values: Vec<String>,
+    },
+    /// 分类, kind_name 或者 kind_type_name
+    Kind(String),
+    /// 标签 tag_name
+    Tag(String),
+    /// 是否付费 (is_pay)
+    Paid(bool),
+    /// 是否含应用内购买 (iap)
+    Iap(bool),
+}
+
+impl Default for AppFilter {
+    fn default() -> Self {
+        Self::All(Vec::new())
+    }
+}
+
+/// `ILIKE` 模式里的 `%` `_` `\` 当作普通字符
+fn escape_like(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+impl AppFilter {
+    /// 条件的数量, 不算 all / any 本身
+    fn conditions(&self) -> usize {
+        match self {
+            Self::All(filters) | Self::Any(filters) => filters.iter().map(Self::conditions).sum(),
+            _ => 1,
+        }
+    }
+
+    fn depth(&self) -> usize {
+        match self {
+            Self::All(filters) | Self::Any(filters) => {
+                1 + filters.iter().map(Self::depth).max().unwrap_or(0)
+            }
+            _ => 0,
+        }
+    }
+
+    /// 检查条件数量、嵌套层数和范围, 出错时返回错误信息
+    pub fn validate(&self) -> Result<(), String> {
+        if self.conditions() > MAX_CONDITIONS {
+            return Err(format!("筛选条件不能超过 {MAX_CONDITIONS} 个"));
+        }
+        if self.depth() > MAX_DEPTH {
+            return Err(format!("筛选条件不能嵌套超过 {MAX_DEPTH} 层"));
+        }
+        self.validate_ranges()
+    }
+
+    fn validate_ranges(&self) -> Result<(), String> {
+        match self {
+            Self::All(filters) | Self::Any(filters) => {
+                filters.iter().try_for_each(Self::validate_ranges)
+            }
+            Self::Range {
+                field,
+                min: Some(min),
+                max: Some(max),
+            } if min > max => Err(format!("{field:?} 的范围 {min} 到 {max} 无效")),
+            Self::Range { field, min, max }
+                if min.is_some_and(|v| !v.is_finite()) || max.is_some_and(|v| !v.is_finite()) =>
+            {
+                Err(format!("{field:?} 的范围不是有效的数字"))
+            }
+            Self::DateRange {
+                field,
+                since: Some(since),
+                until: Some(until),
+            } if since >= until => Err(format!("{field:?} 的时间范围 {since} 到 {until} 无效")),
+            _ => Ok(()),
+        }
+    }
+
+    /// 把条件写进 `builder`, 不带 `WHERE`, 值都用参数绑定
+    pub fn push_sql(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        match self {
+            Self::All(filters) | Self::Any(filters) if filters.is_empty() => {
+                builder.push(if matches!(self, Self::All(_)) {
+                    "TRUE"
+                } else {
+                    "FALSE"
+                });
+            }
+            Self::All(filters) | Self::Any(filters) => {
+                let separator = if matches!(self, Self::All(_)) {
+                    " AND "
+                } else {
+                    " OR "
+                };
+                builder.push("(");
+                for (i, filter) in filters.iter().enumerate() {
+                    if i > 0 {
+                        builder.push(separator);
+                    }
+                    filter.push_sql(builder);
+                }
+                builder.push(")");
+            }
+            Self::Text {
+                field,
+                value,
+                exact,
+            } => {
+                if *exact {
+                    builder.push(format_args!("{} = ", field.column()));
+                    builder.push_bind(value.clone());
+                } else {
+                    builder.push(format_args!("{} ILIKE ", field.column()));
+                    builder.push_bind(format!("%{}%", escape_like(value)));
+                }
+            }
+            Self::Range { field, min, max } => {
+                let mut bounds = Vec::new();
+                if let Some(min) = min {
+                    bounds.push((">=", *min));
+                }
+                if let Some(max) = max {
+                    bounds.push(("<=", *max));
+                }
+                if bounds.is_empty() {
+                    builder.push("TRUE");
+                    return;
+                }
+                builder.push("(");
+                for (i, (op, value)) in bounds.into_iter().enumerate() {
+                    if i > 0 {
+                        builder.push(" AND ");
+                    }
+                    builder.push(format_args!("{} {op} ", field.expr()));
+                    builder.push_bind(value);
+                    builder.push("::float8");
+                }
+                builder.push(")");
+            }
+            Self::DateRange {
+                field,
+                since,
+                until,
+            } => match (since, until) {
+                (None, None) => {
+                    builder.push("TRUE");
+                }
+                (since, until) => {
+                    builder.push("(");
+                    if let Some(since) = since {
+                        builder.push(format_args!("{} >= ", field.column()));
+                        builder.push_bind(*since);
+                    }
+                    if since.is_some() && until.is_some() {
+                        builder.push(" AND ");
+                    }
+                    if let Some(until) = until {
+                        builder.push(format_args!("{} < ", field.column()));
+                        builder.push_bind(*until);
+                    }
+                    builder.push(")");
+                }
+            },
+            Self::Contains { field, values } => {
+                builder.push(format_args!("{} @> ", field.column()));
+                builder.push_bind(values.clone());
+            }
+            Self::Kind(kind) => {
+                builder.push("(kind_name = ");
+                builder.push_bind(kind.clone());
+                builder.push(" OR kind_type_name = ");
+                builder.push_bind(kind.clone());
+                builder.push(")");
+            }
+            Self::Tag(tag) => {
+                builder.push("tag_name = ");
+                builder.push_bind(tag.clone());
+            }
+            Self::Paid(paid) => {
+                builder.push("is_pay = ");
+                builder.push_bind(*paid);
+            }
+            Self::Iap(iap) => {
+                builder.push("iap = ");
+                builder.push_bind(*iap);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_sql(filter: &AppFilter) -> String {
+        let mut builder = QueryBuilder::<Postgres>::new("");
+        filter.push_sql(&mut builder);
+        builder.sql().to_string()
+    }
+
+    #[test]
+    fn test_push_sql() {
+        let filter: AppFilter = serde_json::from_str(
+            r#"{"all": [
+                {"range": {"field": "download_count", "min": 10000}},
+                {"any": [{"kind": "游戏"}, {"tag": "棋牌"}]},
+                {"contains": {"field": "main_device_codes", "values": ["0", "1"]}},
+                {"date_range": {"field": "listed_at", "since": "2025-01-01", "until": "2025-02-01"}},
+                {"text": {"field": "developer_en_name", "value": "huawei"}},
+                {"paid": false}
+            ]}"#,
+        )
+        .unwrap();
+        filter.validate().unwrap();
+        assert_eq!(
+            to_sql(&filter),
+            "((download_count >= $1::float8) \
+             AND ((kind_name = $2 OR kind_type_name = $3) OR tag_name = $4) \
+             AND main_device_codes @> $5 \
+             AND (listed_at >= $6 AND listed_at < $7) \
+             AND dev_en_name ILIKE $8 \
+             AND is_pay = $9)"
+        );
+
+        assert_eq!(to_sql(&AppFilter::default()), "TRUE");
+        assert_eq!(to_sql(&AppFilter::Any(Vec::new())), "FALSE");
+        assert_eq!(
+            to_sql(&AppFilter::Range {
+                field: NumericField::AverageRating,
+                min: Some(3.0),
+                max: Some(4.5),
+            }),
+            "(average_rating >= $1::float8 AND average_rating <= $2::float8)"
+        );
+        assert_eq!(
+            to_sql(&AppFilter::Range {
+                field: NumericField::Price,
+                min: Some(1.0),
+                max: Some(6.0),
+            }),
+            "(price >= $1::float8 AND price <= $2::float8)"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let range = |min, max| AppFilter::Range {
+            field: NumericField::SizeBytes,
+            min,
+            max,
+        };
+        assert!(range(Some(2.0), Some(1.0)).validate().is_err());
+        assert!(range(Some(f64::NAN), None).validate().is_err());
+        assert!(
+            AppFilter::Any(vec![range(Some(1.0), None)])
+                .validate()
+                .is_ok()
+        );
+
+        let too_many = AppFilter::All(vec![AppFilter::Paid(true); MAX_CONDITIONS + 1]);
+        assert!(too_many.validate().is_err());
+
+        let mut nested = AppFilter::Iap(true);
+        for _ in 0..=MAX_DEPTH {
+            nested = AppFilter::All(vec![nested]);
+        }
+        assert!(nested.validate().is_err());
+
+        let bad_time = serde_json::from_str::<AppFilter>(
+            r#"{"date_range": {"field": "created_at", "since": "昨天"}}"#,
+        );
+        assert!(bad_time.is_err());
+    }
+
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like(r"100%_a\b"), r"100\%\_a\\b");
+    }
+}
@@ -0,0 +1,482 @@
+//! 增长排行
+//!
+//! 按时间窗口比较每个应用窗口开始时 (基准) 和窗口内最新的数据.
+//! 基准是窗口开始前最后一个样本, 窗口开始前太久没有数据就用窗口内第一个样本,
+//! 实际用的基准时间和最新时间都会返回.
+//! 评分只在变化时才记录, 所以评分的基准不限制往前看多久.
+//!
+//! 窗口的开始和结束都是零点时, 下载量增长直接读每天的统计 app_daily_stats (见 [`super::daily_stats`]).
+//! 评分增长依然读 app_rating, 因为每天的统计里没有 1 星 / 5 星的评分人数
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveTime};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::db::Database;
+
+/// 不填时间范围时的默认窗口
+pub const DEFAULT_TIME_RANGE: &str = "7d";
+
+/// 至少要有两个样本才能算增长
+pub const MIN_SAMPLES: u32 = 2;
+
+/// 评分增长排行默认的最少评分人数
+pub const DEFAULT_MIN_VOTES: i64 = 50;
+
+/// 排行的时间窗口
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct GrowthWindow {
+    pub since: DateTime<Local>,
+    pub until: DateTime<Local>,
+}
+
+impl GrowthWindow {
+    /// 按查询参数确定窗口
+    ///
+    /// 填了 `since` 就是自定义窗口, 忽略 `time_range`;
+    /// 否则 `time_range` 为 `30d` / `12h` / `2w` 这样的长度, 默认 [`DEFAULT_TIME_RANGE`].
+    /// `until` 不填就是现在
+    pub fn resolve(
+        time_range: Option<&str>,
+        since: Option<DateTime<Local>>,
+        until: Option<DateTime<Local>>,
+        now: DateTime<Local>,
+    ) -> Result<Self, String> {
+        let until = until.unwrap_or(now);
+        let since = match since {
+            Some(since) => since,
+            None => {
+                let time_range = time_range.unwrap_or(DEFAULT_TIME_RANGE);
+                if time_range == "custom" {
+                    return Err("自定义时间范围需要填写 since".to_string());
+                }
+                until - parse_span(time_range)?
+            }
+        };
+        if since >= until {
+            return Err(format!("时间范围 {since} 到 {until} 无效"));
+        }
+        Ok(Self { since, until })
+    }
+
+    /// 找基准样本时往前看的起点, 最多往前一个窗口长度, 至少一天
+    pub fn lookback_start(&self) -> DateTime<Local> {
+        self.since - (self.until - self.since).max(Duration::days(1))
+    }
+
+    /// 开始和结束都是零点的窗口, 返回 (开始日期, 结束日期), 不包含结束日期
+    pub fn whole_days(&self) -> Option<(NaiveDate, NaiveDate)> {
+        let midnight = |at: DateTime<Local>| at.time() == NaiveTime::MIN;
+        (midnight(self.since) && midnight(self.until))
+            .then(|| (self.since.date_naive(), self.until.date_naive()))
+    }
+}
+
+/// 解析 `7d` / `12h` / `2w` 这样的时间长度
+fn parse_span(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("无法解析时间范围 {value}, 应为 1d / 7d / 30d / 12h 这样的格式");
+    let unit = value.chars().last().ok_or_else(invalid)?;
+    let number: i64 = value[..value.len() - unit.len_utf8()]
+        .parse()
+        .map_err(|_| invalid())?;
+    if number <= 0 {
+        return Err(invalid());
+    }
+    let span = match unit {
+        'h' => Duration::try_hours(number),
+        'd' => Duration::try_days(number),
+        'w' => Duration::try_weeks(number),
+        _ => None,
+    };
+    span.filter(|span| *span <= Duration::days(3650))
+        .ok_or_else(invalid)
+}
+
+/// 带窗口的排行结果
+#[derive(Debug, Clone, Serialize)]
+pub struct GrowthRanking<T> {
+    #[serde(flatten)]
+    pub window: GrowthWindow,
+    pub apps: Vec<T>,
+}
+
+/// 下载量增长的排序方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadGrowthSort {
+    /// 增加的下载量
+    #[default]
+    Absolute,
+    /// 增加的下载量 / 基准下载量
+    Relative,
+}
+
+impl DownloadGrowthSort {
+    fn order_by(&self) -> &'static str {
+        match self {
+            Self::Absolute => "growth DESC, growth_rate DESC NULLS LAST",
+            Self::Relative => "growth_rate DESC NULLS LAST, growth DESC",
+        }
+    }
+}
+
+/// 下载量增长排行的一项
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct DownloadGrowth {
+    pub app_id: String,
+    pub name: String,
+    pub pkg_name: String,
+    pub developer_name: String,
+    pub icon_url: String,
+    /// 实际用作基准的样本时间, 可能早于窗口开始 (窗口开始前最后一个样本) 或者晚于窗口开始 (窗口内第一个样本)
+    pub baseline_at: DateTime<Local>,
+    pub baseline_downloads: i64,
+    /// 窗口内最新的样本时间
+    pub latest_at: DateTime<Local>,
+    pub latest_downloads: i64,
+    pub growth: i64,
+    /// 基准下载量为 0 时为 None
+    pub growth_rate: Option<f64>,
+    /// 基准到最新之间 (包含两端) 的样本数
+    pub samples: i64,
+}
+
+/// 评分增长的排序方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RatingGrowthSort {
+    /// 平均评分的变化
+    #[default]
+    AverageRating,
+    /// 总评分人数的变化
+    RatingCount,
+    /// 新增的 5 星评分
+    #[serde(rename = "star_5")]
+    Star5,
+    /// 新增的 1 星评分
+    #[serde(rename = "star_1")]
+    Star1,
+}
+
+impl RatingGrowthSort {
+    fn order_by(&self, desc: bool) -> String {
+        let (first, second) = match self {
+            Self::AverageRating => ("rating_change", "count_change"),
+            Self::RatingCount => ("count_change", "rating_change"),
+            Self::Star5 => ("new_star_5", "count_change"),
+            Self::Star1 => ("new_star_1", "count_change"),
+        };
+        let direction = if desc { "DESC" } else { "ASC" };
+        format!("{first} {direction}, {second} {direction}")
+    }
+}
+
+/// 评分增长排行的一项
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct RatingGrowth {
+    pub app_id: String,
+    pub name: String,
+    pub pkg_name: String,
+    pub developer_name: String,
+    pub icon_url: String,
+    /// 实际用作基准的评分时间, 窗口开始前没有评分时是窗口内第一条
+    pub baseline_at: DateTime<Local>,
+    pub baseline_rating: f64,
+    pub baseline_count: i64,
+    /// 窗口内最新的评分时间
+    pub latest_at: DateTime<Local>,
+    pub latest_rating: f64,
+    pub latest_count: i64,
+    pub rating_change: f64,
+    pub count_change: i64,
+    pub new_star_1: i64,
+    pub new_star_5: i64,
+    /// 基准到最新之间 (包含两端) 的评分记录数
+    pub samples: i64,
+}
+
+impl Database {
+    /// 下载量增长排行
+    ///
+    /// 样本来自原始数据和汇总 (见 [`super::rollup`]), 所以原始数据被清理之后也能算.
+    /// 按天的窗口读 app_daily_stats, 见 [`Self::get_download_growth_apps_daily`].
+    /// 样本数少于 `min_samples` (至少 [`MIN_SAMPLES`]) 或者基准下载量少于 `min_baseline` 的应用不参与排行
+    pub async fn get_download_growth_apps(
+        &self,
+        window: GrowthWindow,
+        sort: DownloadGrowthSort,
+        min_samples: u32,
+        min_baseline: i64,
+        limit: u32,
+    ) -> Result<Vec<DownloadGrowth>> {
+        if let Some(days) = window.whole_days() {
+            return self
+                .get_download_growth_apps_daily(
+                    window,
+                    days,
+                    sort,
+                    min_samples,
+                    min_baseline,
+                    limit,
+                )
+                .await;
+        }
+        // 汇总行的 last_at / download_count 就是那条原始数据的, UNION 会去掉重复
+        let query = format!(
+            r#"
+            WITH points AS (
+                SELECT app_id, created_at AS at, download_count FROM app_metrics
+                WHERE created_at >= $1 AND created_at <= $3
+                UNION
+                SELECT app_id, last_at, download_count FROM app_metrics_hourly
+                WHERE bucket >= date_trunc('hour', $1) AND last_at >= $1 AND last_at <= $3
+                UNION
+                SELECT app_id, last_at, download_count FROM app_metrics_daily
+                WHERE bucket >= date_trunc('day', $1) AND last_at >= $1 AND last_at <= $3
+            ), bounds AS (
+                SELECT app_id,
+                    coalesce(max(at) FILTER (WHERE at <= $2), min(at)) AS baseline_at,
+                    max(at) AS latest_at
+                FROM points GROUP BY app_id
+                HAVING max(at) > $2
+            ), stats AS (
+                SELECT b.app_id, b.baseline_at, b.latest_at, count(*) AS samples,
+                    max(p.download_count) FILTER (WHERE p.at = b.baseline_at) AS baseline_downloads,
+                    max(p.download_count) FILTER (WHERE p.at = b.latest_at) AS latest_downloads
+                FROM bounds b
+                JOIN points p ON p.app_id = b.app_id AND p.at >= b.baseline_at AND p.at <= b.latest_at
+                WHERE b.latest_at > b.baseline_at
+                GROUP BY b.app_id, b.baseline_at, b.latest_at
+            )
+            SELECT ai.app_id, ai.name, ai.pkg_name, ai.developer_name, ai.icon_url,
+                s.baseline_at, s.baseline_downloads, s.latest_at, s.latest_downloads,
+                s.latest_downloads - s.baseline_downloads AS growth,
+                CASE WHEN s.baseline_downloads > 0
+                    THEN (s.latest_downloads - s.baseline_downloads)::float8 / s.baseline_downloads
+                END AS growth_rate,
+                s.samples
+            FROM stats s
+            JOIN app_info ai ON ai.app_id = s.app_id
+            WHERE s.samples >= $4 AND s.baseline_downloads >= $5
+            ORDER BY {}, ai.app_id
+            LIMIT $6
+        "#,
+            sort.order_by()
+        );
+
+        Ok(sqlx::query_as(&query)
+            .bind(window.lookback_start())
+            .bind(window.since)
+            .bind(window.until)
+            .bind(min_samples.max(MIN_SAMPLES) as i64)
+            .bind(min_baseline)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    /// 按天的下载量增长排行, 读 app_daily_stats
+    ///
+    /// 每一行是当天结束时的下载量, 基准是窗口开始前一天 (往前找, 最多一个窗口长度) 的一行,
+    /// 没有就是窗口内第一天. 样本时间是那天结束的时间 (不晚于现在), 样本数是天数
+    async fn get_download_growth_apps_daily(
+        &self,
+        window: GrowthWindow,
+        (since_day, until_day): (NaiveDate, NaiveDate),
+        sort: DownloadGrowthSort,
+        min_samples: u32,
+        min_baseline: i64,
+        limit: u32,
+    ) -> Result<Vec<DownloadGrowth>> {
+        let query = format!(
+            r#"
+            WITH points AS (
+                SELECT app_id, day, download_count FROM app_daily_stats
+                WHERE day >= $1 AND day < $3
+            ), bounds AS (
+                SELECT app_id,
+                    coalesce(max(day) FILTER (WHERE day < $2), min(day)) AS baseline_day,
+                    max(day) AS latest_day
+                FROM points GROUP BY app_id
+                HAVING max(day) >= $2
+            ), stats AS (
+                SELECT b.app_id, b.baseline_day, b.latest_day, count(*) AS samples,
+                    max(p.download_count) FILTER (WHERE p.day = b.baseline_day) AS baseline_downloads,
+                    max(p.download_count) FILTER (WHERE p.day = b.latest_day) AS latest_downloads
+                FROM bounds b
+                JOIN points p ON p.app_id = b.app_id AND p.day >= b.baseline_day AND p.day <= b.latest_day
+                WHERE b.latest_day > b.baseline_day
+                GROUP BY b.app_id, b.baseline_day, b.latest_day
+            )
+            SELECT ai.app_id, ai.name, ai.pkg_name, ai.developer_name, ai.icon_url,
+                least((s.baseline_day + 1)::timestamptz, now()) AS baseline_at,
+                s.baseline_downloads,
+                least((s.latest_day + 1)::timestamptz, now()) AS latest_at,
+                s.latest_downloads,
+                s.latest_downloads - s.baseline_downloads AS growth,
+                CASE WHEN s.baseline_downloads > 0
+                    THEN (s.latest_downloads - s.baseline_downloads)::float8 / s.baseline_downloads
+                END AS growth_rate,
+                s.samples
+            FROM stats s
+            JOIN app_info ai ON ai.app_id = s.app_id
+            WHERE s.samples >= $4 AND s.baseline_downloads >= $5
+            ORDER BY {}, ai.app_id
+            LIMIT $6
+        "#,
+            sort.order_by()
+        );
+
+        Ok(sqlx::query_as(&query)
+            .bind(window.lookback_start().date_naive())
+            .bind(since_day)
+            .bind(until_day)
+            .bind(min_samples.max(MIN_SAMPLES) as i64)
+            .bind(min_baseline)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    /// 评分增长排行, 按 app_rating 的历史计算
+    ///
+    /// 只有窗口内评分有变化, 并且最新评分人数不少于 `min_votes` 的应用参与排行
+    pub async fn get_rating_growth_apps(
+        &self,
+        window: GrowthWindow,
+        sort: RatingGrowthSort,
+        desc: bool,
+        min_votes: i64,
+        limit: u32,
+    ) -> Result<Vec<RatingGrowth>> {
+        const COLUMNS: &str = "created_at, average_rating, total_star_rating_count, star_1_rating_count, star_5_rating_count";
+        // 基准优先取窗口开始前最后一条, 没有就取窗口内第一条
+        let query = format!(
+            r#"
+            WITH latest AS (
+                SELECT DISTINCT ON (app_id) app_id, count(*) OVER (PARTITION BY app_id) AS changes,
+                    {COLUMNS}
+                FROM app_rating
+                WHERE created_at > $1 AND created_at <= $2
+                ORDER BY app_id, created_at DESC, id DESC
+            ), stats AS (
+                SELECT l.app_id, l.changes + (b.created_at <= $1)::int AS samples,
+                    b.created_at AS baseline_at, b.average_rating::float8 AS baseline_rating,
+                    b.total_star_rating_count::bigint AS baseline_count,
+                    l.created_at AS latest_at, l.average_rating::float8 AS latest_rating,
+                    l.total_star_rating_count::bigint AS latest_count,
+                    (l.average_rating - b.average_rating)::float8 AS rating_change,
+                    (l.total_star_rating_count - b.total_star_rating_count)::bigint AS count_change,
+                    (l.star_1_rating_count - b.star_1_rating_count)::bigint AS new_star_1,
+                    (l.star_5_rating_count - b.star_5_rating_count)::bigint AS new_star_5
+                FROM latest l
+                JOIN LATERAL (
+                    (SELECT {COLUMNS} FROM app_rating
+                    WHERE app_id = l.app_id AND created_at <= $1
+                    ORDER BY created_at DESC, id DESC LIMIT 1)
+                    UNION ALL
+                    (SELECT {COLUMNS} FROM app_rating
+                    WHERE app_id = l.app_id AND created_at > $1
+                    ORDER BY created_at, id LIMIT 1)
+                    LIMIT 1
+                ) b ON TRUE
+                WHERE l.created_at > b.created_at AND l.total_star_rating_count >= $3
+            )
+            SELECT ai.app_id, ai.name, ai.pkg_name, ai.developer_name, ai.icon_url,
+                s.baseline_at, s.baseline_rating, s.baseline_count,
+                s.latest_at, s.latest_rating, s.latest_count,
+                s.rating_change, s.count_change, s.new_star_1, s.new_star_5, s.samples
+            FROM stats s
+            JOIN app_info ai ON ai.app_id = s.app_id
+            ORDER BY {}, ai.app_id
+            LIMIT $4
+        "#,
+            sort.order_by(desc)
+        );
+
+        Ok(sqlx::query_as(&query)
+            .bind(window.since)
+            .bind(window.until)
+            .bind(min_votes)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_span() {
+        assert_eq!(parse_span("1d"), Ok(Duration::days(1)));
+        assert_eq!(parse_span("30d"), Ok(Duration::days(30)));
+        assert_eq!(parse_span("12h"), Ok(Duration::hours(12)));
+        assert_eq!(parse_span("2w"), Ok(Duration::weeks(2)));
+        for invalid in ["", "d", "0d", "-1d", "7", "7m", "99999d", "一d", "7天"] {
+            assert!(parse_span(invalid).is_err(), "{invalid} 应该无效");
+        }
+    }
+
+    #[test]
+    fn test_resolve_window() {
+        let now = Local::now();
+        let window = GrowthWindow::resolve(None, None, None, now).unwrap();
+        assert_eq!(window.until, now);
+        assert_eq!(window.since, now - Duration::days(7));
+        assert_eq!(window.lookback_start(), now - Duration::days(14));
+
+        let window = GrowthWindow::resolve(Some("12h"), None, None, now).unwrap();
+        assert_eq!(window.lookback_start(), now - Duration::hours(36));
+
+        let since = now - Duration::days(3);
+        let until = now - Duration::days(1);
+        let window = GrowthWindow::resolve(Some("30d"), Some(since), Some(until), now).unwrap();
+        assert_eq!((window.since, window.until), (since, until));
+
+        assert!(GrowthWindow::resolve(Some("custom"), None, None, now).is_err());
+        assert!(GrowthWindow::resolve(None, Some(until), Some(since), now).is_err());
+    }
+
+    #[test]
+    fn test_whole_days() {
+        let day = |value: &str| crate::utils::parse_datetime(value).unwrap();
+        let window = GrowthWindow {
+            since: day("2025-06-01"),
+            until: day("2025-06-08"),
+        };
+        assert_eq!(
+            window.whole_days(),
+            Some((
+                NaiveDate::from_ymd_opt(2025, 6, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2025, 6, 8).unwrap()
+            ))
+        );
+        assert_eq!(
+            window.lookback_start().date_naive(),
+            NaiveDate::from_ymd_opt(2025, 5, 25).unwrap()
+        );
+
+        let window = GrowthWindow {
+            since: day("2025-06-01 12:00:00"),
+            until: day("2025-06-08"),
+        };
+        assert_eq!(window.whole_days(), None);
+    }
+
+    #[test]
+    fn test_rating_sort() {
+        let sort: RatingGrowthSort = serde_json::from_str("\"star_5\"").unwrap();
+        assert_eq!(sort, RatingGrowthSort::Star5);
+        assert_eq!(
+            RatingGrowthSort::default().order_by(true),
+            "rating_change DESC, count_change DESC"
+        );
+        assert_eq!(
+            RatingGrowthSort::Star1.order_by(false),
+            "new_star_1 ASC, count_change ASC"
+        );
+    }
+}
@@ -0,0 +1,543 @@
+//! app_data_history 的快照 + 增量存储
+//!
+//! 每个应用隔一段时间存一行完整的 raw_json_data (快照), 中间的行只存相对快照的
+//! JSON Patch (RFC 6902 的 add / remove / replace). 增量都是相对快照算的, 不是一条接一条,
+//! 读任何一个版本最多只需要快照 + 一个 patch
+
+use std::collections::HashMap;
+use std::ops::AddAssign;
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local};
+use serde_json::{Value as JsonValue, json};
+use sqlx::{FromRow, PgConnection};
+
+use crate::db::Database;
+use crate::model::AppDataHistory;
+use crate::utils::json_content_hash;
+
+/// 一个快照最多带这么多行增量, 之后存新的快照
+pub const SNAPSHOT_EVERY: i64 = 32;
+
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn unescape_pointer(token: &str) -> String {
+    token.replace("~1", "/").replace("~0", "~")
+}
+
+/// 计算从 `old` 到 `new` 的 JSON Patch
+///
+/// 对象逐个 key 递归比较, 数组和其他值不一样就整个替换
+pub fn diff(old: &JsonValue, new: &JsonValue) -> JsonValue {
+    let mut ops = Vec::new();
+    diff_into(&mut String::new(), old, new, &mut ops);
+    JsonValue::Array(ops)
+}
+
+fn diff_into(path: &mut String, old: &JsonValue, new: &JsonValue, ops: &mut Vec<JsonValue>) {
+    match (old, new) {
+        (JsonValue::Object(old), JsonValue::Object(new)) => {
+            for key in old.keys().filter(|key| !new.contains_key(*key)) {
+                ops.push(
+                    json!({"op": "remove", "path": format!("{path}/{}", escape_pointer(key))}),
+                );
+            }
+            for (key, value) in new {
+                let len = path.len();
+                path.push('/');
+                path.push_str(&escape_pointer(key));
+                match old.get(key) {
+                    Some(old_value) => diff_into(path, old_value, value, ops),
+                    None => ops.push(json!({"op": "add", "path": path.as_str(), "value": value})),
+                }
+                path.truncate(len);
+            }
+        }
+        _ if old != new => {
+            ops.push(json!({"op": "replace", "path": path.as_str(), "value": new}));
+        }
+        _ => {}
+    }
+}
+
+/// 找到 `pointer` 的父节点和最后一段
+fn pointer_parent<'a>(
+    root: &'a mut JsonValue,
+    pointer: &str,
+) -> Result<(&'a mut JsonValue, String)> {
+    let Some(pointer) = pointer.strip_prefix('/') else {
+        bail!("JSON Pointer {pointer} 不是以 / 开头");
+    };
+    let mut tokens: Vec<String> = pointer.split('/').map(unescape_pointer).collect();
+    let last = tokens.pop().unwrap_or_default();
+    let mut node = root;
+    for token in &tokens {
+        node = match node {
+            JsonValue::Object(map) => map.get_mut(token),
+            JsonValue::Array(items) => token.parse::<usize>().ok().and_then(|i| items.get_mut(i)),
+            _ => None,
+        }
+        .with_context(|| format!("JSON Pointer /{pointer} 不存在"))?;
+    }
+    Ok((node, last))
+}
+
+/// 把 JSON Patch 应用到 `base` 上
+pub fn apply_patch(base: &JsonValue, patch: &JsonValue) -> Result<JsonValue> {
+    let mut result = base.clone();
+    let Some(ops) = patch.as_array() else {
+        bail!("JSON Patch 不是数组");
+    };
+    for op in ops {
+        let kind = op["op"].as_str().unwrap_or_default();
+        let path = op["path"].as_str().context("JSON Patch 缺少 path")?;
+        let value = op.get("value").cloned();
+        if path.is_empty() {
+            match (kind, value) {
+                ("add" | "replace", Some(value)) => result = value,
+                _ => bail!("无法对根节点执行 {kind}"),
+            }
+            continue;
+        }
+        let (parent, key) = pointer_parent(&mut result, path)?;
+        match (kind, parent, value) {
+            ("add" | "replace", JsonValue::Object(map), Some(value)) => {
+                map.insert(key, value);
+            }
+            ("add", JsonValue::Array(items), Some(value)) if key == "-" => items.push(value),
+            ("add", JsonValue::Array(items), Some(value)) => {
+                let i = key.parse::<usize>().ok().filter(|i| *i <= items.len());
+                items.insert(i.with_context(|| format!("数组下标 {path} 越界"))?, value);
+            }
+            ("replace", JsonValue::Array(items), Some(value)) => {
+                let item = key.parse::<usize>().ok().and_then(|i| items.get_mut(i));
+                *item.with_context(|| format!("数组下标 {path} 越界"))? = value;
+            }
+            ("remove", JsonValue::Object(map), _) => {
+                map.remove(&key)
+                    .with_context(|| format!("要删除的 {path} 不存在"))?;
+            }
+            ("remove", JsonValue::Array(items), _) => {
+                let i = key.parse::<usize>().ok().filter(|i| *i < items.len());
+                items.remove(i.with_context(|| format!("数组下标 {path} 越界"))?);
+            }
+            _ => bail!("无法执行 JSON Patch {op}"),
+        }
+    }
+    Ok(result)
+}
+
+fn json_len(value: &JsonValue) -> usize {
+    serde_json::to_string(value).map_or(0, |s| s.len())
+}
+
+/// 一个应用最新的快照
+#[derive(Debug, Clone)]
+pub struct DataSnapshot {
+    pub id: i64,
+    pub data: JsonValue,
+    /// 已经有多少行增量指向这个快照
+    pub deltas: i64,
+}
+
+/// 决定新数据怎么存, `Some(patch)` 存成相对 `snapshot` 的增量, `None` 存成新的快照
+///
+/// 没有快照, 快照带的增量够多了, 或者 patch 比完整数据的一半还大, 都存快照
+pub fn plan_entry(snapshot: Option<&DataSnapshot>, new: &JsonValue) -> Option<JsonValue> {
+    let snapshot = snapshot?;
+    if snapshot.deltas + 1 >= SNAPSHOT_EVERY {
+        return None;
+    }
+    let patch = diff(&snapshot.data, new);
+    (json_len(&patch) * 2 <= json_len(new)).then_some(patch)
+}
+
+/// app_data_history 里存的一行
+#[derive(Debug, Clone, FromRow)]
+struct StoredDataHistory {
+    id: i64,
+    app_id: String,
+    pkg_name: String,
+    raw_json_data: Option<JsonValue>,
+    raw_json_patch: Option<JsonValue>,
+    snapshot_id: Option<i64>,
+    content_hash: Option<String>,
+    created_at: DateTime<Local>,
+}
+
+const SELECT_STORED: &str = "SELECT h.id, h.app_id, h.pkg_name, h.raw_json_data, h.raw_json_patch, \
+     h.snapshot_id, h.content_hash, h.created_at FROM app_data_history h";
+
+impl StoredDataHistory {
+    fn stored_len(&self) -> usize {
+        self.raw_json_data.as_ref().map_or(0, json_len)
+            + self.raw_json_patch.as_ref().map_or(0, json_len)
+    }
+
+    /// 还原成完整数据, `snapshots` 里要有这一行指向的快照
+    fn rebuild(&self, snapshots: &HashMap<i64, JsonValue>) -> Result<JsonValue> {
+        match (&self.raw_json_data, &self.raw_json_patch, self.snapshot_id) {
+            (Some(data), _, _) => Ok(data.clone()),
+            (None, Some(patch), Some(snapshot_id)) => {
+                let snapshot = snapshots.get(&snapshot_id).with_context(|| {
+                    format!("data history {} 的快照 {snapshot_id} 不存在", self.id)
+                })?;
+                apply_patch(snapshot, patch)
+                    .with_context(|| format!("无法还原 data history {}", self.id))
+            }
+            _ => bail!("data history {} 既不是快照也不是增量", self.id),
+        }
+    }
+
+    fn into_history(self, raw_json_data: JsonValue) -> AppDataHistory {
+        AppDataHistory {
+            id: self.id,
+            app_id: self.app_id,
+            pkg_name: self.pkg_name,
+            raw_json_data,
+            created_at: self.created_at,
+        }
+    }
+}
+
+/// 读出一批行 (以及它们指向的快照), 还原成完整数据
+async fn rebuild_rows(
+    conn: &mut PgConnection,
+    rows: Vec<StoredDataHistory>,
+) -> Result<Vec<AppDataHistory>> {
+    let mut snapshots: HashMap<i64, JsonValue> = rows
+        .iter()
+        .filter_map(|row| Some((row.id, row.raw_json_data.clone()?)))
+        .collect();
+    let mut missing: Vec<i64> = rows
+        .iter()
+        .filter_map(|row| row.snapshot_id)
+        .filter(|id| !snapshots.contains_key(id))
+        .collect();
+    missing.sort_unstable();
+    missing.dedup();
+    if !missing.is_empty() {
+        let found: Vec<(i64, JsonValue)> = sqlx::query_as(
+            "SELECT id, raw_json_data FROM app_data_history \
+             WHERE id = ANY($1) AND raw_json_data IS NOT NULL",
+        )
+        .bind(&missing)
+        .fetch_all(conn)
+        .await?;
+        snapshots.extend(found);
+    }
+
+    let mut histories = Vec::with_capacity(rows.len());
+    for row in rows {
+        let data = row.rebuild(&snapshots)?;
+        histories.push(row.into_history(data));
+    }
+    Ok(histories)
+}
+
+/// [`Database::compact_data_history`] 的统计
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CompactReport {
+    pub apps: u64,
+    pub rows: u64,
+    pub snapshots: u64,
+    pub deltas: u64,
+    /// 实际改写的行数
+    pub updated: u64,
+    /// raw_json_data + raw_json_patch 的 JSON 字节数
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+impl CompactReport {
+    pub fn saved_bytes(&self) -> i64 {
+        self.bytes_before as i64 - self.bytes_after as i64
+    }
+
+    pub fn saved_percent(&self) -> f64 {
+        if self.bytes_before == 0 {
+            0.0
+        } else {
+            self.saved_bytes() as f64 * 100.0 / self.bytes_before as f64
+        }
+    }
+}
+
+impl AddAssign for CompactReport {
+    fn add_assign(&mut self, other: Self) {
+        self.apps += other.apps;
+        self.rows += other.rows;
+        self.snapshots += other.snapshots;
+        self.deltas += other.deltas;
+        self.updated += other.updated;
+        self.bytes_before += other.bytes_before;
+        self.bytes_after += other.bytes_after;
+    }
+}
+
+impl Database {
+    /// 批量获取应用最新的快照, 在 save_app_data_batch 的事务里调用
+    pub async fn get_data_snapshots(
+        conn: &mut PgConnection,
+        app_ids: &[&str],
+    ) -> Result<HashMap<String, DataSnapshot>> {
+        const QUERY: &str = r#"
+            SELECT q.app_id, s.id, s.raw_json_data,
+                (SELECT count(*) FROM app_data_history d WHERE d.snapshot_id = s.id) AS deltas
+            FROM unnest($1::text[]) AS q(app_id)
+            JOIN LATERAL (
+                SELECT id, raw_json_data FROM app_data_history
+                WHERE app_id = q.app_id AND raw_json_data IS NOT NULL
+                ORDER BY id DESC LIMIT 1
+            ) s ON TRUE
+        "#;
+
+        let rows: Vec<(String, i64, JsonValue, i64)> =
+            sqlx::query_as(QUERY).bind(app_ids).fetch_all(conn).await?;
+        Ok(rows
+            .into_iter()
+            .map(|(app_id, id, data, deltas)| (app_id, DataSnapshot { id, data, deltas }))
+            .collect())
+    }
+
+    /// 还原应用最新的一条原始数据
+    ///
+    /// `column` 是 app_id 或者 pkg_name
+    pub async fn get_last_data(
+        conn: &mut PgConnection,
+        column: &str,
+        value: &str,
+    ) -> Result<Option<JsonValue>> {
+        let query =
+            format!("{SELECT_STORED} WHERE h.{column} = $1 ORDER BY h.created_at DESC LIMIT 1");
+        let row: Option<StoredDataHistory> = sqlx::query_as(&query)
+            .bind(value)
+            .fetch_optional(&mut *conn)
+            .await?;
+        match row {
+            Some(row) => Ok(rebuild_rows(conn, vec![row])
+                .await?
+                .pop()
+                .map(|history| history.raw_json_data)),
+            None => Ok(None),
+        }
+    }
+
+    /// 获取应用完整的原始数据历史, 按时间排列
+    pub async fn get_data_history(&self, app_id: &str) -> Result<Vec<AppDataHistory>> {
+        let query = format!("{SELECT_STORED} WHERE h.app_id = $1 ORDER BY h.created_at, h.id");
+        let mut conn = self.pool.acquire().await?;
+        let rows: Vec<StoredDataHistory> = sqlx::query_as(&query)
+            .bind(app_id)
+            .fetch_all(&mut *conn)
+            .await?;
+        rebuild_rows(&mut conn, rows).await
+    }
+
+    /// 获取应用在 `at` 时的原始数据, 即 `at` 之前最后一条记录
+    pub async fn get_data_version(
+        &self,
+        app_id: &str,
+        at: DateTime<Local>,
+    ) -> Result<Option<AppDataHistory>> {
+        let query = format!(
+            "{SELECT_STORED} WHERE h.app_id = $1 AND h.created_at <= $2 \
+             ORDER BY h.created_at DESC, h.id DESC LIMIT 1"
+        );
+        let mut conn = self.pool.acquire().await?;
+        let rows: Vec<StoredDataHistory> = sqlx::query_as(&query)
+            .bind(app_id)
+            .bind(at)
+            .fetch_all(&mut *conn)
+            .await?;
+        Ok(rebuild_rows(&mut conn, rows).await?.pop())
+    }
+
+    /// 有 data history 的所有 app_id
+    pub async fn get_data_history_app_ids(&self) -> Result<Vec<String>> {
+        Ok(
+            sqlx::query_scalar("SELECT DISTINCT app_id FROM app_data_history ORDER BY app_id")
+                .fetch_all(&self.pool)
+                .await?,
+        )
+    }
+
+    /// 把一个应用的 data history 重新整理成快照 + 增量
+    ///
+    /// 在一个事务里按 id 顺序还原每一行, 重新决定存快照还是增量, 只改写有变化的行,
+    /// 顺便补上缺失的 content_hash. `dry_run` 时只统计, 回滚事务
+    pub async fn compact_data_history(&self, app_id: &str, dry_run: bool) -> Result<CompactReport> {
+        let mut tx = self.pool.begin().await?;
+        // 和 save_app_data_batch 用同一个锁, 整理时不会有新数据插进来
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+            .bind(app_id)
+            .execute(&mut *tx)
+            .await?;
+        let query = format!("{SELECT_STORED} WHERE h.app_id = $1 ORDER BY h.id");
+        let rows: Vec<StoredDataHistory> = sqlx::query_as(&query)
+            .bind(app_id)
+            .fetch_all(&mut *tx)
+            .await?;
+
+        let mut report = CompactReport {
+            apps: 1,
+            rows: rows.len() as u64,
+            ..Default::default()
+        };
+        let mut stored = HashMap::new();
+        for row in &rows {
+            report.bytes_before += row.stored_len() as u64;
+            stored.insert(
+                row.id,
+                (
+                    row.snapshot_id,
+                    row.raw_json_patch.clone(),
+                    row.content_hash.clone(),
+                ),
+            );
+        }
+        let histories = rebuild_rows(&mut tx, rows).await?;
+
+        let mut snapshot: Option<DataSnapshot> = None;
+        for history in histories {
+            let data = history.raw_json_data;
+            let content_hash = json_content_hash(&data);
+            let (data, patch, snapshot_id) = match plan_entry(snapshot.as_ref(), &data) {
+                Some(patch) => {
+                    let current = snapshot.as_mut().expect("有 patch 就有快照");
+                    current.deltas += 1;
+                    report.deltas += 1;
+                    (None, Some(patch), Some(current.id))
+                }
+                None => {
+                    report.snapshots += 1;
+                    snapshot = Some(DataSnapshot {
+                        id: history.id,
+                        data: data.clone(),
+                        deltas: 0,
+                    });
+                    (Some(data), None, None)
+                }
+            };
+            report.bytes_after +=
+                (data.as_ref().map_or(0, json_len) + patch.as_ref().map_or(0, json_len)) as u64;
+
+            let (old_snapshot_id, old_patch, old_hash) = &stored[&history.id];
+            let unchanged = *old_snapshot_id == snapshot_id
+                && *old_patch == patch
+                && old_hash.as_deref() == Some(content_hash.as_str());
+            if unchanged {
+                continue;
+            }
+            report.updated += 1;
+            sqlx::query(
+                "UPDATE app_data_history \
+                 SET raw_json_data = $2, raw_json_patch = $3, snapshot_id = $4, content_hash = $5 \
+                 WHERE id = $1",
+            )
+            .bind(history.id)
+            .bind(data)
+            .bind(patch)
+            .bind(snapshot_id)
+            .bind(content_hash)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        if !dry_run {
+            tx.commit().await?;
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_and_apply() {
+        let old = json!({
+            "name": "木鸟民宿",
+            "description": "很长的描述",
+            "size": 100,
+            "tags": ["a", "b"],
+            "a/b": {"x~y": 1, "keep": true},
+        });
+        let new = json!({
+            "name": "木鸟民宿",
+            "description": "新的描述",
+            "tags": ["a"],
+            "version": "1.0.1",
+            "a/b": {"x~y": 2, "keep": true},
+        });
+        let patch = diff(&old, &new);
+        assert_eq!(
+            patch,
+            json!([
+                {"op": "remove", "path": "/size"},
+                {"op": "replace", "path": "/a~1b/x~0y", "value": 2},
+                {"op": "replace", "path": "/description", "value": "新的描述"},
+                {"op": "replace", "path": "/tags", "value": ["a"]},
+                {"op": "add", "path": "/version", "value": "1.0.1"},
+            ])
+        );
+        assert_eq!(apply_patch(&old, &patch).unwrap(), new);
+        assert_eq!(diff(&new, &new), json!([]));
+        assert_eq!(apply_patch(&new, &json!([])).unwrap(), new);
+
+        // 类型变了就整个替换
+        let patch = diff(&json!({"a": 1}), &json!([1]));
+        assert_eq!(apply_patch(&json!({"a": 1}), &patch).unwrap(), json!([1]));
+    }
+
+    #[test]
+    fn test_apply_patch_errors() {
+        let base = json!({"a": {"b": 1}, "list": [1, 2]});
+        assert!(apply_patch(&base, &json!({})).is_err());
+        assert!(apply_patch(&base, &json!([{"op": "remove", "path": "/c"}])).is_err());
+        assert!(apply_patch(&base, &json!([{"op": "add", "path": "/x/y", "value": 1}])).is_err());
+        assert!(apply_patch(&base, &json!([{"op": "move", "path": "/a"}])).is_err());
+        assert_eq!(
+            apply_patch(
+                &base,
+                &json!([
+                    {"op": "add", "path": "/list/-", "value": 3},
+                    {"op": "remove", "path": "/list/0"},
+                    {"op": "add", "path": "/a/c", "value": 2},
+                ])
+            )
+            .unwrap(),
+            json!({"a": {"b": 1, "c": 2}, "list": [2, 3]})
+        );
+    }
+
+    #[test]
+    fn test_plan_entry() {
+        let description = "描述".repeat(200);
+        let data = json!({"description": description, "version": "1.0.0"});
+        let new = json!({"description": description, "version": "1.0.1"});
+        assert_eq!(plan_entry(None, &new), None);
+
+        let mut snapshot = DataSnapshot {
+            id: 1,
+            data,
+            deltas: 0,
+        };
+        assert_eq!(
+            plan_entry(Some(&snapshot), &new),
+            Some(json!([{"op": "replace", "path": "/version", "value": "1.0.1"}]))
+        );
+
+        // 增量够多了, 存新的快照
+        snapshot.deltas = SNAPSHOT_EVERY - 1;
+        assert_eq!(plan_entry(Some(&snapshot), &new), None);
+
+        // 几乎全变了, patch 不划算
+        snapshot.deltas = 0;
+        let changed = json!({"description": "短", "version": "2.0.0"});
+        assert_eq!(plan_entry(Some(&snapshot), &changed), None);
+    }
+}
@@ -1,30 +1,101 @@
 use anyhow::Result;
+use chrono::{DateTime, Local};
 use serde_json::Value as JsonValue;
+use sqlx::{PgConnection, Postgres, QueryBuilder, query_builder::Separated};
 
 use crate::db::Database;
-use crate::model::{AppInfo, AppMetric, AppRating};
+use crate::db::history::plan_entry;
+use crate::model::{AppInfo, AppMetric, AppQuery, AppRating};
+use crate::sync::drift::SchemaDrift;
+use crate::sync::guess::{
+    GuessJob, density::BucketStats, negative::NegativeRange, scan::GuessScan,
+};
+use crate::sync::quarantine::ParseFailure;
 use crate::sync::substance::SubstanceData;
+use crate::utils::json_content_hash;
+
+/// 一条多行 INSERT 最多的行数, postgres 一条语句最多 65535 个参数
+const BULK_INSERT_ROWS: usize = 1000;
+
+/// 按 app_id 从 app_info 取 pkg_name 的子查询
+fn push_pkg_name<'args>(
+    row: &mut Separated<'_, 'args, Postgres, &'static str>,
+    app_id: &'args str,
+) {
+    row.push("(SELECT pkg_name FROM app_info WHERE app_id = ")
+        .push_bind_unseparated(app_id)
+        .push_unseparated(")");
+}
 
 impl Database {
-    /// 插入应用信息到 app_info 表
-    pub async fn insert_app_info(&self, app_info: &AppInfo) -> Result<()> {
-        const QUERY: &str = r#"
-            INSERT INTO app_info (
-                app_id, alliance_app_id, name, pkg_name, dev_id, developer_name,
-                dev_en_name, supplier, kind_id, kind_name, tag_name,
-                kind_type_id, kind_type_name, icon_url, brief_desc, description,
-                privacy_url, ctype, detail_id, app_level, jocat_id, iap, hms,
-                tariff_type, packing_type, order_app, denpend_gms, denpend_hms,
-                force_update, img_tag, is_pay, is_disciplined, is_shelves,
-                submit_type, delete_archive, charging, button_grey, app_gift,
-                free_days, pay_install_type, created_at, listed_at, comment,
-                release_countries, main_device_codes
-            ) VALUES (
-                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15,
-                $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28,
-                $29, $30, $31, $32, $33, $34, $35, $36, $37, $38, $39, $40, $41,
-                $42, $43, $44, $45
-            )
+    /// 批量插入应用信息到 app_info 表, 已经存在的更新
+    ///
+    /// 同一批里不能有重复的 app_id
+    pub async fn insert_app_infos(conn: &mut PgConnection, app_infos: &[AppInfo]) -> Result<()> {
+        for chunk in app_infos.chunks(BULK_INSERT_ROWS) {
+            let mut builder = QueryBuilder::<Postgres>::new(
+                r#"INSERT INTO app_info (
+                    app_id, alliance_app_id, name, pkg_name, dev_id, developer_name,
+                    dev_en_name, supplier, kind_id, kind_name, tag_name,
+                    kind_type_id, kind_type_name, icon_url, brief_desc, description,
+                    privacy_url, ctype, detail_id, app_level, jocat_id, iap, hms,
+                    tariff_type, packing_type, order_app, denpend_gms, denpend_hms,
+                    force_update, img_tag, is_pay, is_disciplined, is_shelves,
+                    submit_type, delete_archive, charging, button_grey, app_gift,
+                    free_days, pay_install_type, created_at, listed_at, comment,
+                    release_countries, main_device_codes, content_hash
+                ) "#,
+            );
+            builder.push_values(chunk, |mut row, app_info| {
+                row.push_bind(&app_info.app_id)
+                    .push_bind(&app_info.alliance_app_id)
+                    .push_bind(&app_info.name)
+                    .push_bind(&app_info.pkg_name)
+                    .push_bind(&app_info.dev_id)
+                    .push_bind(&app_info.developer_name)
+                    .push_bind(&app_info.dev_en_name)
+                    .push_bind(&app_info.supplier)
+                    .push_bind(app_info.kind_id)
+                    .push_bind(&app_info.kind_name)
+                    .push_bind(&app_info.tag_name)
+                    .push_bind(app_info.kind_type_id)
+                    .push_bind(&app_info.kind_type_name)
+                    .push_bind(&app_info.icon_url)
+                    .push_bind(&app_info.brief_desc)
+                    .push_bind(&app_info.description)
+                    .push_bind(&app_info.privacy_url)
+                    .push_bind(app_info.ctype)
+                    .push_bind(&app_info.detail_id)
+                    .push_bind(app_info.app_level)
+                    .push_bind(app_info.jocat_id)
+                    .push_bind(app_info.iap)
+                    .push_bind(app_info.hms)
+                    .push_bind(&app_info.tariff_type)
+                    .push_bind(app_info.packing_type)
+                    .push_bind(app_info.order_app)
+                    .push_bind(app_info.denpend_gms)
+                    .push_bind(app_info.denpend_hms)
+                    .push_bind(app_info.force_update)
+                    .push_bind(&app_info.img_tag)
+                    .push_bind(app_info.is_pay)
+                    .push_bind(app_info.is_disciplined)
+                    .push_bind(app_info.is_shelves)
+                    .push_bind(app_info.submit_type)
+                    .push_bind(app_info.delete_archive)
+                    .push_bind(app_info.charging)
+                    .push_bind(app_info.button_grey)
+                    .push_bind(app_info.app_gift)
+                    .push_bind(app_info.free_days)
+                    .push_bind(app_info.pay_install_type)
+                    .push_bind(app_info.created_at)
+                    .push_bind(app_info.listed_at)
+                    .push_bind(&app_info.comment)
+                    .push_bind(&app_info.release_countries)
+                    .push_bind(&app_info.main_device_codes)
+                    .push_bind(app_info.content_hash());
+            });
+            builder.push(
+                r#"
             ON CONFLICT (app_id) DO UPDATE SET
                 alliance_app_id = EXCLUDED.alliance_app_id,
                 name = EXCLUDED.name,
@@ -68,170 +139,227 @@ impl Database {
                 listed_at = EXCLUDED.listed_at,
                 comment = EXCLUDED.comment,
                 release_countries = EXCLUDED.release_countries,
-                main_device_codes = EXCLUDED.main_device_codes
-        "#;
+                main_device_codes = EXCLUDED.main_device_codes,
+                content_hash = EXCLUDED.content_hash"#,
+            );
+            builder.build().execute(&mut *conn).await?;
+        }
 
-        sqlx::query(QUERY)
-            .bind(&app_info.app_id)
-            .bind(&app_info.alliance_app_id)
-            .bind(&app_info.name)
-            .bind(&app_info.pkg_name)
-            .bind(&app_info.dev_id)
-            .bind(&app_info.developer_name)
-            .bind(&app_info.dev_en_name)
-            .bind(&app_info.supplier)
-            .bind(app_info.kind_id)
-            .bind(&app_info.kind_name)
-            .bind(&app_info.tag_name)
-            .bind(app_info.kind_type_id)
-            .bind(&app_info.kind_type_name)
-            .bind(&app_info.icon_url)
-            .bind(&app_info.brief_desc)
-            .bind(&app_info.description)
-            .bind(&app_info.privacy_url)
-            .bind(app_info.ctype)
-            .bind(&app_info.detail_id)
-            .bind(app_info.app_level)
-            .bind(app_info.jocat_id)
-            .bind(app_info.iap)
-            .bind(app_info.hms)
-            .bind(&app_info.tariff_type)
-            .bind(app_info.packing_type)
-            .bind(app_info.order_app)
-            .bind(app_info.denpend_gms)
-            .bind(app_info.denpend_hms)
-            .bind(app_info.force_update)
-            .bind(&app_info.img_tag)
-            .bind(app_info.is_pay)
-            .bind(app_info.is_disciplined)
-            .bind(app_info.is_shelves)
-            .bind(app_info.submit_type)
-            .bind(app_info.delete_archive)
-            .bind(app_info.charging)
-            .bind(app_info.button_grey)
-            .bind(app_info.app_gift)
-            .bind(app_info.free_days)
-            .bind(app_info.pay_install_type)
-            .bind(app_info.created_at)
-            .bind(app_info.listed_at)
-            .bind(&app_info.comment)
-            .bind(&app_info.release_countries)
-            .bind(&app_info.main_device_codes)
-            .execute(&self.pool)
-            .await?;
+        Ok(())
+    }
+
+    /// 批量插入应用指标到 app_metrics 表
+    pub async fn insert_app_metrics(
+        conn: &mut PgConnection,
+        app_metrics: &[AppMetric],
+    ) -> Result<()> {
+        for chunk in app_metrics.chunks(BULK_INSERT_ROWS) {
+            let mut builder = QueryBuilder::<Postgres>::new(
+                r#"INSERT INTO app_metrics (
+                    app_id, pkg_name, version, version_code, size_bytes, sha256, info_score,
+                    info_rate_count, download_count, price, release_date, new_features,
+                    upgrade_msg, target_sdk, minsdk, compile_sdk_version,
+                    min_hmos_api_level, api_release_type, content_hash
+                ) "#,
+            );
+            builder.push_values(chunk, |mut row, app_metric| {
+                row.push_bind(&app_metric.app_id);
+                push_pkg_name(&mut row, &app_metric.app_id);
+                row.push_bind(&app_metric.version)
+                    .push_bind(app_metric.version_code)
+                    .push_bind(app_metric.size_bytes)
+                    .push_bind(&app_metric.sha256)
+                    .push_bind(app_metric.info_score)
+                    .push_bind(app_metric.info_rate_count)
+                    .push_bind(app_metric.download_count)
+                    .push_bind(&app_metric.price)
+                    .push_bind(app_metric.release_date)
+                    .push_bind(&app_metric.new_features)
+                    .push_bind(&app_metric.upgrade_msg)
+                    .push_bind(app_metric.target_sdk)
+                    .push_bind(app_metric.minsdk)
+                    .push_bind(app_metric.compile_sdk_version)
+                    .push_bind(app_metric.min_hmos_api_level)
+                    .push_bind(&app_metric.api_release_type)
+                    .push_bind(app_metric.content_hash());
+            });
+            builder.build().execute(&mut *conn).await?;
+        }
 
         Ok(())
     }
 
-    /// 插入应用指标到 app_metrics 表
-    pub async fn insert_app_metric(&self, app_metric: &AppMetric) -> Result<()> {
+    /// 批量插入导入的历史指标, 使用指标自己的 created_at 和 imported_from
+    pub async fn insert_imported_metrics(&self, metrics: &[AppMetric]) -> Result<()> {
         const QUERY: &str = r#"
             INSERT INTO app_metrics (
                 app_id, pkg_name, version, version_code, size_bytes, sha256, info_score,
                 info_rate_count, download_count, price, release_date, new_features,
                 upgrade_msg, target_sdk, minsdk, compile_sdk_version,
-                min_hmos_api_level, api_release_type
+                min_hmos_api_level, api_release_type, content_hash, imported_from, created_at
             ) VALUES (
                 $1,
                 (SELECT pkg_name FROM app_info WHERE app_id = $1),
-                $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17
+                $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17,
+                $18, $19, $20
             )
         "#;
 
-        sqlx::query(QUERY)
-            .bind(&app_metric.app_id)
-            .bind(&app_metric.version)
-            .bind(app_metric.version_code)
-            .bind(app_metric.size_bytes)
-            .bind(&app_metric.sha256)
-            .bind(app_metric.info_score)
-            .bind(app_metric.info_rate_count)
-            .bind(app_metric.download_count)
-            .bind(&app_metric.price)
-            .bind(app_metric.release_date)
-            .bind(&app_metric.new_features)
-            .bind(&app_metric.upgrade_msg)
-            .bind(app_metric.target_sdk)
-            .bind(app_metric.minsdk)
-            .bind(app_metric.compile_sdk_version)
-            .bind(app_metric.min_hmos_api_level)
-            .bind(&app_metric.api_release_type)
-            .execute(&self.pool)
-            .await?;
-
+        let mut tx = self.pool.begin().await?;
+        for metric in metrics {
+            sqlx::query(QUERY)
+                .bind(&metric.app_id)
+                .bind(&metric.version)
+                .bind(metric.version_code)
+                .bind(metric.size_bytes)
+                .bind(&metric.sha256)
+                .bind(metric.info_score)
+                .bind(metric.info_rate_count)
+                .bind(metric.download_count)
+                .bind(&metric.price)
+                .bind(metric.release_date)
+                .bind(&metric.new_features)
+                .bind(&metric.upgrade_msg)
+                .bind(metric.target_sdk)
+                .bind(metric.minsdk)
+                .bind(metric.compile_sdk_version)
+                .bind(metric.min_hmos_api_level)
+                .bind(&metric.api_release_type)
+                .bind(metric.content_hash())
+                .bind(&metric.imported_from)
+                .bind(metric.created_at)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
         Ok(())
     }
 
-    /// 插入应用评分到 app_rating 表
-    pub async fn insert_app_rating(&self, app_rating: &AppRating) -> Result<()> {
+    /// 记录外部来源看到的应用
+    ///
+    /// 已经有记录的只更新 last_seen_at / external_id / metadata, discovered 只会从 false 变成 true
+    pub async fn upsert_app_source(
+        &self,
+        app_id: &str,
+        source: &str,
+        external_id: Option<&str>,
+        metadata: &JsonValue,
+        discovered: bool,
+    ) -> Result<()> {
         const QUERY: &str = r#"
-            INSERT INTO app_rating (
-                app_id, pkg_name, average_rating,
-                star_1_rating_count, star_2_rating_count, star_3_rating_count,
-                star_4_rating_count, star_5_rating_count, my_star_rating,
-                total_star_rating_count, only_star_count, full_average_rating,
-                source_type
-            ) VALUES (
-                $1,
-                (SELECT pkg_name FROM app_info WHERE app_id = $1),
-                $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12
-            )
+            INSERT INTO app_source (app_id, source, external_id, metadata, discovered)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (app_id, source) DO UPDATE SET
+                external_id = COALESCE(EXCLUDED.external_id, app_source.external_id),
+                metadata = EXCLUDED.metadata,
+                discovered = app_source.discovered OR EXCLUDED.discovered,
+                last_seen_at = now()
         "#;
 
         sqlx::query(QUERY)
-            .bind(&app_rating.app_id)
-            .bind(app_rating.average_rating)
-            .bind(app_rating.star_1_rating_count)
-            .bind(app_rating.star_2_rating_count)
-            .bind(app_rating.star_3_rating_count)
-            .bind(app_rating.star_4_rating_count)
-            .bind(app_rating.star_5_rating_count)
-            .bind(app_rating.my_star_rating)
-            .bind(app_rating.total_star_rating_count)
-            .bind(app_rating.only_star_count)
-            .bind(app_rating.full_average_rating)
-            .bind(&app_rating.source_type)
+            .bind(app_id)
+            .bind(source)
+            .bind(external_id)
+            .bind(metadata)
+            .bind(discovered)
             .execute(&self.pool)
             .await?;
-
         Ok(())
     }
 
-    /// 插入应用数据到 app_data_history 表
-    pub async fn insert_data_history(&self, app_id: &str, data: &JsonValue) -> Result<()> {
-        let query = r#"
-            INSERT INTO app_data_history (app_id, pkg_name, raw_json_data)
-            VALUES ($1,
-            (SELECT pkg_name FROM app_info WHERE app_id = $1),
-            $2::jsonb)
-        "#;
-
-        sqlx::query(query)
-            .bind(app_id)
-            .bind(data)
-            .execute(&self.pool)
-            .await?;
+    /// 批量插入应用评分到 app_rating 表
+    pub async fn insert_app_ratings(
+        conn: &mut PgConnection,
+        app_ratings: &[AppRating],
+    ) -> Result<()> {
+        for chunk in app_ratings.chunks(BULK_INSERT_ROWS) {
+            let mut builder = QueryBuilder::<Postgres>::new(
+                r#"INSERT INTO app_rating (
+                    app_id, pkg_name, average_rating,
+                    star_1_rating_count, star_2_rating_count, star_3_rating_count,
+                    star_4_rating_count, star_5_rating_count, my_star_rating,
+                    total_star_rating_count, only_star_count, full_average_rating,
+                    source_type, content_hash
+                ) "#,
+            );
+            builder.push_values(chunk, |mut row, app_rating| {
+                row.push_bind(&app_rating.app_id);
+                push_pkg_name(&mut row, &app_rating.app_id);
+                row.push_bind(app_rating.average_rating)
+                    .push_bind(app_rating.star_1_rating_count)
+                    .push_bind(app_rating.star_2_rating_count)
+                    .push_bind(app_rating.star_3_rating_count)
+                    .push_bind(app_rating.star_4_rating_count)
+                    .push_bind(app_rating.star_5_rating_count)
+                    .push_bind(app_rating.my_star_rating)
+                    .push_bind(app_rating.total_star_rating_count)
+                    .push_bind(app_rating.only_star_count)
+                    .push_bind(app_rating.full_average_rating)
+                    .push_bind(&app_rating.source_type)
+                    .push_bind(app_rating.content_hash());
+            });
+            builder.build().execute(&mut *conn).await?;
+        }
 
         Ok(())
     }
 
-    /// 插入评分数据到 app_rating_history 表
-    pub async fn insert_rating_history(&self, app_id: &str, rating: &JsonValue) -> Result<()> {
-        let query = r#"
-            INSERT INTO app_rating_history (app_id, pkg_name, raw_json_rating)
-            VALUES ($1,
-            (SELECT pkg_name FROM app_info WHERE app_id = $1),
-            $2::jsonb)
-        "#;
+    /// 批量插入应用数据到 app_data_history 表, 每一项是 (app_id, raw_json_data)
+    ///
+    /// 按 [`plan_entry`] 决定存完整快照还是相对最新快照的增量, 同一批里不能有重复的 app_id
+    pub async fn insert_data_histories(
+        conn: &mut PgConnection,
+        histories: &[(&str, &JsonValue)],
+    ) -> Result<()> {
+        let app_ids: Vec<&str> = histories.iter().map(|(app_id, _)| *app_id).collect();
+        let snapshots = Self::get_data_snapshots(conn, &app_ids).await?;
+        for chunk in histories.chunks(BULK_INSERT_ROWS) {
+            let mut builder = QueryBuilder::<Postgres>::new(
+                "INSERT INTO app_data_history \
+                 (app_id, pkg_name, raw_json_data, raw_json_patch, snapshot_id, content_hash) ",
+            );
+            builder.push_values(chunk, |mut row, (app_id, data)| {
+                row.push_bind(*app_id);
+                push_pkg_name(&mut row, app_id);
+                let snapshot = snapshots.get(*app_id);
+                match plan_entry(snapshot, data) {
+                    Some(patch) => row
+                        .push_bind(None::<JsonValue>)
+                        .push_bind(patch)
+                        .push_bind(snapshot.map(|s| s.id)),
+                    None => row
+                        .push_bind(*data)
+                        .push_bind(None::<JsonValue>)
+                        .push_bind(None::<i64>),
+                };
+                row.push_bind(json_content_hash(data));
+            });
+            builder.build().execute(&mut *conn).await?;
+        }
 
-        sqlx::query(query)
-            .bind(app_id)
-            .bind(rating)
-            .execute(&self.pool)
-            .await?;
+        Ok(())
+    }
+
+    /// 批量插入评分数据到 app_rating_history 表, 每一项是 (app_id, raw_json_rating)
+    pub async fn insert_rating_histories(
+        conn: &mut PgConnection,
+        histories: &[(&str, &JsonValue)],
+    ) -> Result<()> {
+        for chunk in histories.chunks(BULK_INSERT_ROWS) {
+            let mut builder = QueryBuilder::<Postgres>::new(
+                "INSERT INTO app_rating_history (app_id, pkg_name, raw_json_rating, content_hash) ",
+            );
+            builder.push_values(chunk, |mut row, (app_id, rating)| {
+                row.push_bind(*app_id);
+                push_pkg_name(&mut row, app_id);
+                row.push_bind(*rating).push_bind(json_content_hash(rating));
+            });
+            builder.build().execute(&mut *conn).await?;
+        }
 
         Ok(())
     }
+
     /// 插入 substance 到 substance_info 表
     pub async fn insert_substance(
         &self,
@@ -296,4 +424,290 @@ impl Database {
 
         Ok(())
     }
+
+    /// 插入一条数据结构记录到 schema_profile 表
+    pub async fn insert_schema_profile(
+        &self,
+        source: &str,
+        key: &str,
+        value_type: &str,
+        example_app_id: &str,
+    ) -> Result<()> {
+        const QUERY: &str = r#"
+            INSERT INTO schema_profile (source, key, value_type, example_app_id)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (source, key, value_type) DO NOTHING
+        "#;
+
+        sqlx::query(QUERY)
+            .bind(source)
+            .bind(key)
+            .bind(value_type)
+            .bind(example_app_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// 插入一条数据结构变化到 schema_drift 表
+    pub async fn insert_schema_drift(&self, drift: &SchemaDrift) -> Result<()> {
+        const QUERY: &str = r#"
+            INSERT INTO schema_drift (source, key, change, old_type, new_type, example_app_id)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (source, key, change, new_type) DO NOTHING
+        "#;
+
+        sqlx::query(QUERY)
+            .bind(&drift.source)
+            .bind(&drift.key)
+            .bind(drift.change.as_str())
+            .bind(&drift.old_type)
+            .bind(&drift.new_type)
+            .bind(&drift.example_app_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// 把解析失败的原始数据放进 app_quarantine 表
+    ///
+    /// 同一个应用已经有未处理的记录时, 更新原始数据和错误信息
+    pub async fn insert_quarantine(&self, failure: &ParseFailure) -> Result<()> {
+        const QUERY: &str = r#"
+            INSERT INTO app_quarantine (query_key, query_value, raw_json_data, error)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (query_key, query_value) WHERE resolved_at IS NULL
+            DO UPDATE SET
+                raw_json_data = EXCLUDED.raw_json_data,
+                error = EXCLUDED.error,
+                updated_at = now()
+        "#;
+
+        sqlx::query(QUERY)
+            .bind(failure.app_query.app_db_name())
+            .bind(failure.app_query.name())
+            .bind(&failure.payload)
+            .bind(&failure.error)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// 重新处理成功, 标记为已处理
+    pub async fn resolve_quarantine(&self, id: i64) -> Result<()> {
+        const QUERY: &str = r#"
+            UPDATE app_quarantine
+            SET resolved_at = now(), attempts = attempts + 1, updated_at = now()
+            WHERE id = $1
+        "#;
+
+        sqlx::query(QUERY).bind(id).execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    /// 如果 listed_at 比现有的更早, 就更新 app_info.listed_at
+    ///
+    /// 和 trg_update_app_listed_at 一样, 上架时间只会往前改
+    pub async fn lower_listed_at(&self, app_id: &str, listed_at: DateTime<Local>) -> Result<bool> {
+        const QUERY: &str = r#"
+            UPDATE app_info
+            SET listed_at = $2
+            WHERE app_id = $1 AND listed_at > $2
+        "#;
+
+        let result = sqlx::query(QUERY)
+            .bind(app_id)
+            .bind(listed_at)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 重新处理依然失败, 记录新的错误
+    pub async fn fail_quarantine(&self, id: i64, error: &str) -> Result<()> {
+        const QUERY: &str = r#"
+            UPDATE app_quarantine
+            SET error = $2, attempts = attempts + 1, updated_at = now()
+            WHERE id = $1
+        "#;
+
+        sqlx::query(QUERY)
+            .bind(id)
+            .bind(error)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// 新建一个猜测任务, 返回任务
+    pub async fn insert_guess_scan(&self, job: &GuessJob, total: Option<u64>) -> Result<GuessScan> {
+        const QUERY: &str = r#"
+            INSERT INTO guess_scan (mode, definition, total)
+            VALUES ($1, $2, $3)
+            RETURNING *
+        "#;
+
+        let scan = sqlx::query_as::<_, GuessScan>(QUERY)
+            .bind(job.scan.name())
+            .bind(serde_json::to_value(job)?)
+            .bind(total.map(|total| total as i64))
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(scan)
+    }
+
+    /// 保存猜测任务的进度
+    pub async fn update_guess_scan_progress(&self, scan: &GuessScan) -> Result<()> {
+        const QUERY: &str = r#"
+            UPDATE guess_scan
+            SET next_offset = $2, hits = $3, requests = $4, errors = $5,
+                skipped = $6, active_seconds = $7, updated_at = now()
+            WHERE id = $1
+        "#;
+
+        sqlx::query(QUERY)
+            .bind(scan.id)
+            .bind(scan.next_offset)
+            .bind(scan.hits)
+            .bind(scan.requests)
+            .bind(scan.errors)
+            .bind(scan.skipped)
+            .bind(scan.active_seconds)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// 更新猜测任务的状态, 结束状态会同时记录结束时间
+    pub async fn set_guess_scan_status(&self, id: i64, status: &str) -> Result<()> {
+        const QUERY: &str = r#"
+            UPDATE guess_scan
+            SET status = $2,
+                updated_at = now(),
+                finished_at = CASE WHEN $2 = 'running' THEN NULL ELSE now() END
+            WHERE id = $1
+        "#;
+
+        sqlx::query(QUERY)
+            .bind(id)
+            .bind(status)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// 记录猜测任务里请求出错的 ID, 已经记过的增加出错次数
+    pub async fn add_guess_retry(&self, scan_id: i64, queries: &[AppQuery]) -> Result<()> {
+        const QUERY: &str = r#"
+            INSERT INTO guess_retry (scan_id, query_key, query_value)
+            SELECT $1, query_key, query_value
+            FROM UNNEST($2::text[], $3::text[]) AS t(query_key, query_value)
+            ON CONFLICT (scan_id, query_key, query_value) DO UPDATE SET
+                attempts = guess_retry.attempts + 1,
+                updated_at = now()
+        "#;
+
+        sqlx::query(QUERY)
+            .bind(scan_id)
+            .bind(queries.iter().map(|q| q.app_db_name()).collect::<Vec<_>>())
+            .bind(queries.iter().map(|q| q.name()).collect::<Vec<_>>())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// 删掉已经重试成功的 ID
+    pub async fn delete_guess_retry(&self, scan_id: i64, queries: &[AppQuery]) -> Result<()> {
+        const QUERY: &str = r#"
+            DELETE FROM guess_retry AS r
+            USING UNNEST($2::text[], $3::text[]) AS t(query_key, query_value)
+            WHERE r.scan_id = $1 AND r.query_key = t.query_key AND r.query_value = t.query_value
+        "#;
+
+        sqlx::query(QUERY)
+            .bind(scan_id)
+            .bind(queries.iter().map(|q| q.app_db_name()).collect::<Vec<_>>())
+            .bind(queries.iter().map(|q| q.name()).collect::<Vec<_>>())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// 保存密度模型里每个桶的猜测记录
+    pub async fn upsert_guess_density(
+        &self,
+        bucket_size: u64,
+        stats: &[BucketStats],
+    ) -> Result<()> {
+        const QUERY: &str = r#"
+            INSERT INTO guess_density (prefix, bucket_size, bucket, probes, hits, scanned_to)
+            SELECT prefix, $1, bucket, probes, hits, scanned_to
+            FROM UNNEST($2::text[], $3::bigint[], $4::bigint[], $5::bigint[], $6::bigint[])
+                AS t(prefix, bucket, probes, hits, scanned_to)
+            ON CONFLICT (prefix, bucket_size, bucket) DO UPDATE SET
+                probes = EXCLUDED.probes,
+                hits = EXCLUDED.hits,
+                scanned_to = EXCLUDED.scanned_to,
+                updated_at = now()
+        "#;
+
+        sqlx::query(QUERY)
+            .bind(bucket_size as i64)
+            .bind(stats.iter().map(|s| s.prefix.clone()).collect::<Vec<_>>())
+            .bind(stats.iter().map(|s| s.bucket).collect::<Vec<_>>())
+            .bind(stats.iter().map(|s| s.probes).collect::<Vec<_>>())
+            .bind(stats.iter().map(|s| s.hits).collect::<Vec<_>>())
+            .bind(stats.iter().map(|s| s.scanned_to).collect::<Vec<_>>())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// 保存不存在的 ID 区间
+    ///
+    /// 和重叠的旧区间合并成一个, 猜测时间取最早的. 内存里只有复查时间之内的区间,
+    /// 所以不能直接用新区间替换, 否则没加载的旧区间会被缩小
+    pub async fn replace_guess_negative(&self, ranges: &[NegativeRange]) -> Result<()> {
+        const MERGE: &str = r#"
+            WITH old AS (
+                DELETE FROM guess_negative
+                WHERE query_key = $1 AND prefix = $2
+                  AND range_start <= $4 AND range_end >= $3
+                RETURNING range_start, range_end, probed_at
+            )
+            INSERT INTO guess_negative (query_key, prefix, range_start, range_end, probed_at)
+            SELECT $1, $2,
+                   LEAST($3, MIN(old.range_start)),
+                   GREATEST($4, MAX(old.range_end)),
+                   LEAST($5, MIN(old.probed_at))
+            FROM old
+        "#;
+
+        let mut tx = self.pool.begin().await?;
+        for range in ranges {
+            sqlx::query(MERGE)
+                .bind(&range.query_key)
+                .bind(&range.prefix)
+                .bind(range.range_start)
+                .bind(range.range_end)
+                .bind(range.probed_at)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
 }
@@ -0,0 +1,348 @@
+//! 数据库迁移
+//!
+//! `assets/sql/migrations/NNN_name/up.sql` 在编译时嵌入程序, 按版本号顺序执行,
+//! 执行过的记录在 `schema_migrations` 表里. 空数据库直接执行 `main.sql`,
+//! 然后把所有迁移记为已执行
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local};
+use sha2::{Digest, Sha256};
+use sqlx::{Executor, FromRow, PgConnection};
+use tracing::{Level, event};
+
+use super::Database;
+
+/// 并发执行迁移时用的 advisory lock, 随便取的一个固定值
+const MIGRATION_LOCK_ID: i64 = 0x6d61_726b_6574;
+
+const MAIN_SQL: &str = include_str!("../../assets/sql/main.sql");
+
+const CREATE_MIGRATIONS_TABLE: &str = "CREATE TABLE IF NOT EXISTS schema_migrations (
+    version         INTEGER PRIMARY KEY,                -- 迁移版本号（目录名的 NNN）
+    name            TEXT NOT NULL,                      -- 迁移目录名
+    checksum        TEXT NOT NULL,                      -- up.sql 的 sha256
+    applied_at      TIMESTAMPTZ NOT NULL DEFAULT now()  -- 执行时间
+)";
+
+/// 一个嵌入的迁移
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub version: i32,
+    /// 目录名, 比如 `007_add_pkg_name`
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+impl Migration {
+    pub fn checksum(&self) -> String {
+        format!("{:x}", Sha256::digest(self.sql.as_bytes()))
+    }
+}
+
+macro_rules! migration {
+    ($version:literal, $name:literal) => {
+        Migration {
+            version: $version,
+            name: $name,
+            sql: include_str!(concat!("../../assets/sql/migrations/", $name, "/up.sql")),
+        }
+    };
+}
+
+/// 所有迁移, 按版本号排列, 新的迁移加在最后
+pub const MIGRATIONS: &[Migration] = &[
+    migration!(1, "001_add_star"),
+    migration!(2, "002_split_tables"),
+    migration!(3, "003_remove_duplicate_app_raw"),
+    migration!(4, "004_add_created_at_to_app_info"),
+    migration!(5, "005_set_not_null_constraints"),
+    migration!(6, "006_add_comment_and_listed_at_to_app_info"),
+    migration!(7, "007_add_pkg_name"),
+    migration!(8, "008_add_device_and_country_fields"),
+    migration!(9, "009_spilt_app_raw"),
+    migration!(10, "010_add_substance"),
+    migration!(11, "011_add_content_hash"),
+    migration!(12, "012_add_schema_drift"),
+    migration!(13, "013_add_quarantine"),
+    migration!(14, "014_add_guess_scan"),
+    migration!(15, "015_add_guess_density"),
+    migration!(16, "016_add_guess_negative"),
+    migration!(17, "017_add_atomic_service"),
+    migration!(18, "018_add_imported_metrics"),
+    migration!(19, "019_add_app_source"),
+    migration!(20, "020_add_data_history_delta"),
+    migration!(21, "021_add_metrics_rollup"),
+    migration!(22, "022_add_app_daily_stats"),
+    migration!(23, "023_add_app_rating_created_at"),
+    migration!(24, "024_add_app_search"),
+    migration!(25, "025_add_guess_retry"),
+];
+
+/// 程序需要的数据库版本
+pub fn latest_version() -> i32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+/// schema_migrations 里的一行
+#[derive(Debug, Clone, FromRow)]
+pub struct AppliedMigration {
+    pub version: i32,
+    pub name: String,
+    pub checksum: String,
+    pub applied_at: DateTime<Local>,
+}
+
+/// 数据库的迁移状态
+#[derive(Debug)]
+pub struct MigrationStatus {
+    /// None 表示没有 schema_migrations 表
+    pub applied: Option<Vec<AppliedMigration>>,
+    /// 有没有 app_info 表, 用来区分空数据库和以前手动迁移的数据库
+    pub has_tables: bool,
+}
+
+impl MigrationStatus {
+    pub fn version(&self) -> Option<i32> {
+        self.applied
+            .as_ref()
+            .map(|applied| applied.iter().map(|m| m.version).max().unwrap_or(0))
+    }
+
+    /// 还没执行的迁移
+    pub fn pending(&self) -> Vec<&'static Migration> {
+        let applied = self.applied.as_deref().unwrap_or_default();
+        MIGRATIONS
+            .iter()
+            .filter(|m| !applied.iter().any(|a| a.version == m.version))
+            .collect()
+    }
+
+    /// 执行过, 但是和程序里的 up.sql 不一样的迁移
+    pub fn modified(&self) -> Vec<&AppliedMigration> {
+        let applied = self.applied.as_deref().unwrap_or_default();
+        applied
+            .iter()
+            .filter(|a| {
+                MIGRATIONS
+                    .iter()
+                    .any(|m| m.version == a.version && m.checksum() != a.checksum)
+            })
+            .collect()
+    }
+}
+
+impl Database {
+    async fn table_exists(&self, table: &str) -> Result<bool> {
+        let exists: bool = sqlx::query_scalar("SELECT to_regclass($1) IS NOT NULL")
+            .bind(table)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(exists)
+    }
+
+    /// 读取迁移状态
+    pub async fn migration_status(&self) -> Result<MigrationStatus> {
+        let has_tables = self.table_exists("app_info").await?;
+        let applied = if self.table_exists("schema_migrations").await? {
+            Some(
+                sqlx::query_as::<_, AppliedMigration>(
+                    "SELECT version, name, checksum, applied_at FROM schema_migrations ORDER BY version",
+                )
+                .fetch_all(&self.pool)
+                .await?,
+            )
+        } else {
+            None
+        };
+        Ok(MigrationStatus {
+            applied,
+            has_tables,
+        })
+    }
+
+    /// 检查数据库版本, 比程序需要的旧就报错
+    pub async fn check_schema_version(&self) -> Result<()> {
+        let status = self.migration_status().await?;
+        let latest = latest_version();
+        // 表是刚建的, 还没有记录, 和没有表一样
+        match status.version().filter(|version| *version > 0) {
+            None if status.has_tables => bail!(
+                "数据库没有版本记录, 请先运行 `migrate baseline <已经手动执行到的版本>`, 再运行 `migrate`"
+            ),
+            None => bail!("数据库是空的, 请先运行 `migrate` 创建表"),
+            Some(version) if version < latest || !status.pending().is_empty() => {
+                let pending = status
+                    .pending()
+                    .iter()
+                    .map(|m| m.name)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                bail!(
+                    "数据库版本 {version} 低于程序需要的 {latest}, 请先运行 `migrate`, 待执行: {pending}"
+                )
+            }
+            Some(version) if version > latest => {
+                event!(
+                    Level::WARN,
+                    "数据库版本 {version} 比程序的 {latest} 新, 可能需要更新程序"
+                );
+            }
+            Some(_) => {}
+        }
+        for modified in status.modified() {
+            event!(
+                Level::WARN,
+                "已执行的迁移 {} 和程序里的 up.sql 不一样",
+                modified.name
+            );
+        }
+        Ok(())
+    }
+
+    /// 执行所有待执行的迁移, 返回执行了的迁移
+    ///
+    /// 空数据库执行 main.sql 并把所有迁移记为已执行.
+    /// 每个迁移在一个事务里执行, 用 advisory lock 防止多个进程同时迁移
+    pub async fn migrate(&self) -> Result<Vec<&'static Migration>> {
+        sqlx::query(CREATE_MIGRATIONS_TABLE)
+            .execute(&self.pool)
+            .await?;
+
+        let status = self.migration_status().await?;
+        if status.version() == Some(0) && !status.has_tables {
+            self.init_schema().await?;
+            return Ok(MIGRATIONS.iter().collect());
+        }
+        if status.version() == Some(0) {
+            bail!(
+                "数据库已经有表但是没有版本记录, 请先运行 `migrate baseline <已经手动执行到的版本>`"
+            );
+        }
+
+        let mut applied = Vec::new();
+        for migration in status.pending() {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query("SELECT pg_advisory_xact_lock($1)")
+                .bind(MIGRATION_LOCK_ID)
+                .execute(&mut *tx)
+                .await?;
+            // 拿到锁之前可能已经被别的进程执行了
+            let done: bool = sqlx::query_scalar(
+                "SELECT EXISTS (SELECT 1 FROM schema_migrations WHERE version = $1)",
+            )
+            .bind(migration.version)
+            .fetch_one(&mut *tx)
+            .await?;
+            if done {
+                continue;
+            }
+
+            event!(Level::INFO, "执行迁移 {}", migration.name);
+            tx.execute(migration.sql)
+                .await
+                .with_context(|| format!("执行迁移 {} 失败, 已回滚", migration.name))?;
+            record_migration(&mut tx, migration).await?;
+            tx.commit().await?;
+            applied.push(migration);
+        }
+        Ok(applied)
+    }
+
+    /// 空数据库: 执行 main.sql, 记录所有迁移
+    async fn init_schema(&self) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(MIGRATION_LOCK_ID)
+            .execute(&mut *tx)
+            .await?;
+        let exists: bool = sqlx::query_scalar("SELECT to_regclass('app_info') IS NOT NULL")
+            .fetch_one(&mut *tx)
+            .await?;
+        if exists {
+            // 别的进程已经建好了
+            return Ok(());
+        }
+        event!(Level::INFO, "空数据库, 执行 main.sql");
+        tx.execute(MAIN_SQL)
+            .await
+            .with_context(|| "执行 main.sql 失败, 已回滚")?;
+        for migration in MIGRATIONS {
+            record_migration(&mut tx, migration).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// 把 `version` 及之前的迁移记为已执行, 不执行 SQL
+    ///
+    /// 用于以前手动执行迁移的数据库, 返回新记录的迁移
+    pub async fn baseline(&self, version: i32) -> Result<Vec<&'static Migration>> {
+        if !MIGRATIONS.iter().any(|m| m.version == version) {
+            bail!("没有版本为 {version} 的迁移, 最新的是 {}", latest_version());
+        }
+        sqlx::query(CREATE_MIGRATIONS_TABLE)
+            .execute(&self.pool)
+            .await?;
+        let mut tx = self.pool.begin().await?;
+        let mut recorded = Vec::new();
+        for migration in MIGRATIONS {
+            if migration.version > version {
+                break;
+            }
+            if record_migration(&mut tx, migration).await? {
+                recorded.push(migration);
+            }
+        }
+        tx.commit().await?;
+        Ok(recorded)
+    }
+}
+
+/// 写入 schema_migrations, 已经有了就跳过, 返回是否新写入
+async fn record_migration(conn: &mut PgConnection, migration: &Migration) -> Result<bool> {
+    let result = sqlx::query(
+        "INSERT INTO schema_migrations (version, name, checksum) VALUES ($1, $2, $3)
+         ON CONFLICT (version) DO NOTHING",
+    )
+    .bind(migration.version)
+    .bind(migration.name)
+    .bind(migration.checksum())
+    .execute(conn)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, i as i32 + 1);
+            assert!(
+                migration
+                    .name
+                    .starts_with(&format!("{:03}_", migration.version)),
+                "{} 的目录名和版本号对不上",
+                migration.name
+            );
+            assert!(!migration.sql.trim().is_empty());
+        }
+        assert_eq!(latest_version(), MIGRATIONS.len() as i32);
+    }
+
+    #[test]
+    fn test_all_migrations_registered() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/sql/migrations");
+        let mut dirs: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+        dirs.sort();
+        let registered: Vec<&str> = MIGRATIONS.iter().map(|m| m.name).collect();
+        assert_eq!(dirs, registered, "迁移目录没有全部注册到 MIGRATIONS");
+    }
+}
@@ -1,20 +1,32 @@
-use crate::model::{AppInfo, AppMetric, AppQuery, AppRating, RawJsonData, RawRatingData};
+use crate::model::{AppInfo, AppMetric, AppRating, RawJsonData, RawRatingData};
+use crate::sync::drift::GLOBAL_DRIFT_DETECTOR;
 use crate::sync::substance::SubstanceData;
+use crate::utils::json_content_hash;
+
+use std::collections::HashMap;
 
 use anyhow::Result;
 use chrono::{DateTime, Local};
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
+use tracing::{Level, event};
 
 use sqlx::{
     FromRow,
     postgres::{PgPool, PgPoolOptions},
 };
 
+pub mod daily_stats;
+pub mod filter;
+pub mod growth;
+pub mod history;
 pub mod insert;
+pub mod migrate;
 pub mod query;
 pub mod read_data;
+pub mod rollup;
+pub mod search;
 
 /// 分页查询结果
 #[derive(Debug, Deserialize, Serialize)]
@@ -31,14 +43,6 @@ pub struct Database {
     pub pool: PgPool,
 }
 
-#[derive(Debug, Clone)]
-pub struct DbSearch {
-    pub key: String,
-    pub value: String,
-    pub is_exact: bool,
-    pub not_null: bool,
-}
-
 #[derive(Debug, FromRow, serde::Deserialize, serde::Serialize)]
 pub struct AppCounts {
     pub total: i64,
@@ -46,45 +50,48 @@ pub struct AppCounts {
     pub atomic_services: i64,
 }
 
-impl DbSearch {
-    pub fn new(key: String, value: String, is_exact: bool, not_null: bool) -> Self {
-        Self {
-            key,
-            value,
-            is_exact,
-            not_null,
-        }
-    }
-    pub fn exact(key: String, value: String, not_null: bool) -> Self {
-        Self {
-            key,
-            value,
-            is_exact: true,
-            not_null,
-        }
-    }
-    pub fn fuzzy(key: String, value: String, not_null: bool) -> Self {
-        Self {
-            key,
-            value,
-            is_exact: false,
-            not_null,
-        }
-    }
-    /// exact: 不动
-    /// not exact: %value%
-    pub fn search_value(&self) -> String {
-        if self.is_exact {
-            self.value.clone()
-        } else {
-            format!("%{}%", self.value)
-        }
-    }
+/// [`Database::save_app_data_batch`] 里的一个应用
+#[derive(Debug, Clone)]
+pub struct AppDataItem<'a> {
+    pub data: &'a (RawJsonData, JsonValue),
+    pub rating: Option<&'a RawRatingData>,
+    /// 上架时间, 有的话覆盖 app_info.listed_at
+    pub listed_at: Option<DateTime<Local>>,
+    pub comment: Option<JsonValue>,
+}
+
+/// 一个应用在各个表里最新一行的内容哈希
+///
+/// 由 [`Database::get_content_hashes`] 一次查询全部取出
+#[derive(Debug, Clone, Default, FromRow)]
+pub struct ContentHashes {
+    pub app_id: String,
+    pub app_exists: bool,
+    pub info_hash: Option<String>,
+    /// 不在 info_hash 里, 调用方给了新的值也要更新 app_info
+    pub listed_at: Option<DateTime<Local>>,
+    pub comment: Option<JsonValue>,
+    pub metric_hash: Option<String>,
+    /// 有 data history 记录, 但是最新一条可能是迁移前的, 没有哈希
+    pub has_data_history: bool,
+    pub data_hash: Option<String>,
+    /// 有 rating history 记录, 但是最新一条可能是迁移前的, 没有哈希
+    pub has_rating_history: bool,
+    pub rating_hash: Option<String>,
 }
 
 impl Database {
-    /// 创建数据库连接池
+    /// 创建数据库连接池, 并检查数据库版本
+    ///
+    /// 数据库比程序需要的旧时报错, 需要先运行 `migrate`
     pub async fn new(database_url: &str, max_connect: u32) -> Result<Self> {
+        let db = Self::connect(database_url, max_connect).await?;
+        db.check_schema_version().await?;
+        Ok(db)
+    }
+
+    /// 只创建数据库连接池, 不检查版本, 给迁移用
+    pub async fn connect(database_url: &str, max_connect: u32) -> Result<Self> {
         let pool = PgPoolOptions::new()
             .max_connections(max_connect)
             .connect(database_url)
@@ -95,6 +102,8 @@ impl Database {
 
     /// 保存应用数据到数据库
     /// 返回布尔值表示是否插入了新数据
+    ///
+    /// 就是只有一个应用的 [`Database::save_app_data_batch`]
     pub async fn save_app_data(
         &self,
         raw_data: &(RawJsonData, JsonValue),
@@ -102,86 +111,176 @@ impl Database {
         listed_at: Option<DateTime<Local>>,
         comment: Option<JsonValue>,
     ) -> Result<(bool, bool, bool)> {
-        // 转换原始JSON数据用于比较
-        let (raw_data, raw_value) = raw_data;
-        let app_id = raw_data.app_id.clone();
-        let query = AppQuery::app_id(&app_id);
-        let exists = self.app_exists(&query).await;
-        let insert_data = if exists && self.is_same_data(&query, raw_value).await {
-            (false, false)
-        } else {
-            let mut app_info: AppInfo = raw_data.into();
-            app_info.comment = comment;
-            if let Some(listed_at) = listed_at {
-                app_info.listed_at = listed_at;
-            }
+        let item = AppDataItem {
+            data: raw_data,
+            rating: raw_rating,
+            listed_at,
+            comment,
+        };
+        Ok(self
+            .save_app_data_batch(std::slice::from_ref(&item))
+            .await?
+            .pop()
+            .unwrap_or_default())
+    }
+
+    /// 批量保存应用数据, 返回值和 `items` 一一对应, 表示 (app info, metric, 评分) 是否插入了新数据
+    ///
+    /// 一次查询拿到所有应用的最新哈希, 变化了的用多行 INSERT 写入, 整批在一个事务里,
+    /// 中途出错全部回滚. 事务开始时按 app_id 加 advisory lock,
+    /// 同一个应用同时提交两次时, 后一次会等前一次提交之后再比较, 不会重复插入.
+    /// 同一批里同一个应用出现多次时只保存最后一次
+    pub async fn save_app_data_batch(
+        &self,
+        items: &[AppDataItem<'_>],
+    ) -> Result<Vec<(bool, bool, bool)>> {
+        let mut results = vec![(false, false, false); items.len()];
+        if items.is_empty() {
+            return Ok(results);
+        }
+        let last_index: HashMap<&str, usize> = items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| (item.data.0.app_id.as_str(), i))
+            .collect();
+        // 排好序再加锁, 两批互相等待时不会死锁
+        let mut app_ids: Vec<String> = last_index.keys().map(|id| id.to_string()).collect();
+        app_ids.sort();
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "SELECT pg_advisory_xact_lock(hashtextextended(id, 0)) FROM unnest($1::text[]) AS id",
+        )
+        .bind(&app_ids)
+        .execute(&mut *tx)
+        .await?;
+        let hashes: HashMap<String, ContentHashes> = Self::get_content_hashes(&mut tx, &app_ids)
+            .await?
+            .into_iter()
+            .map(|hashes| (hashes.app_id.clone(), hashes))
+            .collect();
+
+        let mut app_infos = Vec::new();
+        let mut app_metrics = Vec::new();
+        let mut app_ratings = Vec::new();
+        let mut data_histories = Vec::new();
+        let rating_values: Vec<Option<JsonValue>> = items
+            .iter()
+            .map(|item| item.rating.map(|r| serde_json::to_value(r).unwrap()))
+            .collect();
+        let mut rating_histories = Vec::new();
 
-            // 转换并保存应用信息
-            let info_new = if self.is_same_app_info(&query, &app_info).await {
+        for (i, item) in items.iter().enumerate() {
+            // 转换原始JSON数据用于比较
+            let (raw_data, raw_value) = item.data;
+            let app_id = raw_data.app_id.as_str();
+            if last_index[app_id] != i {
+                continue;
+            }
+            let hashes = hashes.get(app_id).cloned().unwrap_or_default();
+            let data_hash = json_content_hash(raw_value);
+            let same_data = if !hashes.app_exists {
                 false
+            } else if let Some(last_hash) = &hashes.data_hash {
+                *last_hash == data_hash
+            } else if hashes.has_data_history {
+                // 迁移前的记录没有哈希, 退回完整比较一次, 顺便把哈希补上
+                let same = Self::is_same_data(&mut tx, app_id, raw_value).await?;
+                if same {
+                    Self::fill_last_data_hash(&mut tx, app_id, &data_hash).await?;
+                }
+                same
             } else {
-                self.insert_app_info(&app_info).await?;
+                false
+            };
+            if !same_data {
+                let mut app_info: AppInfo = raw_data.into();
+                app_info.comment = item.comment.clone();
+                if let Some(listed_at) = item.listed_at {
+                    app_info.listed_at = listed_at;
+                }
+
+                // 转换并保存应用信息
+                results[i].0 = hashes.info_hash.as_deref() != Some(&app_info.content_hash())
+                    || item.listed_at.is_some_and(|t| hashes.listed_at != Some(t))
+                    || item
+                        .comment
+                        .as_ref()
+                        .is_some_and(|c| hashes.comment.as_ref() != Some(c));
+                if results[i].0 {
+                    app_infos.push(app_info);
+                }
+
+                // 保存指标信息
+                let app_metric = AppMetric::from_raw_data(raw_data);
+                results[i].1 = hashes.metric_hash.as_deref() != Some(&app_metric.content_hash());
+                if results[i].1 {
+                    app_metrics.push(app_metric);
+                }
+                if results[i].0 || results[i].1 {
+                    data_histories.push((app_id, raw_value));
+                }
+            }
+
+            // 保存评分信息（如果有）
+            if let (Some(raw_star), Some(value)) = (item.rating, &rating_values[i]) {
+                let rating_hash = json_content_hash(value);
+                let same_rating = if let Some(last_hash) = &hashes.rating_hash {
+                    *last_hash == rating_hash
+                } else if hashes.has_rating_history {
+                    let same = Self::is_same_rating(&mut tx, app_id, value).await?;
+                    if same {
+                        Self::fill_last_rating_hash(&mut tx, app_id, &rating_hash).await?;
+                    }
+                    same
+                } else {
+                    false
+                };
+                if !same_rating {
+                    app_ratings.push(AppRating::from_raw_star(raw_data, raw_star));
+                    rating_histories.push((app_id, value));
+                    results[i].2 = true;
+                }
+            }
+        }
+
+        // app_info 要先写, 其他表的 pkg_name 从 app_info 里取
+        Self::insert_app_infos(&mut tx, &app_infos).await?;
+        Self::insert_app_metrics(&mut tx, &app_metrics).await?;
+        Self::insert_app_ratings(&mut tx, &app_ratings).await?;
+        Self::insert_rating_histories(&mut tx, &rating_histories).await?;
+        Self::insert_data_histories(&mut tx, &data_histories).await?;
+        tx.commit().await?;
+
+        // 提交成功之后再输出
+        for (item, inserted) in items.iter().zip(&results) {
+            let (app_id, name) = (&item.data.0.app_id, &item.data.0.name);
+            if inserted.0 {
                 println!(
                     "{}",
-                    format!("插入新的 app info {} ({})", app_id, app_info.name).bright_green()
+                    format!("插入新的 app info {app_id} ({name})").bright_green()
                 );
-                true
-            };
-
-            // 保存指标信息
-            let app_metric = AppMetric::from_raw_data(raw_data);
-            let metric_new = if self.is_same_app_metric(&query, &app_metric).await {
-                false
-            } else {
-                self.insert_app_metric(&app_metric).await?;
+            }
+            if inserted.1 {
                 println!(
                     "{}",
-                    format!(
-                        "插入新的 app metric {} ({})",
-                        app_metric.app_id, raw_data.name
-                    )
-                    .bright_green()
+                    format!("插入新的 app metric {app_id} ({name})").bright_green()
                 );
-                true
-            };
-            (info_new, metric_new)
-        };
-        // 保存评分信息（如果有）
-        let insert_rate = if let Some(raw_star) = raw_rating {
-            let value = serde_json::to_value(raw_star).unwrap();
-            if self.is_same_rating(&query, &value).await {
-                false
-            } else {
-                let app_rating = AppRating::from_raw_star(raw_data, raw_star);
+            }
+            if inserted.2 {
                 println!(
                     "{}",
-                    format!("更新评分数据 {} ({})", app_id, raw_data.name).bright_green()
+                    format!("更新评分数据 {app_id} ({name})").bright_green()
                 );
-                self.insert_app_rating(&app_rating).await?;
-                self.insert_rating_history(&app_id, &value).await?;
-                true
             }
-        } else {
-            false
-        };
-        if insert_data.0 || insert_data.1 {
-            self.insert_data_history(&app_id, raw_value).await?
         }
 
-        // if insert_data.0 || insert_data.1 || insert_rate {
-        //     // 保存原始JSON数据
-        //     self.insert_raw_data(
-        //         &app_id,
-        //         raw_value,
-        //         raw_rating.map(|r| serde_json::to_value(r).unwrap()),
-        //     )
-        //     .await?;
-        //     println!(
-        //         "{}",
-        //         format!("应用数据保存成功: {} ({})", app_id, raw_data.name).green()
-        //     );
-        // }
-        Ok((insert_data.0, insert_data.1, insert_rate))
+        // 顺便把检测到的数据结构变化写进去
+        if let Err(e) = GLOBAL_DRIFT_DETECTOR.flush(self).await {
+            event!(Level::WARN, "保存数据结构变化失败: {e:#}");
+        }
+
+        Ok(results)
     }
 
     /// 保存 substance 数据到数据库
@@ -1,14 +1,23 @@
 use anyhow::Result;
 use chrono::{DateTime, Local};
 use serde_json::Value;
-use sqlx::{Row, postgres::PgRow};
+use sqlx::{PgConnection, Postgres, QueryBuilder, Row, postgres::PgRow};
 use std::ops::Range;
 
+use crate::db::filter::AppFilter;
 use crate::db::read_data::{
     SELECT_APP_INFO_FIELDS, SELECT_APP_METRIC_FIELDS, SELECT_APP_RATING_FIELDS,
 };
-use crate::db::{AppCounts, Database, DbSearch, PaginatedAppInfo};
-use crate::model::{AppInfo, AppMetric, AppQuery, AppRating, FullAppInfo, ShortAppRating};
+use crate::db::{AppCounts, ContentHashes, Database, PaginatedAppInfo};
+use crate::model::atomic::ATOMIC_SERVICE_SORT_KEYS;
+use crate::model::{
+    AppInfo, AppMetric, AppQuery, AppRating, AtomicService, FullAppInfo, ShortAppRating,
+};
+use crate::sync::drift::{DriftKind, SchemaDrift};
+use crate::sync::guess::density::BucketStats;
+use crate::sync::guess::negative::NegativeRange;
+use crate::sync::guess::scan::{GuessScan, GuessStrategyStats, hits_per_thousand};
+use crate::sync::quarantine::QuarantineEntry;
 
 impl Database {
     fn read_full_app_data_from_row(row: &PgRow) -> FullAppInfo {
@@ -91,24 +100,10 @@ impl Database {
 
     /// 获取指定应用的最后一条原始JSON数据
     pub async fn get_last_raw_json_data(&self, app: &AppQuery) -> Option<Value> {
-        let query = format!(
-            r#"
-                SELECT raw_json_data
-                FROM app_data_history
-                WHERE {} = $1
-                ORDER BY created_at DESC
-                LIMIT 1
-            "#,
-            app.app_db_name()
-        );
-
-        let result = sqlx::query(&query)
-            .bind(app.name())
-            .fetch_optional(&self.pool)
+        let mut conn = self.pool.acquire().await.ok()?;
+        Self::get_last_data(&mut conn, app.app_db_name(), app.name())
             .await
-            .ok()?;
-
-        result.and_then(|r| r.try_get("raw_json_data").ok())
+            .ok()?
     }
 
     /// 获取指定应用的 created_at 时间
@@ -172,20 +167,120 @@ impl Database {
         Self::read_app_rating_from_row(&result)
     }
 
-    /// 检查新数据是否与最后一条数据相同
-    pub async fn is_same_data(&self, app: &AppQuery, new_data: &Value) -> bool {
-        self.get_last_raw_json_data(app)
-            .await
-            .map(|last_data| last_data == *new_data)
-            .unwrap_or(false)
+    /// 检查新数据是否与最后一条数据相同, 在 save_app_data_batch 的事务里调用
+    pub async fn is_same_data(
+        conn: &mut PgConnection,
+        app_id: &str,
+        new_data: &Value,
+    ) -> Result<bool> {
+        let last = Self::get_last_data(conn, "app_id", app_id).await?;
+        Ok(last.is_some_and(|last| last == *new_data))
     }
 
-    /// 检查评分是否相同
-    pub async fn is_same_rating(&self, app: &AppQuery, new_rating: &Value) -> bool {
-        self.get_last_raw_json_star(app)
-            .await
-            .map(|last_rating| last_rating == *new_rating)
-            .unwrap_or(false)
+    /// 检查评分是否相同, 在 save_app_data_batch 的事务里调用
+    pub async fn is_same_rating(
+        conn: &mut PgConnection,
+        app_id: &str,
+        new_rating: &Value,
+    ) -> Result<bool> {
+        const QUERY: &str = r#"
+            SELECT raw_json_rating FROM app_rating_history
+            WHERE app_id = $1
+            ORDER BY created_at DESC LIMIT 1
+        "#;
+
+        let last: Option<Value> = sqlx::query_scalar(QUERY)
+            .bind(app_id)
+            .fetch_optional(conn)
+            .await?;
+        Ok(last.is_some_and(|last| last == *new_rating))
+    }
+
+    /// 批量获取应用在各个表里最新一行的内容哈希, 在 save_app_data_batch 的事务里调用
+    ///
+    /// 每个传入的 app_id 都会有一行结果 (即使应用不存在)
+    pub async fn get_content_hashes(
+        conn: &mut PgConnection,
+        app_ids: &[String],
+    ) -> Result<Vec<ContentHashes>> {
+        const QUERY: &str = r#"
+            SELECT
+                q.app_id,
+                ai.app_id IS NOT NULL AS app_exists,
+                ai.content_hash AS info_hash,
+                ai.listed_at,
+                ai.comment,
+                am.content_hash AS metric_hash,
+                dh.id IS NOT NULL AS has_data_history,
+                dh.content_hash AS data_hash,
+                rh.id IS NOT NULL AS has_rating_history,
+                rh.content_hash AS rating_hash
+            FROM unnest($1::text[]) AS q(app_id)
+            LEFT JOIN app_info ai ON ai.app_id = q.app_id
+            LEFT JOIN LATERAL (
+                SELECT content_hash FROM app_metrics
+                WHERE app_id = q.app_id AND imported_from IS NULL
+                ORDER BY created_at DESC LIMIT 1
+            ) am ON TRUE
+            LEFT JOIN LATERAL (
+                SELECT id, content_hash FROM app_data_history
+                WHERE app_id = q.app_id
+                ORDER BY created_at DESC LIMIT 1
+            ) dh ON TRUE
+            LEFT JOIN LATERAL (
+                SELECT id, content_hash FROM app_rating_history
+                WHERE app_id = q.app_id
+                ORDER BY created_at DESC LIMIT 1
+            ) rh ON TRUE
+        "#;
+
+        Ok(sqlx::query_as(QUERY).bind(app_ids).fetch_all(conn).await?)
+    }
+
+    /// 给最新一条没有哈希的 data history 补上哈希
+    pub async fn fill_last_data_hash(
+        conn: &mut PgConnection,
+        app_id: &str,
+        hash: &str,
+    ) -> Result<()> {
+        const QUERY: &str = r#"
+            UPDATE app_data_history SET content_hash = $2
+            WHERE id = (
+                SELECT id FROM app_data_history
+                WHERE app_id = $1
+                ORDER BY created_at DESC LIMIT 1
+            ) AND content_hash IS NULL
+        "#;
+
+        sqlx::query(QUERY)
+            .bind(app_id)
+            .bind(hash)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// 给最新一条没有哈希的 rating history 补上哈希
+    pub async fn fill_last_rating_hash(
+        conn: &mut PgConnection,
+        app_id: &str,
+        hash: &str,
+    ) -> Result<()> {
+        const QUERY: &str = r#"
+            UPDATE app_rating_history SET content_hash = $2
+            WHERE id = (
+                SELECT id FROM app_rating_history
+                WHERE app_id = $1
+                ORDER BY created_at DESC LIMIT 1
+            ) AND content_hash IS NULL
+        "#;
+
+        sqlx::query(QUERY)
+            .bind(app_id)
+            .bind(hash)
+            .execute(conn)
+            .await?;
+        Ok(())
     }
 
     /// 实际检查 app info 是否相同
@@ -241,6 +336,31 @@ impl Database {
         }
     }
 
+    /// 获取 app 的全部 metric, 按时间从早到晚, 包括导入的
+    pub async fn get_app_metric_history(&self, app_id: &str) -> Result<Vec<AppMetric>> {
+        let query = format!(
+            "SELECT {} FROM app_metrics WHERE app_id = $1 ORDER BY created_at",
+            SELECT_APP_METRIC_FIELDS
+        );
+        let rows = sqlx::query(&query)
+            .bind(app_id)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.iter().map(Self::read_app_metric_from_row).collect())
+    }
+
+    /// 查询结果的列名和 postgres 类型, 不执行查询
+    pub async fn describe_columns(&self, sql: &str) -> Result<Vec<(String, String)>> {
+        use sqlx::{Column, Executor, TypeInfo};
+
+        let describe = (&self.pool).describe(sql).await?;
+        Ok(describe
+            .columns()
+            .iter()
+            .map(|c| (c.name().to_string(), c.type_info().name().to_string()))
+            .collect())
+    }
+
     /// 获取数据库中所有的 pkg_name
     pub async fn get_all_pkg_names(&self) -> Result<Vec<String>> {
         const QUERY: &str = "SELECT DISTINCT pkg_name FROM app_info WHERE pkg_name IS NOT NULL";
@@ -284,15 +404,16 @@ impl Database {
         Ok(app_ids)
     }
 
-    /// 分页查询 app_info 数据，按照创建时间排序
+    /// 分页查询 app_info 数据，按照 `sort_key` 排序
     ///
     /// # 参数
     /// - `range`: 范围参数，例如 0..10 表示获取前10条记录
+    /// - `filter`: 筛选条件, 见 [`AppFilter`]
     ///
     /// # 示例
     /// ```rust
     /// let db = Database::new("postgres://...", 5).await?;
-    /// let apps = db.get_app_info_paginated(0..10).await?;
+    /// let apps = db.get_app_info_paginated(0..10, "created_at", true, &AppFilter::default()).await?;
     /// println!("获取到 {} 条应用信息", apps.len());
     /// ```
     pub async fn get_app_info_paginated(
@@ -300,76 +421,28 @@ impl Database {
         range: Range<u32>,
         sort_key: &str,
         sort_desc: bool,
-        search: Option<DbSearch>,
+        filter: &AppFilter,
     ) -> Result<Vec<FullAppInfo>> {
         let limit = (range.end - range.start) as i64;
         let offset = range.start as i64;
 
-        let rows = match search {
-            Some(DbSearch {
-                key,
-                value,
-                is_exact,
-                not_null,
-            }) => {
-                let query = format!(
-                    r#"
-                    SELECT {SELECT_APP_INFO_FIELDS}, {}, {}
-                    FROM app_latest_info
-                    WHERE {} ILIKE $1 {}
-                    AND {sort_key} IS NOT NULL
-                    AND app_id != 'C5765880207854862721'
-                    ORDER BY {sort_key} {}
-                    LIMIT $2 OFFSET $3
-                "#,
-                    SELECT_APP_METRIC_FIELDS
-                        .replace("created_at AS metrics_created_at", "metrics_created_at"),
-                    SELECT_APP_RATING_FIELDS
-                        .replace("created_at AS rating_created_at", "rating_created_at"),
-                    key,
-                    if not_null {
-                        format!("AND {sort_key} IS NOT NULL")
-                    } else {
-                        "".to_string()
-                    },
-                    if sort_desc { "DESC" } else { "ASC" }
-                );
-
-                sqlx::query(&query)
-                    .bind(if is_exact {
-                        value
-                    } else {
-                        format!("%{value}%")
-                    })
-                    .bind(limit) // LIMIT
-                    .bind(offset) // OFFSET - 修正了参数顺序
-                    .fetch_all(&self.pool)
-                    .await?
-            }
-            None => {
-                let query = format!(
-                    r#"
-                    SELECT {SELECT_APP_INFO_FIELDS}, {}, {}
-                    FROM app_latest_info
-                    WHERE {sort_key} IS NOT NULL
-                    AND app_id != 'C5765880207854862721'
-                    ORDER BY {sort_key} {}
-                    LIMIT $1 OFFSET $2
-                "#,
-                    SELECT_APP_METRIC_FIELDS
-                        .replace("created_at AS metrics_created_at", "metrics_created_at"),
-                    SELECT_APP_RATING_FIELDS
-                        .replace("created_at AS rating_created_at", "rating_created_at"),
-                    if sort_desc { "DESC" } else { "ASC" }
-                );
-
-                sqlx::query(&query)
-                    .bind(limit) // LIMIT
-                    .bind(offset) // OFFSET - 修正了参数顺序
-                    .fetch_all(&self.pool)
-                    .await?
-            }
-        };
+        let mut builder = QueryBuilder::<Postgres>::new(format!(
+            "SELECT {SELECT_APP_INFO_FIELDS}, {}, {} FROM app_latest_info",
+            SELECT_APP_METRIC_FIELDS
+                .replace("created_at AS metrics_created_at", "metrics_created_at"),
+            SELECT_APP_RATING_FIELDS
+                .replace("created_at AS rating_created_at", "rating_created_at"),
+        ));
+        push_app_list_where(&mut builder, sort_key, filter);
+        builder.push(format_args!(
+            " ORDER BY {sort_key} {} LIMIT ",
+            if sort_desc { "DESC" } else { "ASC" }
+        ));
+        builder.push_bind(limit);
+        builder.push(" OFFSET ");
+        builder.push_bind(offset);
+
+        let rows = builder.build().fetch_all(&self.pool).await?;
 
         let app_infos = rows.iter().map(Self::read_full_app_data_from_row).collect();
         Ok(app_infos)
@@ -425,38 +498,12 @@ impl Database {
         page_size: u32,
         sort_key: &str,
         sort_desc: bool,
-        search: Option<DbSearch>,
+        filter: AppFilter,
     ) -> Result<PaginatedAppInfo<D>> {
-        // 动态统计总数（不依赖 get_app_info_count）
-        let total_count: i64 = match &search {
-            Some(DbSearch {
-                key,
-                value,
-                is_exact,
-                not_null,
-            }) => {
-                let query = format!(
-                    "SELECT COUNT(*) FROM app_latest_info WHERE {} ILIKE $1 {}",
-                    key,
-                    if *not_null { "AND NOT NULL" } else { "" }
-                );
-                let result: (i64,) = sqlx::query_as(&query)
-                    .bind(if *is_exact {
-                        value.clone()
-                    } else {
-                        format!("%{}%", value)
-                    })
-                    .fetch_one(&self.pool)
-                    .await?;
-                result.0
-            }
-            None => {
-                let result: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM app_latest_info")
-                    .fetch_one(&self.pool)
-                    .await?;
-                result.0
-            }
-        };
+        // 动态统计总数（不依赖 get_app_info_count）, 条件和列表查询一样
+        let mut builder = QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM app_latest_info");
+        push_app_list_where(&mut builder, sort_key, &filter);
+        let total_count: i64 = builder.build_query_scalar().fetch_one(&self.pool).await?;
         let total_pages = if page_size == 0 {
             0
         } else {
@@ -465,7 +512,7 @@ impl Database {
         let offset = (page.saturating_sub(1)) * page_size;
 
         let data = self
-            .get_app_info_paginated(offset..(offset + page_size), sort_key, sort_desc, search)
+            .get_app_info_paginated(offset..(offset + page_size), sort_key, sort_desc, &filter)
             .await?
             .into_iter()
             .map(D::from)
@@ -510,45 +557,6 @@ impl Database {
         Ok(count as u32)
     }
 
-    /// 获取指定 pkg_id 的所有 app_metric 信息
-    pub async fn get_app_metrics_by_pkg_id(&self, pkg_id: &str) -> Result<Vec<AppMetric>> {
-        const QUERY: &str = r#"
-            SELECT
-                am.id,
-                am.app_id,
-                am.version,
-                am.version_code,
-                am.size_bytes,
-                am.sha256,
-                am.info_score::text,
-                am.info_rate_count,
-                am.download_count,
-                am.price,
-                am.release_date,
-                am.new_features,
-                am.upgrade_msg,
-                am.target_sdk,
-                am.minsdk,
-                am.compile_sdk_version,
-                am.min_hmos_api_level,
-                am.api_release_type,
-                am.created_at metrics_created_at
-            FROM app_metrics am
-            JOIN app_info ai ON am.app_id = ai.app_id
-            WHERE ai.pkg_name = $1
-            ORDER BY am.created_at DESC
-        "#;
-
-        let rows = sqlx::query(QUERY)
-            .bind(pkg_id)
-            .fetch_all(&self.pool)
-            .await?;
-
-        let app_metrics = rows.iter().map(Self::read_app_metric_from_row).collect();
-
-        Ok(app_metrics)
-    }
-
     /// 获取评分最高的应用排行
     ///
     /// # 参数
@@ -680,125 +688,6 @@ impl Database {
         Ok(app_metrics)
     }
 
-    /// 获取下载量增长最快的应用排行
-    ///
-    /// # 参数
-    /// - `limit`: 返回的应用数量
-    /// - `time_range`: 时间范围（如 "7d" 表示最近7天）
-    ///
-    /// # 示例
-    /// ```rust
-    /// let db = Database::new("postgres://...", 5).await?;
-    /// let growth_apps = db.get_download_growth_apps(10, "7d").await?;
-    /// println!("下载量增长最快的应用: {:?}", growth_apps);
-    /// ```
-    pub async fn get_download_growth_apps(
-        &self,
-        limit: u32,
-        _time_range: &str,
-    ) -> Result<Vec<AppMetric>> {
-        // 简化实现：获取最近有更新的应用，按下载量排序
-        const QUERY: &str = r#"
-            SELECT DISTINCT ON (am.app_id)
-                am.*
-            FROM app_metrics am
-            ORDER BY am.app_id, am.created_at DESC
-            LIMIT $1
-        "#;
-
-        let rows = sqlx::query(QUERY)
-            .bind(limit as i64)
-            .fetch_all(&self.pool)
-            .await?;
-
-        let mut app_metrics = Vec::new();
-        for row in rows {
-            let app_metric = AppMetric {
-                id: row.get("id"),
-                app_id: row.get("app_id"),
-                version: row.get("version"),
-                version_code: row.get("version_code"),
-                size_bytes: row.get("size_bytes"),
-                sha256: row.get("sha256"),
-                info_score: row.get("info_score"),
-                info_rate_count: row.get("info_rate_count"),
-                download_count: row.get("download_count"),
-                price: row.get("price"),
-                release_date: row.get("release_date"),
-                new_features: row.get("new_features"),
-                upgrade_msg: row.get("upgrade_msg"),
-                target_sdk: row.get("target_sdk"),
-                minsdk: row.get("minsdk"),
-                compile_sdk_version: row.get("compile_sdk_version"),
-                min_hmos_api_level: row.get("min_hmos_api_level"),
-                api_release_type: row.get("api_release_type"),
-                created_at: row.get("created_at"),
-            };
-            app_metrics.push(app_metric);
-        }
-
-        // 按下载量排序
-        app_metrics.sort_by(|a, b| b.download_count.cmp(&a.download_count));
-        app_metrics.truncate(limit as usize);
-
-        Ok(app_metrics)
-    }
-
-    /// 获取评分增长最快的应用排行
-    ///
-    /// # 参数
-    /// - `limit`: 返回的应用数量
-    /// - `time_range`: 时间范围（如 "7d" 表示最近7天）
-    ///
-    /// # 示例
-    /// ```rust
-    /// let db = Database::new("postgres://...", 5).await?;
-    /// let rating_growth_apps = db.get_rating_growth_apps(10, "7d").await?;
-    /// println!("评分增长最快的应用: {:?}", rating_growth_apps);
-    /// ```
-    pub async fn get_rating_growth_apps(
-        &self,
-        limit: u32,
-        _time_range: &str,
-    ) -> Result<Vec<ShortAppRating>> {
-        // 简化实现：获取评分最高的应用
-        const QUERY: &str = r#"
-            SELECT
-                ai.app_id,
-                ai.name,
-                ai.pkg_name,
-                ai.developer_name,
-                ai.icon_url,
-                ar.average_rating,
-                ar.total_star_rating_count
-            FROM app_info ai
-            JOIN app_rating ar ON ai.app_id = ar.app_id
-            ORDER BY ar.average_rating DESC, ar.total_star_rating_count DESC
-            LIMIT $1
-        "#;
-
-        let rows = sqlx::query(QUERY)
-            .bind(limit as i64)
-            .fetch_all(&self.pool)
-            .await?;
-
-        let mut app_ratings = Vec::new();
-        for row in rows {
-            let app_rating = ShortAppRating {
-                app_id: row.get("app_id"),
-                name: row.get("name"),
-                pkg_name: row.get("pkg_name"),
-                developer_name: row.get("developer_name"),
-                icon_url: row.get("icon_url"),
-                average_rating: row.get("average_rating"),
-                total_star_rating_count: row.get("total_star_rating_count"),
-            };
-            app_ratings.push(app_rating);
-        }
-
-        Ok(app_ratings)
-    }
-
     /// 获取开发者排行（按发布应用数量）
     ///
     /// # 参数
@@ -966,4 +855,233 @@ impl Database {
 
         Ok(device_codes)
     }
+
+    /// 获取所有已记录的数据结构 (source, key, value_type)
+    pub async fn get_schema_profiles(&self) -> Result<Vec<(String, String, String)>> {
+        const QUERY: &str = "SELECT source, key, value_type FROM schema_profile";
+
+        Ok(sqlx::query_as(QUERY).fetch_all(&self.pool).await?)
+    }
+
+    /// 获取最近的数据结构变化
+    ///
+    /// # 参数
+    /// - `source`: 只看某个来源 (appinfo / page-detail)
+    /// - `limit`: 返回的数量
+    pub async fn get_schema_drifts(
+        &self,
+        source: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<SchemaDrift>> {
+        const QUERY: &str = r#"
+            SELECT id, source, key, change, old_type, new_type, example_app_id, created_at
+            FROM schema_drift
+            WHERE $1::text IS NULL OR source = $1
+            ORDER BY created_at DESC
+            LIMIT $2
+        "#;
+
+        let rows = sqlx::query(QUERY)
+            .bind(source)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let drifts = rows
+            .iter()
+            .filter_map(|row| {
+                Some(SchemaDrift {
+                    id: row.get("id"),
+                    source: row.get("source"),
+                    key: row.get("key"),
+                    change: DriftKind::from_db(row.get("change"))?,
+                    old_type: row.get("old_type"),
+                    new_type: row.get("new_type"),
+                    example_app_id: row.get("example_app_id"),
+                    created_at: row.get("created_at"),
+                })
+            })
+            .collect();
+
+        Ok(drifts)
+    }
+
+    /// 获取未处理的隔离记录
+    pub async fn get_unresolved_quarantine(&self) -> Result<Vec<QuarantineEntry>> {
+        const QUERY: &str = r#"
+            SELECT id, query_key, query_value, raw_json_data, error, attempts,
+                   resolved_at, created_at, updated_at
+            FROM app_quarantine
+            WHERE resolved_at IS NULL
+            ORDER BY created_at
+        "#;
+
+        let entries = sqlx::query_as::<_, QuarantineEntry>(QUERY)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(entries)
+    }
+
+    /// 获取一个猜测任务
+    pub async fn get_guess_scan(&self, id: i64) -> Result<Option<GuessScan>> {
+        const QUERY: &str = "SELECT * FROM guess_scan WHERE id = $1";
+
+        let scan = sqlx::query_as::<_, GuessScan>(QUERY)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(scan)
+    }
+
+    /// 获取猜测任务列表, 可以按状态过滤
+    pub async fn get_guess_scans(&self, status: Option<&str>) -> Result<Vec<GuessScan>> {
+        const QUERY: &str = r#"
+            SELECT * FROM guess_scan
+            WHERE $1::text IS NULL OR status = $1
+            ORDER BY updated_at DESC
+        "#;
+
+        let scans = sqlx::query_as::<_, GuessScan>(QUERY)
+            .bind(status)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(scans)
+    }
+
+    /// 获取猜测任务里等待重试的 ID
+    pub async fn get_guess_retry(&self, scan_id: i64) -> Result<Vec<AppQuery>> {
+        const QUERY: &str = r#"
+            SELECT query_key, query_value FROM guess_retry
+            WHERE scan_id = $1
+            ORDER BY created_at
+        "#;
+
+        let rows: Vec<(String, String)> = sqlx::query_as(QUERY)
+            .bind(scan_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .iter()
+            .filter_map(|(key, value)| AppQuery::from_db_name(key, value))
+            .collect())
+    }
+
+    /// 获取某个桶大小下所有桶的猜测记录
+    pub async fn get_guess_density(&self, bucket_size: u64) -> Result<Vec<BucketStats>> {
+        const QUERY: &str = r#"
+            SELECT prefix, bucket, probes, hits, scanned_to
+            FROM guess_density
+            WHERE bucket_size = $1
+        "#;
+
+        let stats = sqlx::query_as::<_, BucketStats>(QUERY)
+            .bind(bucket_size as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(stats)
+    }
+
+    /// 按猜测方式汇总命中率
+    pub async fn get_guess_strategy_stats(&self) -> Result<Vec<GuessStrategyStats>> {
+        const QUERY: &str = r#"
+            SELECT mode,
+                   COUNT(*) AS scans,
+                   COALESCE(SUM(requests), 0)::BIGINT AS requests,
+                   COALESCE(SUM(hits), 0)::BIGINT AS hits,
+                   COALESCE(SUM(errors), 0)::BIGINT AS errors
+            FROM guess_scan
+            GROUP BY mode
+        "#;
+
+        let mut stats = sqlx::query_as::<_, GuessStrategyStats>(QUERY)
+            .fetch_all(&self.pool)
+            .await?;
+        for s in stats.iter_mut() {
+            s.hits_per_thousand = hits_per_thousand(s.hits, s.requests);
+        }
+        stats.sort_by(|a, b| b.hits_per_thousand.total_cmp(&a.hits_per_thousand));
+
+        Ok(stats)
+    }
+
+    /// 获取 since 之后确认不存在的 ID 区间
+    pub async fn get_guess_negative(&self, since: DateTime<Local>) -> Result<Vec<NegativeRange>> {
+        const QUERY: &str = r#"
+            SELECT query_key, prefix, range_start, range_end, probed_at
+            FROM guess_negative
+            WHERE probed_at >= $1
+        "#;
+
+        let ranges = sqlx::query_as::<_, NegativeRange>(QUERY)
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(ranges)
+    }
+
+    /// 分页获取元服务
+    ///
+    /// sort_key 不在 [`ATOMIC_SERVICE_SORT_KEYS`] 里的按 listed_at 排序,
+    /// search 会在名称, 包名和开发者名称里模糊搜索
+    pub async fn get_atomic_services(
+        &self,
+        range: Range<u32>,
+        sort_key: &str,
+        sort_desc: bool,
+        search: Option<&str>,
+    ) -> Result<(Vec<AtomicService>, i64)> {
+        let sort_key = if ATOMIC_SERVICE_SORT_KEYS.contains(&sort_key) {
+            sort_key
+        } else {
+            "listed_at"
+        };
+        let pattern = search.map(|s| format!("%{s}%"));
+        let query = format!(
+            r#"
+            SELECT *
+            FROM atomic_service_latest
+            WHERE ($1::text IS NULL OR name ILIKE $1 OR pkg_name ILIKE $1 OR developer_name ILIKE $1)
+            ORDER BY {sort_key} {} NULLS LAST, app_id
+            LIMIT $2 OFFSET $3
+            "#,
+            if sort_desc { "DESC" } else { "ASC" }
+        );
+        let services = sqlx::query_as::<_, AtomicService>(&query)
+            .bind(&pattern)
+            .bind((range.end - range.start) as i64)
+            .bind(range.start as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let total: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*)
+            FROM atomic_service_latest
+            WHERE ($1::text IS NULL OR name ILIKE $1 OR pkg_name ILIKE $1 OR developer_name ILIKE $1)
+            "#,
+        )
+        .bind(&pattern)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok((services, total))
+    }
+}
+
+/// 应用列表和总数共用的条件: 排序字段不为空, 加上筛选条件
+fn push_app_list_where(
+    builder: &mut QueryBuilder<'_, Postgres>,
+    sort_key: &str,
+    filter: &AppFilter,
+) {
+    builder.push(format_args!(
+        " WHERE {sort_key} IS NOT NULL AND app_id != 'C5765880207854862721' AND "
+    ));
+    filter.push_sql(builder);
 }
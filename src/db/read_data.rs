@@ -24,7 +24,7 @@ pub const SELECT_APP_METRIC_FIELDS: &str = r#"
     download_count, price::text, release_date,
     new_features, upgrade_msg, target_sdk,
     minsdk, compile_sdk_version, min_hmos_api_level,
-    api_release_type, created_at AS metrics_created_at
+    api_release_type, imported_from, created_at AS metrics_created_at
 "#;
 
 pub const SELECT_APP_RATING_FIELDS: &str = r#"
@@ -110,6 +110,7 @@ impl Database {
             compile_sdk_version: row.get("compile_sdk_version"),
             min_hmos_api_level: row.get("min_hmos_api_level"),
             api_release_type: row.get("api_release_type"),
+            imported_from: row.try_get("imported_from").unwrap_or(None),
             created_at: row.get("metrics_created_at"),
         }
     }
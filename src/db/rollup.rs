@@ -0,0 +1,460 @@
+//! app_metrics 的小时 / 天汇总和保留策略
+//!
+//! 汇总按 app_metrics.id 增量进行, `metrics_rollup_state` 记录汇总到了哪一条.
+//! 为了不漏掉还没提交的事务里的数据, 每次只汇总到上一次运行时看到的最大 id,
+//! 所以汇总会落后一个周期, 读取的时候还没汇总的数据直接用原始数据补上
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use tracing::{Level, event};
+
+use crate::config::RetentionConfig;
+use crate::db::Database;
+use crate::model::AppMetric;
+
+/// 查询范围不超过这么多天时用原始数据
+pub const RAW_MAX_SPAN_DAYS: i64 = 7;
+/// 查询范围不超过这么多天时用小时汇总, 再长就用天汇总
+pub const HOURLY_MAX_SPAN_DAYS: i64 = 90;
+
+/// 一次汇总这么多条原始数据 (按 id)
+const ROLLUP_BATCH: i64 = 50_000;
+/// 一次删除这么多条原始数据
+const PRUNE_BATCH: i64 = 10_000;
+
+const STATE_NAME: &str = "app_metrics";
+
+/// 汇总表里保存的时间段内最后一条原始数据的列
+const LAST_COLUMNS: &[&str] = &[
+    "version",
+    "version_code",
+    "size_bytes",
+    "sha256",
+    "info_score",
+    "info_rate_count",
+    "download_count",
+    "price",
+    "release_date",
+    "new_features",
+    "upgrade_msg",
+    "target_sdk",
+    "minsdk",
+    "compile_sdk_version",
+    "min_hmos_api_level",
+    "api_release_type",
+    "imported_from",
+];
+
+/// 指标历史的精度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricResolution {
+    Raw,
+    Hourly,
+    Daily,
+}
+
+impl MetricResolution {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MetricResolution::Raw => "raw",
+            MetricResolution::Hourly => "hourly",
+            MetricResolution::Daily => "daily",
+        }
+    }
+
+    fn table(&self) -> &'static str {
+        match self {
+            MetricResolution::Raw => "app_metrics",
+            MetricResolution::Hourly => "app_metrics_hourly",
+            MetricResolution::Daily => "app_metrics_daily",
+        }
+    }
+
+    /// date_trunc 的单位
+    fn unit(&self) -> &'static str {
+        match self {
+            MetricResolution::Raw => "second",
+            MetricResolution::Hourly => "hour",
+            MetricResolution::Daily => "day",
+        }
+    }
+
+    fn retention_days(&self, policy: &RetentionConfig) -> Option<u32> {
+        match self {
+            MetricResolution::Raw => policy.raw_days,
+            MetricResolution::Hourly => policy.hourly_days,
+            MetricResolution::Daily => None,
+        }
+    }
+
+    /// `since` 之后这个精度的数据是不是完整的 (还没被保留策略删掉)
+    fn covers(
+        &self,
+        policy: &RetentionConfig,
+        since: DateTime<Local>,
+        now: DateTime<Local>,
+    ) -> bool {
+        self.retention_days(policy)
+            .is_none_or(|days| since >= now - Duration::days(days as i64))
+    }
+
+    /// 按查询范围选精度: 范围越长精度越低, 也不能用已经过了保留期的精度
+    pub fn for_range(
+        since: DateTime<Local>,
+        until: DateTime<Local>,
+        now: DateTime<Local>,
+        policy: &RetentionConfig,
+    ) -> Self {
+        let span = until - since;
+        if span <= Duration::days(RAW_MAX_SPAN_DAYS)
+            && MetricResolution::Raw.covers(policy, since, now)
+        {
+            MetricResolution::Raw
+        } else if span <= Duration::days(HOURLY_MAX_SPAN_DAYS)
+            && MetricResolution::Hourly.covers(policy, since, now)
+        {
+            MetricResolution::Hourly
+        } else {
+            MetricResolution::Daily
+        }
+    }
+}
+
+/// 读出来的列, 和 [`Database::read_app_metric_from_row`] 对应
+fn metric_columns() -> String {
+    LAST_COLUMNS
+        .iter()
+        .map(|column| match *column {
+            "info_score" | "price" => format!("{column}::text"),
+            column => column.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// 把 `$1 < id <= $2` 的原始数据合并进汇总表
+///
+/// 已经有的时间段累加条数, 合并下载量范围, 最后一条数据取时间更晚的
+fn rollup_sql(resolution: MetricResolution) -> String {
+    let table = resolution.table();
+    let unit = resolution.unit();
+    let columns = LAST_COLUMNS.join(", ");
+    let last_columns = LAST_COLUMNS
+        .iter()
+        .map(|column| format!("l.{column}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let updates = ["last_metric_id", "last_at"]
+        .iter()
+        .chain(LAST_COLUMNS)
+        .map(|column| {
+            format!(
+                "{column} = CASE WHEN EXCLUDED.last_at >= {table}.last_at \
+                 THEN EXCLUDED.{column} ELSE {table}.{column} END"
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",\n                ");
+    format!(
+        r#"
+            WITH src AS (
+                SELECT *, date_trunc('{unit}', created_at) AS bucket
+                FROM app_metrics WHERE id > $1 AND id <= $2
+            ), agg AS (
+                SELECT app_id, bucket, count(*) AS samples,
+                    min(download_count) AS download_min, max(download_count) AS download_max
+                FROM src GROUP BY app_id, bucket
+            ), last AS (
+                SELECT DISTINCT ON (app_id, bucket) * FROM src
+                ORDER BY app_id, bucket, created_at DESC, id DESC
+            )
+            INSERT INTO {table} (app_id, bucket, samples, download_min, download_max,
+                last_metric_id, last_at, {columns})
+            SELECT a.app_id, a.bucket, a.samples, a.download_min, a.download_max,
+                l.id, l.created_at, {last_columns}
+            FROM agg a JOIN last l ON l.app_id = a.app_id AND l.bucket = a.bucket
+            ON CONFLICT (app_id, bucket) DO UPDATE SET
+                samples = {table}.samples + EXCLUDED.samples,
+                download_min = LEAST({table}.download_min, EXCLUDED.download_min),
+                download_max = GREATEST({table}.download_max, EXCLUDED.download_max),
+                {updates}
+        "#
+    )
+}
+
+/// [`Database::maintain_metrics`] 的统计
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RollupReport {
+    /// 汇总了的原始数据 id 范围
+    pub from_id: i64,
+    pub to_id: i64,
+    /// 新增或者更新的汇总行
+    pub hourly: u64,
+    pub daily: u64,
+    /// 按保留策略删除的行
+    pub pruned_raw: u64,
+    pub pruned_hourly: u64,
+}
+
+impl Database {
+    /// 把新的原始数据合并进小时 / 天汇总
+    pub async fn rollup_metrics(&self, report: &mut RollupReport) -> Result<()> {
+        sqlx::query("INSERT INTO metrics_rollup_state (name) VALUES ($1) ON CONFLICT DO NOTHING")
+            .bind(STATE_NAME)
+            .execute(&self.pool)
+            .await?;
+        let (last_id, seen_id): (i64, Option<i64>) = sqlx::query_as(
+            "SELECT last_metric_id, seen_metric_id FROM metrics_rollup_state WHERE name = $1",
+        )
+        .bind(STATE_NAME)
+        .fetch_one(&self.pool)
+        .await?;
+        let max_id: i64 = sqlx::query_scalar("SELECT coalesce(max(id), 0) FROM app_metrics")
+            .fetch_one(&self.pool)
+            .await?;
+        // 第一次运行没有上次的记录, 直接汇总到现在
+        let to_id = seen_id.unwrap_or(max_id).max(last_id);
+        report.from_id = last_id;
+        report.to_id = to_id;
+
+        let hourly_sql = rollup_sql(MetricResolution::Hourly);
+        let daily_sql = rollup_sql(MetricResolution::Daily);
+        loop {
+            let mut tx = self.pool.begin().await?;
+            // 锁住进度, 两个进程同时汇总时不会重复累加
+            let last_id: i64 = sqlx::query_scalar(
+                "SELECT last_metric_id FROM metrics_rollup_state WHERE name = $1 FOR UPDATE",
+            )
+            .bind(STATE_NAME)
+            .fetch_one(&mut *tx)
+            .await?;
+            if last_id >= to_id {
+                break;
+            }
+            let end_id = (last_id + ROLLUP_BATCH).min(to_id);
+            report.hourly += sqlx::query(&hourly_sql)
+                .bind(last_id)
+                .bind(end_id)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            report.daily += sqlx::query(&daily_sql)
+                .bind(last_id)
+                .bind(end_id)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            sqlx::query(
+                "UPDATE metrics_rollup_state SET last_metric_id = $2, updated_at = now() WHERE name = $1",
+            )
+            .bind(STATE_NAME)
+            .bind(end_id)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+        }
+
+        sqlx::query("UPDATE metrics_rollup_state SET seen_metric_id = $2 WHERE name = $1")
+            .bind(STATE_NAME)
+            .bind(max_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// 按保留策略删除过期的原始数据和小时汇总
+    ///
+    /// 只删除已经汇总过的原始数据, 每个应用最新的一条 (变更检测要用) 和导入的数据不删
+    pub async fn prune_metrics(
+        &self,
+        policy: &RetentionConfig,
+        report: &mut RollupReport,
+    ) -> Result<()> {
+        const PRUNE_RAW: &str = r#"
+            DELETE FROM app_metrics WHERE id IN (
+                SELECT m.id FROM app_metrics m
+                WHERE m.created_at < now() - make_interval(days => $1)
+                    AND m.imported_from IS NULL
+                    AND m.id <= (SELECT last_metric_id FROM metrics_rollup_state WHERE name = $2)
+                    AND EXISTS (
+                        SELECT 1 FROM app_metrics n
+                        WHERE n.app_id = m.app_id AND n.imported_from IS NULL
+                            AND n.created_at > m.created_at
+                    )
+                LIMIT $3
+            )
+        "#;
+
+        if let Some(days) = policy.raw_days {
+            loop {
+                let deleted = sqlx::query(PRUNE_RAW)
+                    .bind(days as i32)
+                    .bind(STATE_NAME)
+                    .bind(PRUNE_BATCH)
+                    .execute(&self.pool)
+                    .await?
+                    .rows_affected();
+                report.pruned_raw += deleted;
+                if deleted < PRUNE_BATCH as u64 {
+                    break;
+                }
+            }
+        }
+        if let Some(days) = policy.hourly_days {
+            report.pruned_hourly = sqlx::query(
+                "DELETE FROM app_metrics_hourly WHERE bucket < now() - make_interval(days => $1)",
+            )
+            .bind(days as i32)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        }
+        Ok(())
+    }
+
+    /// 汇总并清理, 由 worker 定时调用
+    pub async fn maintain_metrics(&self, policy: &RetentionConfig) -> Result<RollupReport> {
+        let mut report = RollupReport::default();
+        self.rollup_metrics(&mut report).await?;
+        self.prune_metrics(policy, &mut report).await?;
+        event!(
+            Level::INFO,
+            "指标汇总完成: id {}..{}, 小时汇总 {} 行, 天汇总 {} 行, 删除原始数据 {} 行, 删除小时汇总 {} 行",
+            report.from_id,
+            report.to_id,
+            report.hourly,
+            report.daily,
+            report.pruned_raw,
+            report.pruned_hourly
+        );
+        Ok(report)
+    }
+
+    /// 获取应用的指标历史, 从新到旧
+    ///
+    /// `since` 不填就是从第一条数据开始, `until` 不填就是现在. `resolution` 不填时按范围自动选择,
+    /// 见 [`MetricResolution::for_range`]. 返回实际使用的精度
+    pub async fn get_app_metric_series(
+        &self,
+        pkg_name: &str,
+        since: Option<DateTime<Local>>,
+        until: Option<DateTime<Local>>,
+        resolution: Option<MetricResolution>,
+        policy: &RetentionConfig,
+    ) -> Result<(MetricResolution, Vec<AppMetric>)> {
+        let now = Local::now();
+        let app_id: Option<String> =
+            sqlx::query_scalar("SELECT app_id FROM app_info WHERE pkg_name = $1")
+                .bind(pkg_name)
+                .fetch_optional(&self.pool)
+                .await?;
+        let Some(app_id) = app_id else {
+            return Ok((resolution.unwrap_or(MetricResolution::Raw), Vec::new()));
+        };
+        let since = match since {
+            Some(since) => since,
+            None => sqlx::query_scalar::<_, Option<DateTime<Local>>>(
+                "SELECT LEAST(
+                    (SELECT min(created_at) FROM app_metrics WHERE app_id = $1),
+                    (SELECT min(bucket) FROM app_metrics_daily WHERE app_id = $1)
+                )",
+            )
+            .bind(&app_id)
+            .fetch_one(&self.pool)
+            .await?
+            .unwrap_or(now),
+        };
+        let until = until.unwrap_or(now);
+        let resolution =
+            resolution.unwrap_or_else(|| MetricResolution::for_range(since, until, now, policy));
+
+        let columns = metric_columns();
+        let raw = format!(
+            "SELECT id, app_id, {columns}, created_at AS metrics_created_at FROM app_metrics \
+             WHERE app_id = $1 AND created_at >= $2 AND created_at <= $3"
+        );
+        let query = match resolution {
+            MetricResolution::Raw => format!("{raw} ORDER BY metrics_created_at DESC"),
+            MetricResolution::Hourly | MetricResolution::Daily => {
+                let (table, unit) = (resolution.table(), resolution.unit());
+                format!(
+                    "SELECT last_metric_id AS id, app_id, {columns}, last_at AS metrics_created_at \
+                     FROM {table} \
+                     WHERE app_id = $1 AND bucket >= date_trunc('{unit}', $2::timestamptz) AND bucket <= $3 \
+                     UNION ALL {raw} AND id > (\
+                        SELECT coalesce(max(last_metric_id), 0) FROM metrics_rollup_state \
+                        WHERE name = '{STATE_NAME}'\
+                     ) \
+                     ORDER BY metrics_created_at DESC"
+                )
+            }
+        };
+        let rows = sqlx::query(&query)
+            .bind(&app_id)
+            .bind(since)
+            .bind(until)
+            .fetch_all(&self.pool)
+            .await?;
+        let metrics = rows.iter().map(Self::read_app_metric_from_row).collect();
+        Ok((resolution, metrics))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(raw_days: Option<u32>, hourly_days: Option<u32>) -> RetentionConfig {
+        RetentionConfig {
+            raw_days,
+            hourly_days,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_resolution_for_range() {
+        let now = Local::now();
+        let days = |n: i64| now - Duration::days(n);
+        let keep_all = policy(None, None);
+        assert_eq!(
+            MetricResolution::for_range(days(3), now, now, &keep_all),
+            MetricResolution::Raw
+        );
+        assert_eq!(
+            MetricResolution::for_range(days(30), now, now, &keep_all),
+            MetricResolution::Hourly
+        );
+        assert_eq!(
+            MetricResolution::for_range(days(365), now, now, &keep_all),
+            MetricResolution::Daily
+        );
+
+        // 范围很短, 但是原始数据已经删了
+        let retention = policy(Some(30), Some(180));
+        assert_eq!(
+            MetricResolution::for_range(days(60), days(58), now, &retention),
+            MetricResolution::Hourly
+        );
+        assert_eq!(
+            MetricResolution::for_range(days(200), days(199), now, &retention),
+            MetricResolution::Daily
+        );
+        assert_eq!(
+            MetricResolution::for_range(days(2), days(1), now, &retention),
+            MetricResolution::Raw
+        );
+    }
+
+    #[test]
+    fn test_rollup_sql() {
+        let sql = rollup_sql(MetricResolution::Daily);
+        assert!(sql.contains("date_trunc('day', created_at)"));
+        assert!(sql.contains("INSERT INTO app_metrics_daily"));
+        for column in LAST_COLUMNS {
+            assert!(sql.contains(&format!("{column} = CASE WHEN")));
+        }
+    }
+}
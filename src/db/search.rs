@@ -0,0 +1,386 @@
+//! 应用全文搜索
+//!
+//! 在 app_info.search_document (见迁移 024_add_app_search) 上用 GIN 索引搜索,
+//! 按 `ts_rank_cd` 排序. 中文按相邻两个字切分, [`search_tokens`] 和数据库里的
+//! `search_tokens` 函数切分规则一致. 高亮在程序里做, 匹配的文字用 `<mark>` 包起来
+
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::FromRow;
+
+use crate::db::{Database, PaginatedAppInfo};
+
+/// 每页最多返回的数量
+pub const SEARCH_MAX_PAGE_SIZE: u32 = 100;
+
+/// 详细描述的摘要长度 (字符数)
+const SNIPPET_CHARS: usize = 80;
+
+/// 和数据库里 `search_tokens` 的正则一致
+fn is_cjk(c: char) -> bool {
+    matches!(c, '\u{3400}'..='\u{4dbf}' | '\u{4e00}'..='\u{9fff}' | '\u{f900}'..='\u{faff}')
+}
+
+/// 把文字切成连续的汉字和连续的英文数字, 英文转成小写, 其他字符都是分隔符
+pub fn split_terms(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut current = String::new();
+    let mut current_cjk = false;
+    for c in text.chars().map(|c| c.to_ascii_lowercase()) {
+        let cjk = is_cjk(c);
+        if !cjk && !c.is_ascii_alphanumeric() {
+            if !current.is_empty() {
+                terms.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        if !current.is_empty() && cjk != current_cjk {
+            terms.push(std::mem::take(&mut current));
+        }
+        current_cjk = cjk;
+        current.push(c);
+    }
+    if !current.is_empty() {
+        terms.push(current);
+    }
+    terms
+}
+
+/// 一个词切分成的 token: 汉字是相邻两个字加上最后一个字, 英文数字是整个词
+fn term_tokens(term: &str) -> Vec<String> {
+    let chars: Vec<char> = term.chars().collect();
+    if chars.len() < 2 || !is_cjk(chars[0]) {
+        return vec![term.to_string()];
+    }
+    let mut tokens: Vec<String> = chars.windows(2).map(|w| w.iter().collect()).collect();
+    tokens.push(chars[chars.len() - 1].to_string());
+    tokens
+}
+
+/// 和数据库里 `search_tokens` 一样切分
+pub fn search_tokens(text: &str) -> Vec<String> {
+    split_terms(text)
+        .into_iter()
+        .flat_map(|term| term_tokens(&term))
+        .collect()
+}
+
+/// 把搜索词转成 `to_tsquery('simple', ..)` 的参数, 没有可以搜索的文字返回 None
+///
+/// 每个词都要匹配. 汉字的最后一个字只作为单独的字出现在 token 里, 所以搜索时只用相邻两个字;
+/// 只有一个字的时候和英文一样用前缀匹配. token 里只有字母数字和汉字, 不需要转义
+pub fn to_tsquery(query: &str) -> Option<String> {
+    let mut tokens: Vec<String> = Vec::new();
+    for term in split_terms(query) {
+        let chars: Vec<char> = term.chars().collect();
+        let term_tokens = if chars.len() >= 2 && is_cjk(chars[0]) {
+            chars.windows(2).map(|w| w.iter().collect()).collect()
+        } else {
+            vec![format!("{term}:*")]
+        };
+        for token in term_tokens {
+            if !tokens.contains(&token) {
+                tokens.push(token);
+            }
+        }
+    }
+    (!tokens.is_empty()).then(|| tokens.join(" & "))
+}
+
+fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// `text` 里匹配搜索词的位置 (按字符), 已经合并了重叠的部分
+///
+/// 汉字的词没有连在一起出现的话, 退回到匹配相邻两个字
+fn match_ranges(text: &[char], terms: &[String]) -> Vec<(usize, usize)> {
+    let find_all = |needle: &[char], ranges: &mut Vec<(usize, usize)>| {
+        if needle.is_empty() || needle.len() > text.len() {
+            return false;
+        }
+        let before = ranges.len();
+        for start in 0..=text.len() - needle.len() {
+            if text[start..start + needle.len()] == *needle {
+                ranges.push((start, start + needle.len()));
+            }
+        }
+        ranges.len() > before
+    };
+
+    let mut ranges = Vec::new();
+    for term in terms {
+        let needle: Vec<char> = term.chars().collect();
+        if !find_all(needle.as_slice(), &mut ranges) && needle.len() > 2 && is_cjk(needle[0]) {
+            for pair in needle.windows(2) {
+                find_all(pair, &mut ranges);
+            }
+        }
+    }
+    ranges.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// 把 `text` 里匹配 `terms` (见 [`split_terms`]) 的部分用 `<mark>` 包起来, 其他部分转义 HTML
+///
+/// 填了 `max_chars` 就只保留第一个匹配附近的这么多字, 前后省略的部分用 `…` 表示.
+/// 没有匹配返回 None
+pub fn highlight(text: &str, terms: &[String], max_chars: Option<usize>) -> Option<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = chars.iter().map(|c| c.to_ascii_lowercase()).collect();
+    let ranges = match_ranges(&lower, terms);
+    let first = ranges.first()?;
+
+    let (start, end) = match max_chars {
+        Some(max) if chars.len() > max => {
+            let start = first.0.saturating_sub(max / 4).min(chars.len() - max);
+            (start, start + max)
+        }
+        _ => (0, chars.len()),
+    };
+    let mut out = String::new();
+    if start > 0 {
+        out.push('…');
+    }
+    let mut pos = start;
+    for &(range_start, range_end) in &ranges {
+        if range_end <= start || range_start >= end {
+            continue;
+        }
+        let (range_start, range_end) = (range_start.max(start), range_end.min(end));
+        escape_html(
+            &chars[pos..range_start].iter().collect::<String>(),
+            &mut out,
+        );
+        out.push_str("<mark>");
+        escape_html(
+            &chars[range_start..range_end].iter().collect::<String>(),
+            &mut out,
+        );
+        out.push_str("</mark>");
+        pos = range_end;
+    }
+    escape_html(&chars[pos..end].iter().collect::<String>(), &mut out);
+    if end < chars.len() {
+        out.push('…');
+    }
+    Some(out)
+}
+
+#[derive(Debug, FromRow)]
+struct SearchRow {
+    app_id: String,
+    name: String,
+    pkg_name: String,
+    developer_name: String,
+    dev_en_name: Option<String>,
+    icon_url: String,
+    kind_name: String,
+    tag_name: Option<String>,
+    brief_desc: String,
+    description: String,
+    download_count: Option<i64>,
+    average_rating: Option<f64>,
+    rank: f32,
+}
+
+/// 匹配到的字段, 已经转义 HTML 并且用 `<mark>` 标出了匹配的文字, 没有匹配的字段不返回
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SearchHighlight {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub developer_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dev_en_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pkg_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brief_desc: Option<String>,
+    /// 第一个匹配附近的摘要
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// 一条搜索结果
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub app_id: String,
+    pub name: String,
+    pub pkg_name: String,
+    pub developer_name: String,
+    pub icon_url: String,
+    pub kind_name: String,
+    pub tag_name: Option<String>,
+    pub brief_desc: String,
+    pub download_count: Option<i64>,
+    pub average_rating: Option<f64>,
+    /// 相关度, 越大越相关
+    pub rank: f32,
+    pub highlight: SearchHighlight,
+}
+
+impl SearchRow {
+    fn into_hit(self, terms: &[String]) -> SearchHit {
+        let field = |text: &str| highlight(text, terms, None);
+        let highlight = SearchHighlight {
+            name: field(&self.name),
+            developer_name: field(&self.developer_name),
+            dev_en_name: self.dev_en_name.as_deref().and_then(field),
+            pkg_name: field(&self.pkg_name),
+            kind_name: field(&self.kind_name),
+            tag_name: self.tag_name.as_deref().and_then(field),
+            brief_desc: field(&self.brief_desc),
+            description: highlight(&self.description, terms, Some(SNIPPET_CHARS)),
+        };
+        SearchHit {
+            app_id: self.app_id,
+            name: self.name,
+            pkg_name: self.pkg_name,
+            developer_name: self.developer_name,
+            icon_url: self.icon_url,
+            kind_name: self.kind_name,
+            tag_name: self.tag_name,
+            brief_desc: self.brief_desc,
+            download_count: self.download_count,
+            average_rating: self.average_rating,
+            rank: self.rank,
+            highlight,
+        }
+    }
+}
+
+impl Database {
+    /// 在名称、开发者、包名、分类、标签、简介和详细描述里搜索, 按相关度排序
+    ///
+    /// 名称和搜索词完全一样的排在最前面. `tsquery` 由 [`to_tsquery`] 生成, 页码从 1 开始
+    pub async fn search_apps(
+        &self,
+        query: &str,
+        tsquery: &str,
+        page: u32,
+        page_size: u32,
+    ) -> Result<PaginatedAppInfo<SearchHit>> {
+        const COUNT: &str = r#"
+            SELECT count(*) FROM app_info
+            WHERE search_document @@ to_tsquery('simple', $1)
+        "#;
+        const QUERY: &str = r#"
+            SELECT ai.app_id, ai.name, ai.pkg_name, ai.developer_name, ai.dev_en_name,
+                ai.icon_url, ai.kind_name, ai.tag_name, ai.brief_desc, ai.description,
+                m.download_count, r.average_rating::float8 AS average_rating,
+                ts_rank_cd(ai.search_document, q, 1) AS rank
+            FROM app_info ai
+            CROSS JOIN to_tsquery('simple', $1) q
+            LEFT JOIN LATERAL (
+                SELECT download_count FROM app_metrics
+                WHERE app_id = ai.app_id ORDER BY created_at DESC LIMIT 1
+            ) m ON TRUE
+            LEFT JOIN LATERAL (
+                SELECT average_rating FROM app_rating
+                WHERE app_id = ai.app_id ORDER BY created_at DESC LIMIT 1
+            ) r ON TRUE
+            WHERE ai.search_document @@ q
+            ORDER BY lower(ai.name) = lower($2) DESC, rank DESC, m.download_count DESC NULLS LAST,
+                ai.app_id
+            LIMIT $3 OFFSET $4
+        "#;
+
+        let total_count: i64 = sqlx::query_scalar(COUNT)
+            .bind(tsquery)
+            .fetch_one(&self.pool)
+            .await?;
+        let page = page.max(1);
+        let rows: Vec<SearchRow> = sqlx::query_as(QUERY)
+            .bind(tsquery)
+            .bind(query.trim())
+            .bind(page_size as i64)
+            .bind((page - 1) as i64 * page_size as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let terms = split_terms(query);
+        Ok(PaginatedAppInfo {
+            data: rows.into_iter().map(|row| row.into_hit(&terms)).collect(),
+            total_count: total_count as u32,
+            page,
+            page_size,
+            total_pages: (total_count as u32).div_ceil(page_size.max(1)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_tokens() {
+        assert_eq!(split_terms("微信WeChat 8.0"), ["微信", "wechat", "8", "0"]);
+        assert_eq!(
+            search_tokens("应用市场, HarmonyOS"),
+            ["应用", "用市", "市场", "场", "harmonyos"]
+        );
+        assert_eq!(search_tokens("图"), ["图"]);
+        assert!(search_tokens("！？ ——").is_empty());
+    }
+
+    #[test]
+    fn test_to_tsquery() {
+        assert_eq!(
+            to_tsquery("应用市场 Huawei").as_deref(),
+            Some("应用 & 用市 & 市场 & huawei:*")
+        );
+        assert_eq!(to_tsquery("图").as_deref(), Some("图:*"));
+        assert_eq!(to_tsquery("市场市场").as_deref(), Some("市场 & 场市"));
+        assert_eq!(to_tsquery("' & !"), None);
+    }
+
+    #[test]
+    fn test_highlight() {
+        let terms = split_terms("应用市场 huawei");
+        assert_eq!(
+            highlight("华为应用市场 <HUAWEI>", &terms, None).as_deref(),
+            Some("华为<mark>应用市场</mark> &lt;<mark>HUAWEI</mark>&gt;")
+        );
+        // 没有连在一起出现时匹配相邻两个字
+        assert_eq!(
+            highlight("应用和市场", &split_terms("应用市场"), None).as_deref(),
+            Some("<mark>应用</mark>和<mark>市场</mark>")
+        );
+        assert_eq!(highlight("浏览器", &terms, None), None);
+
+        let text = format!("{}应用市场{}", "一".repeat(20), "二".repeat(20));
+        assert_eq!(
+            highlight(&text, &terms, Some(12)).as_deref(),
+            Some(
+                format!(
+                    "…{}<mark>应用市场</mark>{}…",
+                    "一".repeat(3),
+                    "二".repeat(5)
+                )
+                .as_str()
+            )
+        );
+    }
+}
@@ -0,0 +1,102 @@
+pub mod config;
+pub mod db;
+pub mod model;
+pub mod server;
+pub mod sync;
+pub mod utils;
+
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Parser;
+use colored::Colorize;
+
+use crate::sync::export::{Dataset, ExportFormat, ExportOptions, export};
+
+/// 导出数据集给别人分析
+///
+/// 例如: `export metrics --format parquet --since 2025-01-01 --kind 游戏`
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// 要导出的数据集
+    #[arg(value_enum)]
+    dataset: Dataset,
+    /// 导出格式
+    #[arg(long, value_enum, default_value_t = ExportFormat::Jsonl)]
+    format: ExportFormat,
+    /// 输出文件, 默认是 `数据集.格式`, `-` 表示标准输出
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// 开始时间 (包含), 比如 2025-01-01 或者 RFC3339
+    #[arg(long, value_parser = parse_time)]
+    since: Option<chrono::DateTime<chrono::Local>>,
+    /// 结束时间 (不包含)
+    #[arg(long, value_parser = parse_time)]
+    until: Option<chrono::DateTime<chrono::Local>>,
+    /// 应用分类, kind_name 或者 kind_type_name
+    #[arg(long)]
+    kind: Option<String>,
+    /// 开发者名称或者 dev_id
+    #[arg(long)]
+    developer: Option<String>,
+    /// 日志等级, 由 utils::init_log 处理
+    #[arg(short, action = clap::ArgAction::Count, hide = true)]
+    v: u8,
+    #[arg(short, hide = true)]
+    d: bool,
+}
+
+fn parse_time(value: &str) -> Result<chrono::DateTime<chrono::Local>, String> {
+    utils::parse_datetime(value).ok_or_else(|| format!("无法解析时间 {value}"))
+}
+
+fn main() -> anyhow::Result<()> {
+    utils::init_log();
+    let cli = Cli::parse();
+
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(8)
+        .enable_all()
+        .build()
+        .with_context(|| "无法创建 tokio runtime")?;
+
+    rt.block_on(async_main(cli))
+}
+
+async fn async_main(cli: Cli) -> anyhow::Result<()> {
+    let config = config::Config::load().with_context(|| "无法加载配置文件")?;
+    let db = db::Database::new(config.database_url(), config.db_max_connect()).await?;
+
+    let options = ExportOptions {
+        dataset: cli.dataset,
+        format: cli.format,
+        since: cli.since,
+        until: cli.until,
+        kind: cli.kind,
+        developer: cli.developer,
+    };
+    let output = cli
+        .output
+        .unwrap_or_else(|| PathBuf::from(options.file_name()));
+
+    let start = std::time::Instant::now();
+    if output.as_os_str() == "-" {
+        export(&db, &options, &mut tokio::io::stdout()).await?;
+        return Ok(());
+    }
+    let mut file = tokio::fs::File::create(&output)
+        .await
+        .with_context(|| format!("无法写入 {}", output.display()))?;
+    let count = export(&db, &options, &mut file).await?;
+    println!(
+        "{}",
+        format!(
+            "已导出 {count} 行到 {}, 耗时 {:.2?}",
+            output.display(),
+            start.elapsed()
+        )
+        .bright_green()
+    );
+    Ok(())
+}
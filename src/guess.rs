@@ -0,0 +1,101 @@
+pub mod config;
+pub mod db;
+pub mod model;
+pub mod server;
+pub mod sync;
+pub mod utils;
+
+use anyhow::Context;
+use clap::Parser;
+use tracing::{Level, event};
+
+use crate::sync::{
+    code::GLOBAL_CODE_MANAGER,
+    guess::{GuessJob, GuessMode, GuessOptions},
+};
+
+/// 猜测应用 ID
+///
+/// 例如: `guess range --prefix C576588020785 --start 2000000 --end 6390000 --width 7`
+///
+/// 或者: `guess --job assets/guess_job.example.toml`
+///
+/// 中断之后: `guess --resume <任务 ID>`
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// 从 toml 任务文件读取任务, 忽略命令行里的其他参数
+    #[arg(long)]
+    job: Option<String>,
+    /// 继续数据库里已有的猜测任务
+    #[arg(long, conflicts_with = "job")]
+    resume: Option<i64>,
+    #[command(flatten)]
+    options: GuessOptions,
+    #[command(subcommand)]
+    mode: Option<GuessMode>,
+    /// 日志等级, 由 utils::init_log 处理
+    #[arg(short, action = clap::ArgAction::Count, global = true, hide = true)]
+    v: u8,
+    #[arg(short, global = true, hide = true)]
+    d: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    utils::init_log();
+    let cli = Cli::parse();
+
+    let target = match (cli.resume, cli.job, cli.mode) {
+        (Some(id), _, _) => ScanTarget::Resume(id),
+        (None, Some(path), _) => ScanTarget::New(GuessJob::load(&path)?),
+        (None, None, Some(mode)) => {
+            mode.check()?;
+            ScanTarget::New(GuessJob {
+                scan: mode,
+                options: cli.options,
+            })
+        }
+        (None, None, None) => {
+            anyhow::bail!("需要指定猜测方式, --job 任务文件或者 --resume 任务 ID")
+        }
+    };
+
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(8)
+        .enable_all()
+        .build()
+        .with_context(|| "无法创建 tokio runtime")?;
+
+    rt.block_on(async_main(target))
+}
+
+/// 新任务还是继续已有的任务
+enum ScanTarget {
+    New(GuessJob),
+    Resume(i64),
+}
+
+async fn async_main(target: ScanTarget) -> anyhow::Result<()> {
+    let config = config::Config::load().with_context(|| "无法加载配置文件")?;
+
+    let _token = GLOBAL_CODE_MANAGER.update_token().await;
+
+    let db = crate::db::Database::new(config.database_url(), config.db_max_connect()).await?;
+
+    let client = reqwest::ClientBuilder::new()
+        .timeout(std::time::Duration::from_secs(config.api_timeout_seconds()))
+        .build()
+        .with_context(|| "无法创建 Reqwest 客户端")?;
+
+    let scan = match target {
+        ScanTarget::New(job) => {
+            event!(Level::INFO, "开始猜测: {:?}", job);
+            sync::guess::create_scan(&db, job).await?
+        }
+        ScanTarget::Resume(id) => db
+            .get_guess_scan(id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("找不到猜测任务 {id}"))?,
+    };
+    sync::guess::run_scan(&client, &db, config, scan).await
+}
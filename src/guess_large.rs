@@ -1,135 +0,0 @@
-use anyhow::Context;
-use colored::Colorize;
-
-use crate::{model::AppQuery, sync::code::GLOBAL_CODE_MANAGER};
-
-pub mod config;
-pub mod db;
-pub mod model;
-pub mod server;
-pub mod sync;
-pub mod utils;
-
-fn main() -> anyhow::Result<()> {
-    utils::init_log();
-    let rt = tokio::runtime::Builder::new_multi_thread()
-        .worker_threads(8)
-        .enable_all()
-        .build()
-        .with_context(|| "无法创建 tokio runtime")?;
-
-    rt.block_on(async_main())
-}
-
-async fn async_main() -> anyhow::Result<()> {
-    // 加载配置
-    let config = config::Config::load().with_context(|| "无法加载配置文件")?;
-
-    // C576588020785 6374145
-    // C576588020785 6366961
-    // C576588020785 2915863
-    // C576588020785 2866435
-    // 6917566197931927039
-
-    // let range = 2915863..=6366961;
-    // let range = 0..=6366961;
-    // let range = 2860000..=6390000;
-    // let range = 0000000..=9999999;
-    // let start = "C576588020785";
-    let scan_range = 1000_0000;
-    let middle = 6917584511757810835_u64;
-    let range = middle - scan_range..middle + scan_range;
-
-    let _token = GLOBAL_CODE_MANAGER.update_token().await;
-
-    let db = crate::db::Database::new(config.database_url(), config.db_max_connect()).await?;
-
-    let batch = 1000;
-    let wait_time = std::time::Duration::from_millis(5);
-    let mut batch_count = 0;
-    let total_batches = ((range.end - range.start) / batch as u64) + 1;
-    let total_batches_u32 = total_batches as u32;
-    let start_time = std::time::Instant::now();
-
-    let client = reqwest::ClientBuilder::new()
-        .timeout(std::time::Duration::from_secs(config.api_timeout_seconds()))
-        .build()
-        .with_context(|| "无法创建 Reqwest 客户端")?;
-
-    let range_vec: Vec<u64> = range.into_iter().collect();
-    for bunch_id in range_vec.chunks(batch) {
-        let mut join_set = tokio::task::JoinSet::new();
-        for id in bunch_id.iter() {
-            let client = client.clone();
-            let db = db.clone();
-            let api_url = config.api_url().to_string();
-            let locale = config.locale().to_string();
-            let app_id = format!("C{id}");
-            let comment =
-                serde_json::json!({"user": format!("guess_large-{}", env!("CARGO_PKG_VERSION"))});
-            join_set.spawn(async move {
-                if let Ok(data) =
-                    crate::sync::query_app(&client, &api_url, &AppQuery::app_id(&app_id), &locale)
-                        .await
-                {
-                    match db
-                        .save_app_data(&data.0, data.1.as_ref(), None, Some(comment))
-                        .await
-                    {
-                        Ok(inserted) => {
-                            println!(
-                                "{}{}{}",
-                                if inserted.0 {
-                                    format!("已将 {app_id} 的基本插入数据库\n")
-                                        .on_green()
-                                        .to_string()
-                                } else {
-                                    "".to_string()
-                                },
-                                if inserted.1 {
-                                    format!("已将 {app_id} metrics\n").on_green().to_string()
-                                } else {
-                                    "".to_string()
-                                },
-                                if inserted.2 {
-                                    format!("已将 {app_id} 的评分数据插入数据库")
-                                        .on_green()
-                                        .to_string()
-                                } else {
-                                    "".to_string()
-                                }
-                            );
-                        }
-                        Err(err) => {
-                            println!(
-                                "{}",
-                                format!("保存 {app_id} 的数据时出错: {}", err).on_red()
-                            );
-                        }
-                    }
-                }
-            });
-        }
-        join_set.join_all().await;
-        batch_count += 1;
-        let total_elapsed = start_time.elapsed();
-        let avg_time_per_batch = total_elapsed / batch_count;
-        let estimated_total_time = avg_time_per_batch * total_batches_u32;
-        let remaining_time = estimated_total_time.saturating_sub(total_elapsed);
-
-        print!(
-            "\r[批次 {}/{}] id {} - {} 处理完成，总耗时 {:?}，预计剩余 {:?}，等待 {:?}",
-            batch_count,
-            total_batches,
-            bunch_id[0],
-            bunch_id[bunch_id.len() - 1],
-            total_elapsed,
-            remaining_time,
-            wait_time
-        );
-        std::io::Write::flush(&mut std::io::stdout()).unwrap();
-        // tokio::time::sleep(wait_time).await;
-    }
-
-    Ok(())
-}
@@ -0,0 +1,170 @@
+pub mod config;
+pub mod db;
+pub mod model;
+pub mod server;
+pub mod sync;
+pub mod utils;
+
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use colored::Colorize;
+use tracing::{Level, event};
+
+use crate::sync::{
+    code::GLOBAL_CODE_MANAGER,
+    import::{listing, metrics},
+    resolve::resolve_app_input,
+};
+
+/// 导入外部整理的数据
+///
+/// 例如: `import csv import_data/移动端应用市场上新记录整理.CSV`
+/// 或者 `import metrics --app C1164531384803416384 import_data/what.csv`
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: ImportCommand,
+    /// 日志等级, 由 utils::init_log 处理
+    #[arg(short, action = clap::ArgAction::Count, global = true, hide = true)]
+    v: u8,
+    #[arg(short, global = true, hide = true)]
+    d: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum ImportCommand {
+    /// 导入 "移动端应用市场上新记录整理" 表格, 按访问链接同步应用
+    Csv {
+        /// 表格文件, 可以有多个
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// 同时请求的数量
+        #[arg(long, default_value_t = 8)]
+        concurrency: usize,
+        /// 把没能导入的行写到这个 csv 文件
+        #[arg(long)]
+        unmatched: Option<PathBuf>,
+        /// 只检查表格, 不请求也不写数据库
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// 导入某个应用的历史指标 (csv 或 json), 其他字段用最近一次实际抓取的数据补上
+    Metrics {
+        /// 包名、app_id 或者应用市场的分享链接
+        #[arg(long)]
+        app: String,
+        /// 数据文件, `.json` 结尾的按 json 读取, 其他的按 csv 读取
+        file: PathBuf,
+        /// 记录到 imported_from 的来源, 默认是文件名
+        #[arg(long)]
+        source: Option<String>,
+        /// 只检查数据, 不写数据库
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+fn main() -> anyhow::Result<()> {
+    utils::init_log();
+    let cli = Cli::parse();
+
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(8)
+        .enable_all()
+        .build()
+        .with_context(|| "无法创建 tokio runtime")?;
+
+    rt.block_on(async_main(cli.command))
+}
+
+async fn async_main(command: ImportCommand) -> anyhow::Result<()> {
+    let config = config::Config::load().with_context(|| "无法加载配置文件")?;
+    let db = db::Database::new(config.database_url(), config.db_max_connect()).await?;
+    let client = reqwest::ClientBuilder::new()
+        .timeout(std::time::Duration::from_secs(config.api_timeout_seconds()))
+        .build()
+        .with_context(|| "无法创建 Reqwest 客户端")?;
+
+    match command {
+        ImportCommand::Csv {
+            files,
+            concurrency,
+            unmatched,
+            dry_run,
+        } => {
+            if !dry_run {
+                let _token = GLOBAL_CODE_MANAGER.update_token().await;
+            }
+            let mut all_unmatched = Vec::new();
+            for path in files {
+                let report =
+                    listing::import_listing_file(&client, &db, config, &path, concurrency, dry_run)
+                        .await?;
+                println!(
+                    "{}",
+                    format!(
+                        "{}: 共 {} 行, 同步 {} 个, 新应用 {} 个, 未匹配 {} 行",
+                        path.display(),
+                        report.total,
+                        report.synced,
+                        report.new_apps,
+                        report.unmatched.len()
+                    )
+                    .bright_green()
+                );
+                all_unmatched.extend(report.unmatched);
+            }
+
+            for row in &all_unmatched {
+                println!(
+                    "  {} 第 {} 行 {} {}: {}",
+                    row.file, row.line, row.name, row.link, row.reason
+                );
+            }
+            if let Some(path) = unmatched {
+                let mut writer = csv::Writer::from_path(&path)
+                    .with_context(|| format!("无法写入 {}", path.display()))?;
+                for row in &all_unmatched {
+                    writer.serialize(row)?;
+                }
+                writer.flush()?;
+                event!(
+                    Level::INFO,
+                    "已将 {} 行未匹配记录写入 {}",
+                    all_unmatched.len(),
+                    path.display()
+                );
+            }
+        }
+        ImportCommand::Metrics {
+            app,
+            file,
+            source,
+            dry_run,
+        } => {
+            let source = source.unwrap_or_else(|| {
+                file.file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_else(|| file.display().to_string())
+            });
+            let app = resolve_app_input(&app).await?;
+            let report = metrics::import_metrics_file(&db, &app, &file, &source, dry_run).await?;
+            println!(
+                "{}",
+                format!(
+                    "{app}: 共 {} 个点, {}导入 {} 个, 重复 {} 个, 晚于最新数据 {} 个",
+                    report.total,
+                    if dry_run { "可以" } else { "" },
+                    report.imported,
+                    report.duplicated,
+                    report.too_late
+                )
+                .bright_green()
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,124 @@
+pub mod config;
+pub mod db;
+pub mod model;
+pub mod server;
+pub mod sync;
+pub mod utils;
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use colored::Colorize;
+
+use crate::db::migrate::latest_version;
+
+/// 数据库迁移
+///
+/// 例如: `migrate` 执行所有待执行的迁移, `migrate status` 查看状态
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<MigrateCommand>,
+    /// 日志等级, 由 utils::init_log 处理
+    #[arg(short, action = clap::ArgAction::Count, global = true, hide = true)]
+    v: u8,
+    #[arg(short, global = true, hide = true)]
+    d: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum MigrateCommand {
+    /// 执行所有待执行的迁移 (默认), 空数据库会执行 main.sql
+    Up,
+    /// 查看数据库版本和待执行的迁移
+    Status,
+    /// 把这个版本及之前的迁移记为已执行, 用于以前手动执行迁移的数据库
+    Baseline {
+        /// 已经手动执行到的版本, 比如 17 表示 017_add_atomic_service
+        version: i32,
+    },
+}
+
+fn main() -> anyhow::Result<()> {
+    utils::init_log();
+    let cli = Cli::parse();
+
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()
+        .with_context(|| "无法创建 tokio runtime")?;
+
+    rt.block_on(async_main(cli.command.unwrap_or(MigrateCommand::Up)))
+}
+
+async fn async_main(command: MigrateCommand) -> anyhow::Result<()> {
+    let config = config::Config::load().with_context(|| "无法加载配置文件")?;
+    let db = db::Database::connect(config.database_url(), 1).await?;
+
+    match command {
+        MigrateCommand::Up => {
+            let applied = db.migrate().await?;
+            for migration in &applied {
+                println!("{}", format!("已执行 {}", migration.name).bright_green());
+            }
+            println!(
+                "{}",
+                format!(
+                    "执行了 {} 个迁移, 数据库版本 {}",
+                    applied.len(),
+                    latest_version()
+                )
+                .bright_green()
+            );
+        }
+        MigrateCommand::Status => {
+            let status = db.migration_status().await?;
+            match status.version() {
+                None => println!("{}", "数据库没有 schema_migrations 表".yellow()),
+                Some(version) => {
+                    println!("数据库版本 {version}, 程序需要的版本 {}", latest_version())
+                }
+            }
+            for migration in status.applied.iter().flatten() {
+                println!(
+                    "  {} {}",
+                    migration.applied_at.format("%Y-%m-%d %H:%M:%S"),
+                    migration.name
+                );
+            }
+            for migration in status.modified() {
+                println!(
+                    "{}",
+                    format!("  {} 已执行, 但是和程序里的 up.sql 不一样", migration.name).yellow()
+                );
+            }
+            let pending = status.pending();
+            if pending.is_empty() {
+                println!("{}", "没有待执行的迁移".bright_green());
+            } else if status.version().is_none_or(|version| version == 0) && status.has_tables {
+                println!(
+                    "{}",
+                    "数据库已经有表但是没有版本记录, 请先运行 `migrate baseline <已经手动执行到的版本>`"
+                        .yellow()
+                );
+            } else {
+                for migration in pending {
+                    println!("{}", format!("  待执行 {}", migration.name).yellow());
+                }
+            }
+        }
+        MigrateCommand::Baseline { version } => {
+            let recorded = db.baseline(version).await?;
+            println!(
+                "{}",
+                format!(
+                    "已将 {} 个迁移记为已执行, 数据库版本 {version}",
+                    recorded.len()
+                )
+                .bright_green()
+            );
+        }
+    }
+    Ok(())
+}
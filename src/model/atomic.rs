@@ -0,0 +1,65 @@
+//! 元服务
+//!
+//! 包名是 `com.atomicservice.<数字>` 的应用, 免安装, 没有安装包大小和评分,
+//! 在应用市场里以服务卡片的形式展示
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+/// 元服务包名的前缀
+pub const ATOMIC_SERVICE_PREFIX: &str = "com.atomicservice.";
+
+/// 是否是元服务
+pub fn is_atomic_service(pkg_name: &str) -> bool {
+    pkg_name.starts_with(ATOMIC_SERVICE_PREFIX)
+}
+
+/// 服务卡片上展示的内容
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, sqlx::FromRow)]
+pub struct ServiceCard {
+    pub name: String,
+    pub icon_url: String,
+    pub brief_desc: String,
+    pub kind_name: String,
+    pub tag_name: Option<String>,
+    pub main_device_codes: Vec<String>,
+}
+
+/// atomic_service_latest 视图
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, sqlx::FromRow)]
+pub struct AtomicService {
+    pub app_id: String,
+    pub alliance_app_id: String,
+    pub pkg_name: String,
+    #[sqlx(flatten)]
+    pub card: ServiceCard,
+    pub dev_id: String,
+    pub developer_name: String,
+    pub dev_en_name: String,
+    pub description: String,
+    pub privacy_url: String,
+    pub detail_id: String,
+    /// 下面这些来自最新的 metrics, 还没有 metrics 的时候为 None
+    pub version: Option<String>,
+    pub version_code: Option<i64>,
+    pub info_score: Option<f64>,
+    pub info_rate_count: Option<i64>,
+    pub download_count: Option<i64>,
+    pub release_date: Option<i64>,
+    pub listed_at: DateTime<Local>,
+    pub created_at: DateTime<Local>,
+    pub metrics_created_at: Option<DateTime<Local>>,
+}
+
+/// 元服务列表每页和排行最多返回的数量
+pub const ATOMIC_SERVICE_MAX_PAGE_SIZE: u32 = 500;
+
+/// 元服务列表和排行可以用的排序字段
+pub const ATOMIC_SERVICE_SORT_KEYS: &[&str] = &[
+    "download_count",
+    "info_score",
+    "info_rate_count",
+    "release_date",
+    "listed_at",
+    "created_at",
+];
@@ -1,11 +1,13 @@
+pub mod atomic;
 pub mod query;
 pub mod raw;
 
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 
-use crate::utils::sanitize_utf8_string;
+use crate::utils::{content_hash_without, sanitize_utf8_string};
 
+pub use atomic::{AtomicService, ServiceCard, is_atomic_service};
 pub use query::AppQuery;
 pub use raw::{RawJsonData, RawRatingData};
 
@@ -150,6 +152,13 @@ impl AppInfo {
         self.listed_at = db_data.listed_at;
         self.comment = db_data.comment.clone();
     }
+
+    /// 内容哈希, 不包含 created_at / listed_at / comment 这些本地维护的字段
+    ///
+    /// 调用方给的 listed_at / comment 由 [`crate::db::Database::save_app_data_batch`] 单独比较
+    pub fn content_hash(&self) -> String {
+        content_hash_without(self, &["created_at", "listed_at", "comment"])
+    }
 }
 
 /// 简化过的 AppInfo
@@ -231,6 +240,9 @@ pub struct AppMetric {
     pub compile_sdk_version: i32,
     pub min_hmos_api_level: i32,
     pub api_release_type: String,
+    /// 导入来源, None 表示实际抓取的数据
+    #[serde(default)]
+    pub imported_from: Option<String>,
     pub created_at: DateTime<Local>,
 }
 
@@ -274,6 +286,7 @@ impl AppMetric {
             compile_sdk_version: raw_data.compile_sdk_version,
             min_hmos_api_level: raw_data.min_hmos_api_level,
             api_release_type: sanitize_utf8_string(&raw_data.api_release_type).into_owned(),
+            imported_from: None,
             created_at: Local::now(),
         }
     }
@@ -282,6 +295,11 @@ impl AppMetric {
         self.id = db_data.id;
         self.created_at = db_data.created_at;
     }
+
+    /// 内容哈希, 不包含 id / imported_from / created_at
+    pub fn content_hash(&self) -> String {
+        content_hash_without(self, &["id", "imported_from", "created_at"])
+    }
 }
 
 impl AppRating {
@@ -307,6 +325,11 @@ impl AppRating {
         self.id = db_data.id;
         self.created_at = db_data.created_at;
     }
+
+    /// 内容哈希, 不包含 id / created_at
+    pub fn content_hash(&self) -> String {
+        content_hash_without(self, &["id", "created_at"])
+    }
 }
 
 /// 6. 应用数据历史记录表 (app_data_history)
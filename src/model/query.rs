@@ -18,6 +18,15 @@ impl AppQuery {
         Self::AppId(app_id.to_string())
     }
 
+    /// 从 [`Self::app_db_name`] 和值还原
+    pub fn from_db_name(key: &str, value: impl ToString) -> Option<Self> {
+        match key {
+            "pkg_name" => Some(Self::pkg_name(value)),
+            "app_id" => Some(Self::app_id(value)),
+            _ => None,
+        }
+    }
+
     pub fn app_info_type(&self) -> &str {
         match self {
             AppQuery::PkgName(_) => "pkgName",
@@ -10,7 +10,8 @@ use std::io::BufRead;
 use anyhow::Context;
 use tracing::{Level, event};
 
-use crate::sync::code::GLOBAL_CODE_MANAGER;
+use crate::model::AppQuery;
+use crate::sync::{code::GLOBAL_CODE_MANAGER, resolve::resolve_app_input};
 
 fn main() -> anyhow::Result<()> {
     utils::init_log();
@@ -41,26 +42,55 @@ async fn async_main() -> anyhow::Result<()> {
             .ok_or_else(|| anyhow::anyhow!("No file path provided as CLI argument"))?
     };
 
-    let pkg_names: Vec<String> = {
+    let lines: Vec<String> = {
         let file =
             std::fs::File::open(&cli_file).with_context(|| format!("无法读取 {cli_file} 文件"))?;
         let mut reader = std::io::BufReader::new(file);
-        let mut pkg_names = Vec::new();
+        let mut lines = Vec::new();
         let mut line = String::new();
         while reader.read_line(&mut line)? > 0 {
-            pkg_names.push(line.trim().to_string());
+            lines.push(line.trim().to_string());
             line.clear();
         }
-        pkg_names
+        lines
             .into_iter()
             .map(|l| l.trim_matches('\"').to_string())
+            .filter(|l| !l.is_empty())
             .collect()
     };
 
+    // 每行可以是包名、app_id 或者分享链接
+    let mut pkg_names = Vec::new();
+    let mut app_ids = Vec::new();
+    for line in &lines {
+        match resolve_app_input(line).await {
+            Ok(AppQuery::PkgName(pkg_name)) => pkg_names.push(pkg_name),
+            Ok(query) => app_ids.push(query),
+            Err(e) => event!(Level::WARN, "跳过 {line}: {e:#}"),
+        }
+    }
+
     let mut cfg = config.clone();
     cfg.app.packages = pkg_names;
 
     sync::sync_all(&client, &db, &cfg).await?;
 
+    for query in app_ids {
+        match sync::sync_app(
+            &client,
+            &db,
+            config.api_url(),
+            &query,
+            config.locale(),
+            None,
+            None,
+        )
+        .await
+        {
+            Ok(_) => event!(Level::INFO, "{query} 同步完成"),
+            Err(e) => event!(Level::WARN, "{query} 同步失败: {e:#}"),
+        }
+    }
+
     Ok(())
 }
@@ -0,0 +1,86 @@
+pub mod config;
+pub mod db;
+pub mod model;
+pub mod server;
+pub mod sync;
+pub mod utils;
+
+use anyhow::Context;
+use colored::Colorize;
+use tracing::{Level, event};
+
+use crate::sync::code::GLOBAL_CODE_MANAGER;
+
+fn main() -> anyhow::Result<()> {
+    utils::init_log();
+
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(4)
+        .enable_all()
+        .build()
+        .with_context(|| "无法创建 tokio runtime")?;
+    event!(Level::INFO, "async rt built");
+    rt.block_on(async_main())
+}
+
+/// 重新处理隔离表里解析失败的数据
+///
+/// 修好 `RawJsonData` 之后跑一遍, 用存下来的原始数据重新解析并保存
+async fn async_main() -> anyhow::Result<()> {
+    let config = config::Config::load().with_context(|| "无法加载配置文件")?;
+    event!(Level::INFO, "connecting to db");
+    let db = db::Database::new(config.database_url(), config.db_max_connect()).await?;
+    event!(Level::INFO, "connected to db");
+    let client = reqwest::ClientBuilder::new()
+        .timeout(std::time::Duration::from_secs(config.api_timeout_seconds()))
+        .build()
+        .with_context(|| "无法创建 Reqwest 客户端")?;
+    let _token = GLOBAL_CODE_MANAGER.update_token().await;
+
+    let entries = db.get_unresolved_quarantine().await?;
+    println!("隔离表里有 {} 条未处理的数据", entries.len());
+
+    let (mut resolved, mut failed) = (0, 0);
+    for entry in entries {
+        let Some(app_query) = entry.app_query() else {
+            event!(
+                Level::WARN,
+                "未知的查询方式 {}, 跳过 {}",
+                entry.query_key,
+                entry.id
+            );
+            continue;
+        };
+
+        let result = match sync::parse_app_data(
+            &client,
+            config.api_url(),
+            &app_query,
+            entry.raw_json_data.clone(),
+        )
+        .await
+        {
+            Ok((data, rating)) => db
+                .save_app_data(&data, rating.as_ref(), None, None)
+                .await
+                .map(|_| ()),
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(_) => {
+                db.resolve_quarantine(entry.id).await?;
+                resolved += 1;
+                println!("{}", format!("{app_query} 重新处理成功").on_green());
+            }
+            Err(e) => {
+                db.fail_quarantine(entry.id, &format!("{e:#}")).await?;
+                failed += 1;
+                println!("{}", format!("{app_query} 依然失败: {e:#}").on_red());
+            }
+        }
+    }
+
+    println!("处理完成: 成功 {resolved} 条, 失败 {failed} 条");
+    Ok(())
+}
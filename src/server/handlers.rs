@@ -1,7 +1,9 @@
 use axum::{
     Json,
+    body::Body,
     extract::{Path, Query, State},
-    response::IntoResponse,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response as HttpResponse},
 };
 use chrono::{DateTime, Local};
 use serde_json::{Value as JsonValue, json};
@@ -10,9 +12,25 @@ use tracing::{Level, event};
 use std::{str::FromStr, sync::Arc};
 
 use crate::{
-    db::AppCounts,
-    model::{AppInfo, AppMetric, AppQuery, AppRating, FullAppInfo, ShortAppInfo},
-    server::state::{ApiResponse, AppListQuery, AppState, RankingQuery},
+    db::{
+        AppCounts, PaginatedAppInfo,
+        growth::{DEFAULT_MIN_VOTES, GrowthRanking, GrowthWindow, MIN_SAMPLES},
+        search,
+    },
+    model::{
+        AppInfo, AppMetric, AppQuery, AppRating, FullAppInfo, ShortAppInfo,
+        atomic::ATOMIC_SERVICE_MAX_PAGE_SIZE,
+    },
+    server::state::{
+        ApiResponse, AppListQuery, AppState, AtomicServiceQuery, DataHistoryQuery,
+        DownloadGrowthQuery, ExportQuery, GuessScanQuery, MetricHistoryQuery, RankingQuery,
+        RatingGrowthQuery, SchemaDriftQuery, SearchQuery,
+    },
+    sync::{
+        export::{Dataset, ExportFormat, ExportOptions, export},
+        guess::scan::GuessScan,
+        resolve::{classify_id, resolve_app_input},
+    },
 };
 
 #[derive(Debug, serde::Serialize)]
@@ -33,8 +51,9 @@ pub async fn query_app(
     listed_at: Option<DateTime<Local>>,
     comment: Option<JsonValue>,
 ) -> Json<ApiResponse> {
-    match crate::sync::query_app(
+    match crate::sync::query_app_or_quarantine(
         &state.client,
+        &state.db,
         state.cfg.api_url(),
         &query,
         state.cfg.locale(),
@@ -151,7 +170,11 @@ pub async fn query_app_id(
         Level::INFO,
         "http 服务正在尝试通过 appid 获取 {app_id} 的信息"
     );
-    let query = AppQuery::app_id(&app_id);
+    // 纯数字的 alliance_app_id 补上 C
+    let query = match classify_id(&app_id) {
+        Some(query @ AppQuery::AppId(_)) => query,
+        _ => AppQuery::app_id(&app_id),
+    };
     query_app(state, query, None, None).await
 }
 
@@ -184,67 +207,185 @@ pub async fn app_list_info(State(state): State<Arc<AppState>>) -> impl IntoRespo
     }
 }
 
-/// 分页获取应用详细信息
+/// 分页获取应用详细信息, 查询参数里的筛选条件见 [`AppListQuery::filter`]
 pub async fn app_list_paged(
     State(state): State<Arc<AppState>>,
     Path(page): Path<String>,
     Query(query): Query<AppListQuery>,
+) -> HttpResponse {
+    let page = match page.parse::<u32>() {
+        Ok(page) => page,
+        Err(e) => {
+            return Json(ApiResponse::error(format!("Failed to parse page: {}", e)))
+                .into_response();
+        }
+    };
+    let filter = match query.filter() {
+        Ok(filter) => filter,
+        Err(msg) => {
+            return (StatusCode::BAD_REQUEST, Json(ApiResponse::error(msg))).into_response();
+        }
+    };
+    if query.detail() {
+        let result = state
+            .db
+            .get_app_info_paginated_enhanced::<FullAppInfo>(
+                page,
+                query.page_size(),
+                &query.sort_key(),
+                query.desc.unwrap_or_default(),
+                filter,
+            )
+            .await;
+        app_list_response(result, query.page_size())
+    } else {
+        let result = state
+            .db
+            .get_app_info_paginated_enhanced::<ShortAppInfo>(
+                page,
+                query.page_size(),
+                &query.sort_key(),
+                query.desc.unwrap_or_default(),
+                filter,
+            )
+            .await;
+        app_list_response(result, query.page_size())
+    }
+}
+
+fn app_list_response<D: serde::Serialize>(
+    result: anyhow::Result<PaginatedAppInfo<D>>,
+    page_size: u32,
+) -> HttpResponse {
+    match result {
+        Ok(apps) => {
+            let total_count = apps.total_count;
+            Json(ApiResponse::success(
+                apps,
+                Some(total_count),
+                Some(page_size),
+            ))
+            .into_response()
+        }
+        Err(e) => {
+            event!(Level::WARN, "http服务获取分页应用信息失败: {e:#}");
+            Json(ApiResponse::error("Database error")).into_response()
+        }
+    }
+}
+
+/// 全文搜索应用, 按相关度排序, 带高亮
+pub async fn search_apps(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SearchQuery>,
+) -> HttpResponse {
+    let q = query.q.unwrap_or_default();
+    let Some(tsquery) = search::to_tsquery(&q) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error("搜索词里没有可以搜索的文字")),
+        )
+            .into_response();
+    };
+    let page = query.page.unwrap_or(1).max(1);
+    let page_size = query
+        .page_size
+        .unwrap_or(20)
+        .clamp(1, search::SEARCH_MAX_PAGE_SIZE);
+    event!(
+        Level::INFO,
+        "http 服务正在搜索 {q}, 第 {page} 页, 每页 {page_size}"
+    );
+
+    match state.db.search_apps(&q, &tsquery, page, page_size).await {
+        Ok(result) => {
+            let total_count = result.total_count;
+            Json(ApiResponse::success(
+                result,
+                Some(total_count),
+                Some(page_size),
+            ))
+            .into_response()
+        }
+        Err(e) => {
+            event!(Level::WARN, "http服务搜索 {q} 失败: {e:#}");
+            Json(ApiResponse::error("Database error")).into_response()
+        }
+    }
+}
+
+/// 分页获取元服务, 页码从 1 开始
+pub async fn atomic_service_list_paged(
+    State(state): State<Arc<AppState>>,
+    Path(page): Path<u32>,
+    Query(query): Query<AtomicServiceQuery>,
+) -> HttpResponse {
+    let page_size = query
+        .page_size
+        .unwrap_or(100)
+        .clamp(1, ATOMIC_SERVICE_MAX_PAGE_SIZE);
+    let Some(range) = page
+        .saturating_sub(1)
+        .checked_mul(page_size)
+        .and_then(|offset| Some(offset..offset.checked_add(page_size)?))
+    else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(format!("页码 {page} 太大了"))),
+        )
+            .into_response();
+    };
+    match state
+        .db
+        .get_atomic_services(
+            range,
+            query.sort.as_deref().unwrap_or("listed_at"),
+            query.desc.unwrap_or(true),
+            query.search.as_deref(),
+        )
+        .await
+    {
+        Ok((services, total)) => Json(ApiResponse::success(
+            services,
+            Some(total as u32),
+            Some(page_size),
+        ))
+        .into_response(),
+        Err(e) => {
+            event!(Level::WARN, "http服务获取分页元服务失败: {e}");
+            Json(ApiResponse::error("Database error")).into_response()
+        }
+    }
+}
+
+/// 获取元服务排行, 默认按下载量
+pub async fn get_atomic_service_ranking(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AtomicServiceQuery>,
 ) -> impl IntoResponse {
-    match page.parse::<u32>() {
-        Ok(page) => {
-            if query.detail() {
-                match state
-                    .db
-                    .get_app_info_paginated_enhanced::<FullAppInfo>(
-                        page,
-                        query.page_size(),
-                        &query.sort_key(),
-                        query.desc.unwrap_or_default(),
-                        query.search_option(),
-                    )
-                    .await
-                {
-                    Ok(apps) => {
-                        let total_count = apps.total_count;
-                        Json(ApiResponse::success(
-                            apps,
-                            Some(total_count),
-                            Some(query.page_size()),
-                        ))
-                    }
-                    Err(e) => {
-                        event!(Level::WARN, "http服务获取分页应用信息失败: {e}");
-                        Json(ApiResponse::error("Database error".to_string()))
-                    }
-                }
-            } else {
-                match state
-                    .db
-                    .get_app_info_paginated_enhanced::<ShortAppInfo>(
-                        page,
-                        query.page_size(),
-                        &query.sort_key(),
-                        query.desc.unwrap_or_default(),
-                        query.search_option(),
-                    )
-                    .await
-                {
-                    Ok(apps) => {
-                        let total_count = apps.total_count;
-                        Json(ApiResponse::success(
-                            apps,
-                            Some(total_count),
-                            Some(query.page_size()),
-                        ))
-                    }
-                    Err(e) => {
-                        event!(Level::WARN, "http服务获取分页应用信息失败: {e}");
-                        Json(ApiResponse::error("Database error".to_string()))
-                    }
-                }
-            }
+    let limit = query
+        .limit
+        .unwrap_or(10)
+        .clamp(1, ATOMIC_SERVICE_MAX_PAGE_SIZE);
+    let sort = query.sort.as_deref().unwrap_or("download_count");
+    event!(Level::INFO, "获取元服务排行，排序: {sort}，限制: {limit}");
+    match state
+        .db
+        .get_atomic_services(0..limit, sort, query.desc.unwrap_or(true), None)
+        .await
+    {
+        Ok((services, _)) => {
+            let total_count = services.len() as u32;
+            Json(ApiResponse::success(
+                services,
+                Some(total_count),
+                Some(limit),
+            ))
+        }
+        Err(e) => {
+            event!(Level::WARN, "获取元服务排行失败: {e}");
+            Json(ApiResponse::error("Database error"))
         }
-        Err(e) => Json(ApiResponse::error(format!("Failed to parse page: {}", e))),
     }
 }
 
@@ -317,53 +458,107 @@ pub async fn get_recent_ranking(
 }
 
 /// 获取下载量增长排行
+///
+/// 响应里带实际的窗口, 每个应用带实际用作基准和最新的样本时间
 pub async fn get_download_growth_ranking(
     State(state): State<Arc<AppState>>,
-    Query(query): Query<RankingQuery>,
-) -> impl IntoResponse {
+    Query(query): Query<DownloadGrowthQuery>,
+) -> HttpResponse {
     let limit = query.limit.unwrap_or(10);
-    let time_range = query.time_range.unwrap_or_else(|| "7d".to_string());
+    let sort = query.sort.unwrap_or_default();
+    let window = match parse_time_range(&query.since, &query.until).and_then(|(since, until)| {
+        GrowthWindow::resolve(query.time_range.as_deref(), since, until, Local::now())
+    }) {
+        Ok(window) => window,
+        Err(msg) => {
+            return (StatusCode::BAD_REQUEST, Json(ApiResponse::error(msg))).into_response();
+        }
+    };
     event!(
         Level::INFO,
-        "获取下载量增长排行，限制: {}, 时间范围: {}",
+        "获取下载量增长排行，限制: {}, 时间范围: {} 到 {}, 排序: {:?}",
         limit,
-        time_range
+        window.since,
+        window.until,
+        sort
     );
 
-    match state.db.get_download_growth_apps(limit, &time_range).await {
+    match state
+        .db
+        .get_download_growth_apps(
+            window,
+            sort,
+            query.min_samples.unwrap_or(MIN_SAMPLES),
+            query.min_baseline.unwrap_or(0),
+            limit,
+        )
+        .await
+    {
         Ok(apps) => {
             let total_count = apps.len() as u32;
-            Json(ApiResponse::success(apps, Some(total_count), Some(limit)))
+            Json(ApiResponse::success(
+                GrowthRanking { window, apps },
+                Some(total_count),
+                Some(limit),
+            ))
+            .into_response()
         }
         Err(e) => {
-            event!(Level::WARN, "获取下载量增长排行失败: {e}");
-            Json(ApiResponse::error("Database error".to_string()))
+            event!(Level::WARN, "获取下载量增长排行失败: {e:#}");
+            Json(ApiResponse::error("Database error")).into_response()
         }
     }
 }
 
 /// 获取评分增长排行
+///
+/// 按 app_rating 的历史计算窗口内的变化, 响应格式同下载量增长排行
 pub async fn get_rating_growth_ranking(
     State(state): State<Arc<AppState>>,
-    Query(query): Query<RankingQuery>,
-) -> impl IntoResponse {
+    Query(query): Query<RatingGrowthQuery>,
+) -> HttpResponse {
     let limit = query.limit.unwrap_or(10);
-    let time_range = query.time_range.unwrap_or_else(|| "7d".to_string());
+    let sort = query.sort.unwrap_or_default();
+    let window = match parse_time_range(&query.since, &query.until).and_then(|(since, until)| {
+        GrowthWindow::resolve(query.time_range.as_deref(), since, until, Local::now())
+    }) {
+        Ok(window) => window,
+        Err(msg) => {
+            return (StatusCode::BAD_REQUEST, Json(ApiResponse::error(msg))).into_response();
+        }
+    };
     event!(
         Level::INFO,
-        "获取评分增长排行，限制: {}, 时间范围: {}",
+        "获取评分增长排行，限制: {}, 时间范围: {} 到 {}, 排序: {:?}",
         limit,
-        time_range
+        window.since,
+        window.until,
+        sort
     );
 
-    match state.db.get_rating_growth_apps(limit, &time_range).await {
+    match state
+        .db
+        .get_rating_growth_apps(
+            window,
+            sort,
+            query.desc.unwrap_or(true),
+            query.min_votes.unwrap_or(DEFAULT_MIN_VOTES),
+            limit,
+        )
+        .await
+    {
         Ok(apps) => {
             let total_count = apps.len() as u32;
-            Json(ApiResponse::success(apps, Some(total_count), Some(limit)))
+            Json(ApiResponse::success(
+                GrowthRanking { window, apps },
+                Some(total_count),
+                Some(limit),
+            ))
+            .into_response()
         }
         Err(e) => {
-            event!(Level::WARN, "获取评分增长排行失败: {e}");
-            Json(ApiResponse::error("Database error".to_string()))
+            event!(Level::WARN, "获取评分增长排行失败: {e:#}");
+            Json(ApiResponse::error("Database error")).into_response()
         }
     }
 }
@@ -408,24 +603,226 @@ pub async fn get_star_distribution(State(state): State<Arc<AppState>>) -> impl I
     }
 }
 
-/// 获取指定应用的下载量历史变化数据
+/// 查询参数里的 (since, until)
+type TimeRange = (Option<DateTime<Local>>, Option<DateTime<Local>>);
+
+/// 解析查询参数里的时间范围, 出错时返回错误信息
+fn parse_time_range(since: &Option<String>, until: &Option<String>) -> Result<TimeRange, String> {
+    let parse = |value: &Option<String>| match value {
+        Some(value) => crate::utils::parse_datetime(value)
+            .map(Some)
+            .ok_or_else(|| format!("无法解析时间 {value}")),
+        None => Ok(None),
+    };
+    Ok((parse(since)?, parse(until)?))
+}
+
+/// 获取应用下载量历史数据, 按时间范围选择原始数据或者汇总
+///
+/// 实际使用的精度放在 `x-metrics-resolution` 响应头里
 pub async fn get_app_download_history(
     State(state): State<Arc<AppState>>,
     Path(pkg_name): Path<String>,
-) -> impl IntoResponse {
+    Query(query): Query<MetricHistoryQuery>,
+) -> HttpResponse {
+    event!(
+        Level::INFO,
+        "http 服务正在尝试获取应用 {} 的下载量历史数据, 范围: {:?}..{:?}",
+        pkg_name,
+        query.since,
+        query.until
+    );
+    let (since, until) = match parse_time_range(&query.since, &query.until) {
+        Ok(range) => range,
+        Err(msg) => {
+            return (StatusCode::BAD_REQUEST, Json(ApiResponse::error(msg))).into_response();
+        }
+    };
+    match state
+        .db
+        .get_app_metric_series(
+            &pkg_name,
+            since,
+            until,
+            query.resolution,
+            state.cfg.retention(),
+        )
+        .await
+    {
+        Ok((resolution, metrics)) => (
+            [("x-metrics-resolution", resolution.name())],
+            Json(ApiResponse::success(metrics, None, None)),
+        )
+            .into_response(),
+        Err(e) => {
+            event!(
+                Level::WARN,
+                "http服务获取应用 {} 下载量历史失败: {e:#}",
+                pkg_name
+            );
+            Json(ApiResponse::error("Database error")).into_response()
+        }
+    }
+}
+
+/// 获取应用每天的统计
+pub async fn get_app_daily_stats(
+    State(state): State<Arc<AppState>>,
+    Path(pkg_name): Path<String>,
+    Query(query): Query<MetricHistoryQuery>,
+) -> HttpResponse {
     event!(
         Level::INFO,
-        "http 服务正在尝试获取应用 {} 的下载量历史数据",
-        pkg_name
+        "http 服务正在尝试获取应用 {} 的每日统计, 范围: {:?}..{:?}",
+        pkg_name,
+        query.since,
+        query.until
     );
-    match state.db.get_app_metrics_by_pkg_id(&pkg_name).await {
-        Ok(metrics) => Json(ApiResponse::success(metrics, None, None)),
+    let (since, until) = match parse_time_range(&query.since, &query.until) {
+        Ok(range) => range,
+        Err(msg) => {
+            return (StatusCode::BAD_REQUEST, Json(ApiResponse::error(msg))).into_response();
+        }
+    };
+    match state
+        .db
+        .get_app_daily_stats(
+            &pkg_name,
+            since.map(|t| t.date_naive()),
+            until.map(|t| t.date_naive()),
+        )
+        .await
+    {
+        Ok(stats) => {
+            let total_count = stats.len() as u32;
+            Json(ApiResponse::success(stats, Some(total_count), None)).into_response()
+        }
         Err(e) => {
             event!(
                 Level::WARN,
-                "http服务获取应用 {} 下载量历史失败: {e}",
+                "http服务获取应用 {} 每日统计失败: {e:#}",
                 pkg_name
             );
+            Json(ApiResponse::error("Database error")).into_response()
+        }
+    }
+}
+
+/// 获取应用的原始数据历史, 由快照和增量还原
+pub async fn get_app_data_history(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<String>,
+    Query(query): Query<DataHistoryQuery>,
+) -> HttpResponse {
+    event!(
+        Level::INFO,
+        "http 服务正在尝试获取应用 {app_id} 的原始数据历史, 时间: {:?}",
+        query.at
+    );
+    let result = match query.at.as_deref() {
+        Some(at) => {
+            let Some(at) = crate::utils::parse_datetime(at) else {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ApiResponse::error(format!("无法解析时间 {at}"))),
+                )
+                    .into_response();
+            };
+            state
+                .db
+                .get_data_version(&app_id, at)
+                .await
+                .map(|version| version.into_iter().collect::<Vec<_>>())
+        }
+        None => state.db.get_data_history(&app_id).await,
+    };
+    match result {
+        Ok(histories) => {
+            let total_count = histories.len() as u32;
+            Json(ApiResponse::success(histories, Some(total_count), None)).into_response()
+        }
+        Err(e) => {
+            event!(
+                Level::WARN,
+                "http服务获取应用 {app_id} 原始数据历史失败: {e:#}"
+            );
+            Json(ApiResponse::error("Database error")).into_response()
+        }
+    }
+}
+
+/// 获取上游数据结构变化记录
+pub async fn get_schema_drift(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SchemaDriftQuery>,
+) -> impl IntoResponse {
+    let limit = query.limit.unwrap_or(100);
+    event!(
+        Level::INFO,
+        "获取数据结构变化记录，限制: {} 来源: {:?}",
+        limit,
+        query.source
+    );
+    match state
+        .db
+        .get_schema_drifts(query.source.as_deref(), limit)
+        .await
+    {
+        Ok(drifts) => {
+            let total_count = drifts.len() as u32;
+            Json(ApiResponse::success(drifts, Some(total_count), Some(limit)))
+        }
+        Err(e) => {
+            event!(Level::WARN, "获取数据结构变化记录失败: {e}");
+            Json(ApiResponse::error("Database error"))
+        }
+    }
+}
+
+/// 获取猜测任务的进度
+pub async fn get_guess_scans(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<GuessScanQuery>,
+) -> impl IntoResponse {
+    event!(Level::INFO, "获取猜测任务列表，状态: {:?}", query.status);
+    match state.db.get_guess_scans(query.status.as_deref()).await {
+        Ok(scans) => {
+            let total_count = scans.len() as u32;
+            let scans = scans.into_iter().map(GuessScan::status).collect::<Vec<_>>();
+            Json(ApiResponse::success(scans, Some(total_count), None))
+        }
+        Err(e) => {
+            event!(Level::WARN, "获取猜测任务列表失败: {e}");
+            Json(ApiResponse::error("Database error"))
+        }
+    }
+}
+
+/// 获取单个猜测任务的进度
+pub async fn get_guess_scan(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> impl IntoResponse {
+    match state.db.get_guess_scan(id).await {
+        Ok(Some(scan)) => Json(ApiResponse::success(scan.status(), None, None)),
+        Ok(None) => Json(ApiResponse::error("Scan not found")),
+        Err(e) => {
+            event!(Level::WARN, "获取猜测任务 {id} 失败: {e}");
+            Json(ApiResponse::error("Database error"))
+        }
+    }
+}
+
+/// 按猜测方式汇总命中率, 用来比较不同的猜测方式
+pub async fn get_guess_strategies(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    event!(Level::INFO, "获取猜测方式命中率");
+    match state.db.get_guess_strategy_stats().await {
+        Ok(stats) => {
+            let total_count = stats.len() as u32;
+            Json(ApiResponse::success(stats, Some(total_count), None))
+        }
+        Err(e) => {
+            event!(Level::WARN, "获取猜测方式命中率失败: {e}");
             Json(ApiResponse::error("Database error"))
         }
     }
@@ -466,8 +863,9 @@ pub async fn submit_substance(
             };
 
             for query in substance.data.iter() {
-                match crate::sync::query_app(
+                match crate::sync::query_app_or_quarantine(
                     &state.client,
+                    &state.db,
                     state.cfg.api_url(),
                     query,
                     state.cfg.locale(),
@@ -529,22 +927,27 @@ pub async fn submit_app(
     State(state): State<Arc<AppState>>,
     Json(data): Json<JsonValue>,
 ) -> Json<ApiResponse> {
-    // 获取 app_id 或者 pkg_name
-    let app_id = data.get("app_id").and_then(|v| v.as_str());
-    let pkg_name = data.get("pkg_name").and_then(|v| v.as_str());
-    if app_id.is_none() && pkg_name.is_none() {
-        return Json(ApiResponse::error(
-            "app_id 和 pkg_name 至少给一个, 总不能一个不给吧".to_string(),
-        ));
-    } else if app_id.is_some() && pkg_name.is_some() {
-        return Json(ApiResponse::error(
-            "app_id 和 pkg_name 至多给一个, 你两个都给是什么意思".to_string(),
-        ));
-    }
-    let query = match (app_id, pkg_name) {
-        (Some(id), None) => AppQuery::app_id(id),
-        (None, Some(name)) => AppQuery::pkg_name(name),
-        _ => unreachable!(),
+    // 获取 app_id / pkg_name / link, 都可以是包名、app_id、alliance_app_id 或者分享链接
+    let inputs: Vec<&str> = ["app_id", "pkg_name", "link"]
+        .iter()
+        .filter_map(|key| data.get(*key).and_then(|v| v.as_str()))
+        .collect();
+    let input = match inputs.as_slice() {
+        [] => {
+            return Json(ApiResponse::error(
+                "app_id、pkg_name 和 link 至少给一个, 总不能一个不给吧".to_string(),
+            ));
+        }
+        [input] => *input,
+        _ => {
+            return Json(ApiResponse::error(
+                "app_id、pkg_name 和 link 至多给一个, 你给好几个是什么意思".to_string(),
+            ));
+        }
+    };
+    let query = match resolve_app_input(input).await {
+        Ok(query) => query,
+        Err(e) => return Json(ApiResponse::error(format!("{e:#}"))),
     };
     let app_exists = state.db.app_exists(&query).await;
 
@@ -569,3 +972,63 @@ pub async fn submit_app(
         query_app(state, query, listed_at, comment.cloned()).await
     }
 }
+
+/// 流式导出数据集
+///
+/// 导出在后台任务里进行, 通过有界的管道边编码边发送, 客户端读得慢导出也会等着
+pub async fn export_dataset(
+    State(state): State<Arc<AppState>>,
+    Path(dataset): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> HttpResponse {
+    let bad_request =
+        |msg: String| (StatusCode::BAD_REQUEST, Json(ApiResponse::error(msg))).into_response();
+    let Ok(dataset) = <Dataset as clap::ValueEnum>::from_str(&dataset, true) else {
+        return bad_request(format!(
+            "未知的数据集 {dataset}, 可选 apps / metrics / ratings / substances"
+        ));
+    };
+    let format = match query.format.as_deref() {
+        None => ExportFormat::default(),
+        Some(format) => match <ExportFormat as clap::ValueEnum>::from_str(format, true) {
+            Ok(format) => format,
+            Err(_) => {
+                return bad_request(format!("未知的格式 {format}, 可选 jsonl / csv / parquet"));
+            }
+        },
+    };
+    let (since, until) = match parse_time_range(&query.since, &query.until) {
+        Ok(range) => range,
+        Err(msg) => return bad_request(msg),
+    };
+    let options = ExportOptions {
+        dataset,
+        format,
+        since,
+        until,
+        kind: query.kind,
+        developer: query.developer,
+    };
+    event!(Level::INFO, "http 服务开始导出 {:?}", options);
+
+    let (mut writer, reader) = tokio::io::duplex(64 * 1024);
+    let file_name = options.file_name();
+    tokio::spawn(async move {
+        // 出错的时候连接会提前结束, 客户端拿到的是不完整的文件
+        if let Err(e) = export(&state.db, &options, &mut writer).await {
+            event!(Level::WARN, "导出 {} 失败: {e:#}", options.file_name());
+        }
+    });
+
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}\""),
+            ),
+        ],
+        Body::from_stream(tokio_util::io::ReaderStream::new(reader)),
+    )
+        .into_response()
+}
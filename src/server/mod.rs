@@ -23,7 +23,16 @@ pub use routes::create_router;
 pub async fn worker(mut waiter: tokio::sync::oneshot::Receiver<()>) -> anyhow::Result<()> {
     let config = get_config();
     event!(Level::INFO, "connecting to db");
-    let db = crate::db::Database::new(config.database_url(), config.db_max_connect()).await?;
+    let db = if config.auto_migrate() {
+        let db =
+            crate::db::Database::connect(config.database_url(), config.db_max_connect()).await?;
+        let applied = db.migrate().await?;
+        event!(Level::INFO, "执行了 {} 个迁移", applied.len());
+        db.check_schema_version().await?;
+        db
+    } else {
+        crate::db::Database::new(config.database_url(), config.db_max_connect()).await?
+    };
     event!(Level::INFO, "connected to db");
 
     #[cfg(not(feature = "no_sync"))]
@@ -36,11 +45,18 @@ pub async fn worker(mut waiter: tokio::sync::oneshot::Receiver<()>) -> anyhow::R
 
     let interval = config.api_interval();
     let web_part = tokio::spawn(web_main(config.clone(), db.clone()));
+    let rollup_part = tokio::spawn(rollup_main(config.clone(), db.clone()));
+
+    #[cfg(not(feature = "no_sync"))]
+    let mut sources = crate::sync::source::SourceScheduler::new(config);
 
     loop {
         // no_sync 的时候就不同步了
         #[cfg(not(feature = "no_sync"))]
-        crate::sync::sync_all(&client, &db, config).await?;
+        {
+            crate::sync::sync_all(&client, &db, config).await?;
+            sources.run_due(&client, &db, config).await;
+        }
 
         // 通过 select 同时等待/接受结束事件
         let wait_time = std::time::Duration::from_secs(interval);
@@ -55,9 +71,26 @@ pub async fn worker(mut waiter: tokio::sync::oneshot::Receiver<()>) -> anyhow::R
     }
 
     web_part.abort();
+    rollup_part.abort();
     Ok(())
 }
 
+/// 定时汇总 app_metrics 并按保留策略清理, 然后更新每日统计
+pub async fn rollup_main(config: Config, db: Database) {
+    let interval = std::time::Duration::from_secs(config.retention().interval_seconds);
+    loop {
+        match db.maintain_metrics(config.retention()).await {
+            Ok(_) => {
+                if let Err(e) = db.refresh_daily_stats(None).await {
+                    event!(Level::WARN, "每日统计计算失败: {e:#}");
+                }
+            }
+            Err(e) => event!(Level::WARN, "指标汇总失败: {e:#}"),
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
 /// Web服务器主函数
 pub async fn web_main(config: Config, db: Database) -> anyhow::Result<()> {
     let client = reqwest::ClientBuilder::new()
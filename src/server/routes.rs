@@ -17,11 +17,28 @@ pub fn create_router(app_state: Arc<AppState>) -> Router {
         .route("/market_info", get(handlers::app_list_info))
         // 获取分页的应用信息
         .route("/apps/list/{page_count}", get(handlers::app_list_paged))
+        // 全文搜索应用
+        .route("/search", get(handlers::search_apps))
+        // 获取分页的元服务信息
+        .route(
+            "/atomic_services/list/{page}",
+            get(handlers::atomic_service_list_paged),
+        )
         // 获取应用下载量历史数据
         .route(
             "/apps/metrics/{pkg_id}",
             get(handlers::get_app_download_history),
         )
+        // 获取应用每天的统计
+        .route(
+            "/apps/daily_stats/{pkg_name}",
+            get(handlers::get_app_daily_stats),
+        )
+        // 获取应用原始数据历史
+        .route(
+            "/apps/data_history/{app_id}",
+            get(handlers::get_app_data_history),
+        )
         // 新增排行API路由
         // 获取下载量排行榜
         .route(
@@ -42,6 +59,11 @@ pub fn create_router(app_state: Arc<AppState>) -> Router {
             "/rankings/rating-growth",
             get(handlers::get_rating_growth_ranking),
         )
+        // 获取元服务排行榜
+        .route(
+            "/rankings/atomic-services",
+            get(handlers::get_atomic_service_ranking),
+        )
         // 获取开发者排行榜
         .route("/rankings/developers", get(handlers::get_developer_ranking))
         // 获取星级分布
@@ -49,12 +71,23 @@ pub fn create_router(app_state: Arc<AppState>) -> Router {
             "/charts/star-distribution",
             get(handlers::get_star_distribution),
         )
+        // 流式导出数据集
+        .route("/export/{dataset}", get(handlers::export_dataset))
         // 投稿
         .route("/submit", post(handlers::submit_app))
         .route(
             "/submit_substance/{substance_id}",
             post(handlers::submit_substance),
         )
+        // 管理: 上游数据结构变化
+        .route("/admin/schema_drift", get(handlers::get_schema_drift))
+        // 管理: 猜测任务进度
+        .route("/admin/guess_scans", get(handlers::get_guess_scans))
+        .route("/admin/guess_scans/{id}", get(handlers::get_guess_scan))
+        .route(
+            "/admin/guess_strategies",
+            get(handlers::get_guess_strategies),
+        )
         .fallback(api_not_found)
         .with_state(app_state.clone());
 
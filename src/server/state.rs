@@ -3,7 +3,12 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     config::Config,
-    db::{Database, DbSearch},
+    db::{
+        Database,
+        filter::{AppFilter, ArrayField, DateField, NumericField, TextField},
+        growth::{DownloadGrowthSort, RatingGrowthSort},
+        rollup::MetricResolution,
+    },
 };
 
 /// 应用状态，包含数据库连接、HTTP客户端和配置
@@ -83,15 +88,154 @@ impl Default for RankingQuery {
     }
 }
 
+/// 用于下载量增长排行的查询参数
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct DownloadGrowthQuery {
+    pub limit: Option<u32>,
+    /// `1d` / `7d` / `30d` / `12h` 这样的窗口长度, 默认 7d; 填了 since 就是自定义窗口
+    pub time_range: Option<String>,
+    pub since: Option<String>,
+    /// 窗口结束时间, 不填就是现在
+    pub until: Option<String>,
+    /// absolute / relative, 默认 absolute
+    pub sort: Option<DownloadGrowthSort>,
+    /// 基准到最新之间至少要有的样本数, 默认 2
+    pub min_samples: Option<u32>,
+    /// 基准下载量至少要有多少, 默认 0
+    pub min_baseline: Option<i64>,
+}
+
+/// 用于评分增长排行的查询参数
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct RatingGrowthQuery {
+    pub limit: Option<u32>,
+    /// 同 [`DownloadGrowthQuery::time_range`]
+    pub time_range: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    /// average_rating / rating_count / star_5 / star_1, 默认 average_rating
+    pub sort: Option<RatingGrowthSort>,
+    /// 默认 true, 从大到小
+    pub desc: Option<bool>,
+    /// 最新评分人数至少要有多少, 默认 50
+    pub min_votes: Option<i64>,
+}
+
+/// 用于全文搜索的查询参数
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct SearchQuery {
+    /// 搜索词, 多个词用空格分开, 每个词都要匹配
+    pub q: Option<String>,
+    /// 页码, 从 1 开始
+    pub page: Option<u32>,
+    /// 每页数量, 默认 20, 最多 [`crate::db::search::SEARCH_MAX_PAGE_SIZE`]
+    pub page_size: Option<u32>,
+}
+
+/// 用于查询数据结构变化的查询参数
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct SchemaDriftQuery {
+    pub source: Option<String>,
+    pub limit: Option<u32>,
+}
+
+/// 用于查询猜测任务的查询参数
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct GuessScanQuery {
+    /// running / finished / failed, 不填就是全部
+    pub status: Option<String>,
+}
+
+/// 用于元服务列表和排行的查询参数
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct AtomicServiceQuery {
+    /// 排序字段, 见 [`crate::model::atomic::ATOMIC_SERVICE_SORT_KEYS`]
+    pub sort: Option<String>,
+    pub desc: Option<bool>,
+    /// 在名称, 包名和开发者名称里模糊搜索
+    pub search: Option<String>,
+    /// 列表的每页数量
+    pub page_size: Option<u32>,
+    /// 排行的数量
+    pub limit: Option<u32>,
+}
+
+/// 用于导出数据集的查询参数
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct ExportQuery {
+    /// jsonl (默认) / csv / parquet
+    pub format: Option<String>,
+    /// 开始时间 (包含), 比如 2025-01-01 或者 RFC3339
+    pub since: Option<String>,
+    /// 结束时间 (不包含)
+    pub until: Option<String>,
+    /// 应用分类, kind_name 或者 kind_type_name
+    pub kind: Option<String>,
+    /// 开发者名称或者 dev_id
+    pub developer: Option<String>,
+}
+
+/// 用于查询应用指标历史的查询参数
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct MetricHistoryQuery {
+    /// 开始时间 (包含), 不填就是第一条数据
+    pub since: Option<String>,
+    /// 结束时间 (包含), 不填就是现在
+    pub until: Option<String>,
+    /// raw / hourly / daily, 不填就按时间范围自动选择
+    pub resolution: Option<MetricResolution>,
+}
+
+/// 用于查询应用原始数据历史的查询参数
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct DataHistoryQuery {
+    /// 只要这个时间的版本, 不填就是全部历史
+    pub at: Option<String>,
+}
+
 /// 用于查询应用列表的查询参数
+///
+/// 除了 `sort` / `desc` / `page_size` / `detail`, 其他参数都是筛选条件, 用 AND 组合;
+/// 更复杂的组合放在 `filter` 里, 是 JSON 格式的 [`AppFilter`]
 #[derive(Deserialize, Serialize, Clone, Debug, Default)]
 pub struct AppListQuery {
     pub sort: Option<String>,
     pub desc: Option<bool>,
+    /// name / pkg_name / app_id / developer_name / dev_en_name
     pub search_key: Option<String>,
     pub search_value: Option<String>,
     pub search_exact: Option<bool>,
-    pub search_not_null: Option<bool>,
+    pub min_downloads: Option<f64>,
+    pub max_downloads: Option<f64>,
+    pub min_size: Option<f64>,
+    pub max_size: Option<f64>,
+    pub min_rating: Option<f64>,
+    pub max_rating: Option<f64>,
+    pub min_rating_count: Option<f64>,
+    pub max_rating_count: Option<f64>,
+    pub min_price: Option<f64>,
+    pub max_price: Option<f64>,
+    pub min_target_sdk: Option<f64>,
+    pub max_target_sdk: Option<f64>,
+    pub min_api_level: Option<f64>,
+    pub max_api_level: Option<f64>,
+    /// 支持的设备, 逗号分隔, 要全部支持
+    pub device: Option<String>,
+    /// 发布的国家/地区, 逗号分隔, 要全部包含
+    pub country: Option<String>,
+    /// kind_name 或者 kind_type_name
+    pub kind: Option<String>,
+    pub tag: Option<String>,
+    /// 上架时间范围, 包含 since, 不包含 until
+    pub listed_since: Option<String>,
+    pub listed_until: Option<String>,
+    /// 收录时间范围, 包含 since, 不包含 until
+    pub created_since: Option<String>,
+    pub created_until: Option<String>,
+    pub paid: Option<bool>,
+    pub iap: Option<bool>,
+    /// JSON 格式的 [`AppFilter`], 和上面的条件用 AND 组合
+    pub filter: Option<String>,
     pub page_size: Option<u32>,
     pub detail: Option<bool>,
 }
@@ -132,29 +276,106 @@ impl AppListQuery {
         self.detail.unwrap_or(true)
     }
 
-    fn is_valid_search(&self) -> bool {
-        if let Some(key) = &self.search_key {
-            matches!(
-                key.as_str(),
-                "name" | "pkg_name" | "app_id" | "developer_name" | "developer_en_name"
-            )
-        } else {
-            false
+    /// 把查询参数转成筛选条件, 参数有错时返回错误信息
+    pub fn filter(&self) -> Result<AppFilter, String> {
+        let mut filters = Vec::new();
+        if let (Some(key), Some(value)) = (&self.search_key, &self.search_value) {
+            let field = TextField::from_key(key).ok_or_else(|| format!("不支持搜索 {key}"))?;
+            filters.push(AppFilter::Text {
+                field,
+                value: value.clone(),
+                exact: self.search_exact.unwrap_or(false),
+            });
         }
-    }
-
-    pub fn search_option(&self) -> Option<DbSearch> {
-        if !self.is_valid_search() {
-            return None;
+        let ranges = [
+            (
+                NumericField::DownloadCount,
+                self.min_downloads,
+                self.max_downloads,
+            ),
+            (NumericField::SizeBytes, self.min_size, self.max_size),
+            (
+                NumericField::AverageRating,
+                self.min_rating,
+                self.max_rating,
+            ),
+            (
+                NumericField::RatingCount,
+                self.min_rating_count,
+                self.max_rating_count,
+            ),
+            (NumericField::Price, self.min_price, self.max_price),
+            (
+                NumericField::TargetSdk,
+                self.min_target_sdk,
+                self.max_target_sdk,
+            ),
+            (
+                NumericField::MinHmosApiLevel,
+                self.min_api_level,
+                self.max_api_level,
+            ),
+        ];
+        for (field, min, max) in ranges {
+            if min.is_some() || max.is_some() {
+                filters.push(AppFilter::Range { field, min, max });
+            }
+        }
+        for (field, values) in [
+            (ArrayField::MainDeviceCodes, &self.device),
+            (ArrayField::ReleaseCountries, &self.country),
+        ] {
+            if let Some(values) = values {
+                let values = values
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                filters.push(AppFilter::Contains { field, values });
+            }
         }
-        match (&self.search_key, &self.search_value) {
-            (Some(key), Some(value)) => Some(DbSearch::new(
-                key.clone(),
-                value.clone(),
-                self.search_exact.unwrap_or(false),
-                self.search_not_null.unwrap_or(true),
-            )),
-            _ => None,
+        if let Some(kind) = &self.kind {
+            filters.push(AppFilter::Kind(kind.clone()));
         }
+        if let Some(tag) = &self.tag {
+            filters.push(AppFilter::Tag(tag.clone()));
+        }
+        let parse = |value: &Option<String>| match value {
+            Some(value) => crate::utils::parse_datetime(value)
+                .map(Some)
+                .ok_or_else(|| format!("无法解析时间 {value}")),
+            None => Ok(None),
+        };
+        for (field, since, until) in [
+            (DateField::ListedAt, &self.listed_since, &self.listed_until),
+            (
+                DateField::CreatedAt,
+                &self.created_since,
+                &self.created_until,
+            ),
+        ] {
+            if since.is_some() || until.is_some() {
+                filters.push(AppFilter::DateRange {
+                    field,
+                    since: parse(since)?,
+                    until: parse(until)?,
+                });
+            }
+        }
+        if let Some(paid) = self.paid {
+            filters.push(AppFilter::Paid(paid));
+        }
+        if let Some(iap) = self.iap {
+            filters.push(AppFilter::Iap(iap));
+        }
+        if let Some(filter) = &self.filter {
+            filters
+                .push(serde_json::from_str(filter).map_err(|e| format!("无法解析 filter: {e}"))?);
+        }
+
+        let filter = AppFilter::All(filters);
+        filter.validate()?;
+        Ok(filter)
     }
 }
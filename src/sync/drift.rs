@@ -0,0 +1,422 @@
+//! 上游数据结构漂移检测
+//!
+//! 记录 appinfo / page-detail 返回数据里每个 key 的类型,
+//! 出现新 key / key 消失 / 类型变化的时候写到 schema_drift 表里
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    sync::LazyLock,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tokio::sync::Mutex;
+use tracing::{Level, event};
+
+use crate::{db::Database, utils::json_content_hash};
+
+/// appinfo 接口
+pub const SOURCE_APP_INFO: &str = "appinfo";
+/// page-detail 接口里的 starInfo
+pub const SOURCE_PAGE_DETAIL: &str = "page-detail";
+
+/// 类型缺失 (key 不存在)
+pub const MISSING_TYPE: &str = "missing";
+
+pub static GLOBAL_DRIFT_DETECTOR: LazyLock<DriftDetector> = LazyLock::new(|| DriftDetector {
+    state: Mutex::new(DriftState::default()),
+    flushing: Mutex::new(()),
+});
+
+/// 一次结构变化
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DriftKind {
+    /// 新出现的 key
+    Added,
+    /// 之前有, 这次没有的 key
+    Removed,
+    /// key 出现了没见过的类型
+    TypeChanged,
+}
+
+impl DriftKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DriftKind::Added => "added",
+            DriftKind::Removed => "removed",
+            DriftKind::TypeChanged => "type_changed",
+        }
+    }
+
+    pub fn from_db(value: &str) -> Option<Self> {
+        match value {
+            "added" => Some(DriftKind::Added),
+            "removed" => Some(DriftKind::Removed),
+            "type_changed" => Some(DriftKind::TypeChanged),
+            _ => None,
+        }
+    }
+}
+
+/// schema_drift 表
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SchemaDrift {
+    pub id: i64,
+    pub source: String,
+    pub key: String,
+    pub change: DriftKind,
+    /// 之前见过的类型, 多个用 `|` 分隔
+    pub old_type: String,
+    pub new_type: String,
+    pub example_app_id: String,
+    pub created_at: DateTime<Local>,
+}
+
+/// key -> 类型
+pub type Profile = BTreeMap<String, String>;
+/// key -> 见过的所有类型
+pub type KnownProfile = BTreeMap<String, BTreeSet<String>>;
+
+/// JSON 值的类型名
+pub fn value_type(value: &JsonValue) -> &'static str {
+    match value {
+        JsonValue::Null => "null",
+        JsonValue::Bool(_) => "bool",
+        JsonValue::Number(_) => "number",
+        JsonValue::String(_) => "string",
+        JsonValue::Array(_) => "array",
+        JsonValue::Object(_) => "object",
+    }
+}
+
+/// 取出顶层 key 的类型
+pub fn json_profile(value: &JsonValue) -> Profile {
+    value
+        .as_object()
+        .map(|obj| {
+            obj.iter()
+                .map(|(k, v)| (k.clone(), value_type(v).to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// 对比已知结构和这次的结构
+pub fn diff_profile(
+    source: &str,
+    app_id: &str,
+    known: &KnownProfile,
+    profile: &Profile,
+) -> Vec<SchemaDrift> {
+    let mut drifts = Vec::new();
+    let make = |key: &str, change, old_type: String, new_type: &str| SchemaDrift {
+        id: 0,
+        source: source.to_string(),
+        key: key.to_string(),
+        change,
+        old_type,
+        new_type: new_type.to_string(),
+        example_app_id: app_id.to_string(),
+        created_at: Local::now(),
+    };
+    for (key, new_type) in profile {
+        match known.get(key) {
+            None => drifts.push(make(
+                key,
+                DriftKind::Added,
+                MISSING_TYPE.to_string(),
+                new_type,
+            )),
+            Some(types) if !types.contains(new_type) => drifts.push(make(
+                key,
+                DriftKind::TypeChanged,
+                join_types(types),
+                new_type,
+            )),
+            Some(_) => {}
+        }
+    }
+    for (key, types) in known {
+        if !profile.contains_key(key) {
+            drifts.push(make(
+                key,
+                DriftKind::Removed,
+                join_types(types),
+                MISSING_TYPE,
+            ));
+        }
+    }
+    drifts
+}
+
+fn join_types(types: &BTreeSet<String>) -> String {
+    types.iter().cloned().collect::<Vec<_>>().join("|")
+}
+
+/// 变化的去重 key: (source, key, 变化, 新类型)
+type DriftSignature = (String, String, DriftKind, String);
+
+fn drift_signature(drift: &SchemaDrift) -> DriftSignature {
+    (
+        drift.source.clone(),
+        drift.key.clone(),
+        drift.change,
+        drift.new_type.clone(),
+    )
+}
+
+#[derive(Default)]
+struct DriftState {
+    /// 是否已经从数据库加载过结构记录
+    loaded: bool,
+    /// source -> 已知结构, 只包含已经写进数据库的
+    profiles: HashMap<String, KnownProfile>,
+    /// 已经处理过的结构 (source + 结构哈希)
+    seen_shapes: HashSet<String>,
+    /// 等待写入数据库的新结构 (source, app_id, 结构)
+    pending: Vec<(String, String, Profile)>,
+    /// 已经写进数据库的变化, 避免重复写
+    recorded: HashSet<DriftSignature>,
+}
+
+/// 一次 flush 要写进数据库的内容
+#[derive(Debug, Default)]
+struct FlushPlan {
+    /// 新见到的类型 (source, key, 类型, app_id)
+    profiles: Vec<(String, String, String, String)>,
+    drifts: Vec<SchemaDrift>,
+}
+
+impl DriftState {
+    /// 按顺序对比等待写入的结构, 只算出要写的内容, 不修改状态
+    fn plan(&self, pending: &[(String, String, Profile)]) -> FlushPlan {
+        let mut working: HashMap<&str, KnownProfile> = HashMap::new();
+        let mut planned = HashSet::new();
+        let mut plan = FlushPlan::default();
+        for (source, app_id, profile) in pending {
+            let known = working
+                .entry(source)
+                .or_insert_with(|| self.profiles.get(source).cloned().unwrap_or_default());
+            // 第一次见到这个来源, 直接当作基准
+            let drifts = if known.is_empty() {
+                Vec::new()
+            } else {
+                diff_profile(source, app_id, known, profile)
+            };
+            for (key, value_type) in profile {
+                if known
+                    .entry(key.clone())
+                    .or_default()
+                    .insert(value_type.clone())
+                {
+                    plan.profiles.push((
+                        source.clone(),
+                        key.clone(),
+                        value_type.clone(),
+                        app_id.clone(),
+                    ));
+                }
+            }
+            for drift in drifts {
+                let signature = drift_signature(&drift);
+                if !self.recorded.contains(&signature) && planned.insert(signature) {
+                    plan.drifts.push(drift);
+                }
+            }
+        }
+        plan
+    }
+}
+
+pub struct DriftDetector {
+    state: Mutex<DriftState>,
+    /// 同时只有一个 flush 在写数据库
+    flushing: Mutex<()>,
+}
+
+impl DriftDetector {
+    /// 记录一次返回数据的结构
+    ///
+    /// 同样结构的数据只会处理一次, 真正的对比和写库在 [`Self::flush`] 里
+    pub async fn observe(&self, source: &str, app_id: &str, value: &JsonValue) {
+        let profile = json_profile(value);
+        let shape = format!(
+            "{source}:{}",
+            json_content_hash(&serde_json::to_value(&profile).unwrap_or_default())
+        );
+        let mut state = self.state.lock().await;
+        if state.seen_shapes.insert(shape) {
+            state
+                .pending
+                .push((source.to_string(), app_id.to_string(), profile));
+        }
+    }
+
+    /// 把新结构和已知结构对比, 并写入数据库
+    ///
+    /// 对比在锁里做, 写数据库的时候不持有锁. 已经有 flush 在写的时候直接返回,
+    /// 新结构由正在写的那个一起写掉. 写失败的结构放回去下次重新对比
+    pub async fn flush(&self, db: &Database) -> Result<()> {
+        let Ok(_flushing) = self.flushing.try_lock() else {
+            return Ok(());
+        };
+        let loaded = {
+            let state = self.state.lock().await;
+            if state.pending.is_empty() {
+                return Ok(());
+            }
+            state.loaded
+        };
+        if !loaded {
+            let rows = db.get_schema_profiles().await?;
+            let mut state = self.state.lock().await;
+            for (source, key, value_type) in rows {
+                state
+                    .profiles
+                    .entry(source)
+                    .or_default()
+                    .entry(key)
+                    .or_default()
+                    .insert(value_type);
+            }
+            state.loaded = true;
+        }
+
+        // 写的时候别的保存又加进来的结构也一起写掉
+        loop {
+            let (pending, plan) = {
+                let mut state = self.state.lock().await;
+                if state.pending.is_empty() {
+                    return Ok(());
+                }
+                let pending = std::mem::take(&mut state.pending);
+                let plan = state.plan(&pending);
+                (pending, plan)
+            };
+            if let Err(e) = self.write(db, &plan).await {
+                self.state.lock().await.pending.splice(0..0, pending);
+                return Err(e);
+            }
+        }
+    }
+
+    /// 写入数据库, 写成功一条才标记一条
+    ///
+    /// 先写变化再写类型, 类型没写进去的话下次对比还能算出同样的变化
+    async fn write(&self, db: &Database, plan: &FlushPlan) -> Result<()> {
+        for drift in &plan.drifts {
+            event!(
+                Level::WARN,
+                "{} 的数据结构发生变化: {} {} ({} -> {}), 例如 {}",
+                drift.source,
+                drift.key,
+                drift.change.as_str(),
+                drift.old_type,
+                drift.new_type,
+                drift.example_app_id
+            );
+            db.insert_schema_drift(drift).await?;
+            self.state
+                .lock()
+                .await
+                .recorded
+                .insert(drift_signature(drift));
+        }
+        for (source, key, value_type, app_id) in &plan.profiles {
+            db.insert_schema_profile(source, key, value_type, app_id)
+                .await?;
+            self.state
+                .lock()
+                .await
+                .profiles
+                .entry(source.clone())
+                .or_default()
+                .entry(key.clone())
+                .or_default()
+                .insert(value_type.clone());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_profile() {
+        let known: KnownProfile = [
+            ("appId", vec!["string"]),
+            ("tagName", vec!["string", "null"]),
+            ("hot", vec!["string"]),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.into_iter().map(String::from).collect()))
+        .collect();
+
+        let value = serde_json::json!({"appId": "C1", "tagName": null, "hot": 1, "newKey": []});
+        let drifts = diff_profile(SOURCE_APP_INFO, "C1", &known, &json_profile(&value));
+        assert_eq!(drifts.len(), 2);
+        assert!(drifts.iter().any(|d| d.key == "hot"
+            && d.change == DriftKind::TypeChanged
+            && d.new_type == "number"));
+        assert!(
+            drifts
+                .iter()
+                .any(|d| d.key == "newKey" && d.change == DriftKind::Added)
+        );
+
+        let value = serde_json::json!({"appId": "C1"});
+        let drifts = diff_profile(SOURCE_APP_INFO, "C1", &known, &json_profile(&value));
+        assert_eq!(drifts.len(), 2);
+        assert!(drifts.iter().all(|d| d.change == DriftKind::Removed));
+    }
+
+    #[test]
+    fn test_plan() {
+        let profile = |value: JsonValue| json_profile(&value);
+        let mut state = DriftState::default();
+        state.profiles.insert(
+            SOURCE_APP_INFO.to_string(),
+            [("a".to_string(), BTreeSet::from(["number".to_string()]))].into(),
+        );
+        let pending = vec![
+            (
+                SOURCE_APP_INFO.to_string(),
+                "C1".to_string(),
+                profile(serde_json::json!({"a": 1, "b": "x"})),
+            ),
+            (
+                SOURCE_APP_INFO.to_string(),
+                "C2".to_string(),
+                profile(serde_json::json!({"a": "1"})),
+            ),
+        ];
+        let plan = state.plan(&pending);
+        // 第二个结构和第一个结构里新出现的 b 对比
+        let changes: Vec<_> = plan
+            .drifts
+            .iter()
+            .map(|d| (d.key.as_str(), d.change, d.example_app_id.as_str()))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("b", DriftKind::Added, "C1"),
+                ("a", DriftKind::TypeChanged, "C2"),
+                ("b", DriftKind::Removed, "C2"),
+            ]
+        );
+        assert_eq!(plan.profiles.len(), 2);
+        // 对比不修改状态, 写失败之后重新对比还是同样的结果
+        assert!(state.recorded.is_empty());
+        assert_eq!(state.profiles[SOURCE_APP_INFO].len(), 1);
+
+        // 已经写过的变化不再写
+        state.recorded.insert(drift_signature(&plan.drifts[0]));
+        assert_eq!(state.plan(&pending).drifts.len(), 2);
+    }
+}
@@ -0,0 +1,460 @@
+//! 导出文件的格式
+//!
+//! 每一行先读成 [`Cell`], 再按格式编码到 [`SharedBuffer`] 里,
+//! 调用方隔一段时间把缓冲区里已经编码好的部分取走写出去, 所以不会攒下整个数据集
+
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Result, bail};
+use arrow_array::{
+    ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray,
+    TimestampMicrosecondArray,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use sqlx::{Row, postgres::PgRow};
+
+/// parquet 攒够这么多行写一个 RecordBatch
+const PARQUET_BATCH_ROWS: usize = 8192;
+/// parquet 一个 row group 的最大行数, ArrowWriter 会在内存里攒一个 row group
+const PARQUET_ROW_GROUP_ROWS: usize = 65536;
+
+/// 导出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Jsonl,
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Jsonl => "application/x-ndjson",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+}
+
+/// 列的类型, 由 postgres 的类型决定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnKind {
+    Text,
+    Int,
+    Float,
+    Bool,
+    Time,
+    /// json / jsonb / 数组, jsonl 里原样输出, 其他格式输出成 json 文本
+    Json,
+}
+
+#[derive(Debug, Clone)]
+pub struct Column {
+    pub name: String,
+    /// 查询结果里的 postgres 类型, 比如 `INT4` / `TEXT[]`
+    pub pg_type: String,
+    pub kind: ColumnKind,
+}
+
+impl Column {
+    /// 按原始的 postgres 类型创建, NUMERIC 会转成 FLOAT8, 不认识的类型转成 TEXT
+    pub fn from_pg(name: &str, pg_type: &str) -> Self {
+        let (pg_type, kind) = match pg_type {
+            "INT2" | "INT4" | "INT8" => (pg_type, ColumnKind::Int),
+            "FLOAT4" | "FLOAT8" => (pg_type, ColumnKind::Float),
+            "NUMERIC" => ("FLOAT8", ColumnKind::Float),
+            "BOOL" => (pg_type, ColumnKind::Bool),
+            "TIMESTAMPTZ" | "TIMESTAMP" | "DATE" => ("TIMESTAMPTZ", ColumnKind::Time),
+            "JSON" | "JSONB" | "TEXT[]" => (pg_type, ColumnKind::Json),
+            _ => ("TEXT", ColumnKind::Text),
+        };
+        Self {
+            name: name.to_string(),
+            pg_type: pg_type.to_string(),
+            kind,
+        }
+    }
+
+    /// SELECT 里的表达式, 需要转换类型的加上 cast
+    pub fn select_expr(&self, table: &str) -> String {
+        let ident = format!("{table}.\"{}\"", self.name.replace('"', "\"\""));
+        match self.pg_type.as_str() {
+            "FLOAT8" | "TIMESTAMPTZ" | "TEXT" => format!(
+                "{ident}::{} AS \"{}\"",
+                self.pg_type.to_lowercase(),
+                self.name
+            ),
+            _ => ident,
+        }
+    }
+
+    fn arrow_type(&self) -> DataType {
+        match self.kind {
+            ColumnKind::Text | ColumnKind::Json => DataType::Utf8,
+            ColumnKind::Int => DataType::Int64,
+            ColumnKind::Float => DataType::Float64,
+            ColumnKind::Bool => DataType::Boolean,
+            ColumnKind::Time => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+        }
+    }
+}
+
+/// 一个格子
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Null,
+    Text(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Time(DateTime<Utc>),
+    Json(JsonValue),
+}
+
+impl Cell {
+    fn to_json(&self) -> JsonValue {
+        match self {
+            Cell::Null => JsonValue::Null,
+            Cell::Text(s) => s.as_str().into(),
+            Cell::Int(v) => (*v).into(),
+            Cell::Float(v) => (*v).into(),
+            Cell::Bool(v) => (*v).into(),
+            Cell::Time(t) => t
+                .with_timezone(&Local)
+                .to_rfc3339_opts(SecondsFormat::AutoSi, true)
+                .into(),
+            Cell::Json(v) => v.clone(),
+        }
+    }
+
+    fn to_text(&self) -> Option<String> {
+        match self {
+            Cell::Null => None,
+            Cell::Text(s) => Some(s.clone()),
+            Cell::Json(v) => Some(v.to_string()),
+            cell => match cell.to_json() {
+                JsonValue::String(s) => Some(s),
+                v => Some(v.to_string()),
+            },
+        }
+    }
+}
+
+/// 按列的类型读取一行
+pub fn read_cells(row: &PgRow, columns: &[Column]) -> Result<Vec<Cell>> {
+    columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            let cell = match column.pg_type.as_str() {
+                "INT2" => row
+                    .try_get::<Option<i16>, _>(i)?
+                    .map(|v| Cell::Int(v.into())),
+                "INT4" => row
+                    .try_get::<Option<i32>, _>(i)?
+                    .map(|v| Cell::Int(v.into())),
+                "INT8" => row.try_get::<Option<i64>, _>(i)?.map(Cell::Int),
+                "FLOAT4" => row
+                    .try_get::<Option<f32>, _>(i)?
+                    .map(|v| Cell::Float(v.into())),
+                "FLOAT8" => row.try_get::<Option<f64>, _>(i)?.map(Cell::Float),
+                "BOOL" => row.try_get::<Option<bool>, _>(i)?.map(Cell::Bool),
+                "TIMESTAMPTZ" => row.try_get::<Option<DateTime<Utc>>, _>(i)?.map(Cell::Time),
+                "JSON" | "JSONB" => row.try_get::<Option<JsonValue>, _>(i)?.map(Cell::Json),
+                "TEXT[]" => row
+                    .try_get::<Option<Vec<String>>, _>(i)?
+                    .map(|v| Cell::Json(v.into())),
+                _ => row.try_get::<Option<String>, _>(i)?.map(Cell::Text),
+            };
+            Ok(cell.unwrap_or(Cell::Null))
+        })
+        .collect()
+}
+
+/// 可以共享的写入缓冲区, 编码器往里写, 调用方从里面取
+#[derive(Debug, Clone, Default)]
+pub struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Encoder {
+    Jsonl,
+    Csv(Box<csv::Writer<SharedBuffer>>),
+    Parquet {
+        writer: Box<ArrowWriter<SharedBuffer>>,
+        schema: SchemaRef,
+        rows: Vec<Vec<Cell>>,
+    },
+}
+
+/// 逐行编码
+pub struct RowEncoder {
+    columns: Vec<Column>,
+    buffer: SharedBuffer,
+    encoder: Encoder,
+}
+
+impl RowEncoder {
+    pub fn new(format: ExportFormat, columns: Vec<Column>) -> Result<Self> {
+        let buffer = SharedBuffer::default();
+        let encoder = match format {
+            ExportFormat::Jsonl => Encoder::Jsonl,
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(buffer.clone());
+                writer.write_record(columns.iter().map(|c| c.name.as_str()))?;
+                Encoder::Csv(Box::new(writer))
+            }
+            ExportFormat::Parquet => {
+                let schema: SchemaRef = Arc::new(Schema::new(
+                    columns
+                        .iter()
+                        .map(|c| Field::new(&c.name, c.arrow_type(), true))
+                        .collect::<Vec<_>>(),
+                ));
+                let props = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .set_max_row_group_size(PARQUET_ROW_GROUP_ROWS)
+                    .build();
+                let writer = ArrowWriter::try_new(buffer.clone(), schema.clone(), Some(props))?;
+                Encoder::Parquet {
+                    writer: Box::new(writer),
+                    schema,
+                    rows: Vec::with_capacity(PARQUET_BATCH_ROWS),
+                }
+            }
+        };
+        Ok(Self {
+            columns,
+            buffer,
+            encoder,
+        })
+    }
+
+    pub fn write_row(&mut self, cells: Vec<Cell>) -> Result<()> {
+        if cells.len() != self.columns.len() {
+            bail!("列数不对: {} != {}", cells.len(), self.columns.len());
+        }
+        match &mut self.encoder {
+            Encoder::Jsonl => {
+                // 手动拼, 保持列的顺序
+                let mut line = String::from("{");
+                for (i, (column, cell)) in self.columns.iter().zip(&cells).enumerate() {
+                    if i > 0 {
+                        line.push(',');
+                    }
+                    line.push_str(&serde_json::to_string(&column.name)?);
+                    line.push(':');
+                    line.push_str(&cell.to_json().to_string());
+                }
+                line.push_str("}\n");
+                self.buffer.write_all(line.as_bytes())?;
+            }
+            Encoder::Csv(writer) => {
+                writer.write_record(cells.iter().map(|c| c.to_text().unwrap_or_default()))?;
+            }
+            Encoder::Parquet { rows, .. } => {
+                rows.push(cells);
+                if rows.len() >= PARQUET_BATCH_ROWS {
+                    self.flush_parquet_batch()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn flush_parquet_batch(&mut self) -> Result<()> {
+        let Encoder::Parquet {
+            writer,
+            schema,
+            rows,
+        } = &mut self.encoder
+        else {
+            return Ok(());
+        };
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let rows = std::mem::take(rows);
+        let arrays = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| column_array(column.kind, rows.iter().map(|row| &row[i])))
+            .collect();
+        writer.write(&RecordBatch::try_new(schema.clone(), arrays)?)?;
+        Ok(())
+    }
+
+    /// 取走已经编码好的数据
+    pub fn take_output(&mut self) -> Result<Vec<u8>> {
+        if let Encoder::Csv(writer) = &mut self.encoder {
+            writer.flush()?;
+        }
+        Ok(self.buffer.take())
+    }
+
+    /// 已经编码好还没取走的字节数
+    pub fn pending(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// 结束编码, 返回剩下的数据
+    pub fn finish(mut self) -> Result<Vec<u8>> {
+        self.flush_parquet_batch()?;
+        match self.encoder {
+            Encoder::Jsonl => {}
+            Encoder::Csv(mut writer) => writer.flush()?,
+            Encoder::Parquet { writer, .. } => {
+                writer.close()?;
+            }
+        }
+        Ok(self.buffer.take())
+    }
+}
+
+fn column_array<'a>(kind: ColumnKind, cells: impl Iterator<Item = &'a Cell>) -> ArrayRef {
+    match kind {
+        ColumnKind::Int => Arc::new(Int64Array::from_iter(cells.map(|c| match c {
+            Cell::Int(v) => Some(*v),
+            _ => None,
+        }))),
+        ColumnKind::Float => Arc::new(Float64Array::from_iter(cells.map(|c| match c {
+            Cell::Float(v) => Some(*v),
+            Cell::Int(v) => Some(*v as f64),
+            _ => None,
+        }))),
+        ColumnKind::Bool => Arc::new(BooleanArray::from_iter(cells.map(|c| match c {
+            Cell::Bool(v) => Some(*v),
+            _ => None,
+        }))),
+        ColumnKind::Time => Arc::new(
+            TimestampMicrosecondArray::from_iter(cells.map(|c| match c {
+                Cell::Time(t) => Some(t.timestamp_micros()),
+                _ => None,
+            }))
+            .with_timezone_utc(),
+        ),
+        ColumnKind::Text | ColumnKind::Json => {
+            Arc::new(StringArray::from_iter(cells.map(Cell::to_text)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns() -> Vec<Column> {
+        vec![
+            Column::from_pg("app_id", "TEXT"),
+            Column::from_pg("download_count", "INT8"),
+            Column::from_pg("info_score", "NUMERIC"),
+            Column::from_pg("main_device_codes", "TEXT[]"),
+        ]
+    }
+
+    fn rows() -> Vec<Vec<Cell>> {
+        vec![
+            vec![
+                Cell::Text("C1".to_string()),
+                Cell::Int(100),
+                Cell::Float(4.5),
+                Cell::Json(serde_json::json!(["0", "1"])),
+            ],
+            vec![
+                Cell::Text("C2, \"x\"".to_string()),
+                Cell::Null,
+                Cell::Null,
+                Cell::Null,
+            ],
+        ]
+    }
+
+    fn encode(format: ExportFormat) -> Vec<u8> {
+        let mut encoder = RowEncoder::new(format, columns()).unwrap();
+        let mut output = Vec::new();
+        for row in rows() {
+            encoder.write_row(row).unwrap();
+            output.extend(encoder.take_output().unwrap());
+        }
+        output.extend(encoder.finish().unwrap());
+        output
+    }
+
+    #[test]
+    fn test_column_from_pg() {
+        let columns = columns();
+        assert_eq!(columns[2].kind, ColumnKind::Float);
+        assert_eq!(
+            columns[2].select_expr("t"),
+            "t.\"info_score\"::float8 AS \"info_score\""
+        );
+        assert_eq!(columns[1].select_expr("t"), "t.\"download_count\"");
+        assert_eq!(Column::from_pg("x", "INTERVAL").kind, ColumnKind::Text);
+    }
+
+    #[test]
+    fn test_encode_jsonl_and_csv() {
+        let jsonl = String::from_utf8(encode(ExportFormat::Jsonl)).unwrap();
+        assert_eq!(
+            jsonl,
+            "{\"app_id\":\"C1\",\"download_count\":100,\"info_score\":4.5,\"main_device_codes\":[\"0\",\"1\"]}\n\
+             {\"app_id\":\"C2, \\\"x\\\"\",\"download_count\":null,\"info_score\":null,\"main_device_codes\":null}\n"
+        );
+
+        let csv = String::from_utf8(encode(ExportFormat::Csv)).unwrap();
+        assert_eq!(
+            csv,
+            "app_id,download_count,info_score,main_device_codes\n\
+             C1,100,4.5,\"[\"\"0\"\",\"\"1\"\"]\"\n\
+             \"C2, \"\"x\"\"\",,,\n"
+        );
+    }
+
+    #[test]
+    fn test_encode_parquet() {
+        let data = encode(ExportFormat::Parquet);
+        assert!(data.starts_with(b"PAR1"));
+        assert!(data.ends_with(b"PAR1"));
+    }
+}
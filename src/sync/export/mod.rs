@@ -0,0 +1,176 @@
+//! 导出数据集给别人分析
+//!
+//! 数据集: 每个应用的最新信息 (app_latest_info), 完整的 app_metrics / app_rating 历史, 以及专题.
+//! 用 sqlx 的流式查询逐行读取, 编码好的数据边读边写出去, 整个数据集不会放在内存里
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use futures_util::TryStreamExt;
+use serde::Deserialize;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tracing::{Level, event};
+
+use crate::db::Database;
+
+pub mod format;
+
+pub use format::ExportFormat;
+use format::{Column, RowEncoder, read_cells};
+
+/// 缓冲区超过这么多字节就写出去
+const FLUSH_BYTES: usize = 64 * 1024;
+
+/// 应用的过滤条件, `ai` 是 app_info
+const APP_FILTER: &str = "($3::text IS NULL OR ai.kind_name = $3 OR ai.kind_type_name = $3) \
+    AND ($4::text IS NULL OR ai.developer_name = $4 OR ai.dev_id = $4)";
+
+/// 可以导出的数据集
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Dataset {
+    /// 每个应用的最新信息 (app_latest_info), 按上架时间过滤
+    Apps,
+    /// 完整的 app_metrics 历史
+    Metrics,
+    /// 完整的 app_rating 历史
+    Ratings,
+    /// 专题, 带上包含的 app_id
+    Substances,
+}
+
+impl Dataset {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Dataset::Apps => "apps",
+            Dataset::Metrics => "metrics",
+            Dataset::Ratings => "ratings",
+            Dataset::Substances => "substances",
+        }
+    }
+
+    /// 原始查询
+    fn base_sql(&self) -> &'static str {
+        match self {
+            Dataset::Apps => "SELECT * FROM app_latest_info",
+            Dataset::Metrics => "SELECT * FROM app_metrics",
+            Dataset::Ratings => "SELECT * FROM app_rating",
+            Dataset::Substances => {
+                "SELECT si.*, ARRAY(
+                    SELECT m.app_id FROM substance_app_map m
+                    WHERE m.substance_id = si.substance_id ORDER BY m.app_id
+                ) AS app_ids FROM substance_info si"
+            }
+        }
+    }
+
+    /// 按时间过滤的列
+    fn time_column(&self) -> &'static str {
+        match self {
+            Dataset::Apps => "listed_at",
+            _ => "created_at",
+        }
+    }
+
+    fn order_by(&self) -> &'static str {
+        match self {
+            Dataset::Apps => "t.app_id",
+            Dataset::Metrics | Dataset::Ratings => "t.id",
+            Dataset::Substances => "t.substance_id",
+        }
+    }
+
+    /// 按应用过滤, 专题只要包含一个符合条件的应用就算
+    fn app_filter(&self) -> String {
+        let matched = match self {
+            Dataset::Substances => format!(
+                "EXISTS (SELECT 1 FROM substance_app_map m JOIN app_info ai ON ai.app_id = m.app_id \
+                 WHERE m.substance_id = t.substance_id AND {APP_FILTER})"
+            ),
+            _ => format!("t.app_id IN (SELECT ai.app_id FROM app_info ai WHERE {APP_FILTER})"),
+        };
+        format!("(($3::text IS NULL AND $4::text IS NULL) OR {matched})")
+    }
+
+    /// 完整的查询, `$1`/`$2` 是时间范围, `$3` 是分类, `$4` 是开发者
+    fn sql(&self, select: &str) -> String {
+        let time = self.time_column();
+        format!(
+            "SELECT {select} FROM ({}) t \
+             WHERE ($1::timestamptz IS NULL OR t.{time} >= $1) \
+             AND ($2::timestamptz IS NULL OR t.{time} < $2) \
+             AND {} \
+             ORDER BY {}",
+            self.base_sql(),
+            self.app_filter(),
+            self.order_by()
+        )
+    }
+}
+
+/// 导出参数
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub dataset: Dataset,
+    pub format: ExportFormat,
+    /// 包含
+    pub since: Option<DateTime<Local>>,
+    /// 不包含
+    pub until: Option<DateTime<Local>>,
+    /// 应用分类, kind_name 或者 kind_type_name (比如 `工具` / `游戏`)
+    pub kind: Option<String>,
+    /// 开发者名称或者 dev_id
+    pub developer: Option<String>,
+}
+
+impl ExportOptions {
+    pub fn file_name(&self) -> String {
+        format!("{}.{}", self.dataset.name(), self.format.extension())
+    }
+}
+
+/// 导出到 `out`, 返回行数
+pub async fn export<W: AsyncWrite + Unpin>(
+    db: &Database,
+    options: &ExportOptions,
+    out: &mut W,
+) -> Result<u64> {
+    let dataset = options.dataset;
+    let columns: Vec<Column> = db
+        .describe_columns(&dataset.sql("*"))
+        .await
+        .with_context(|| format!("无法获取 {} 的列", dataset.name()))?
+        .iter()
+        .map(|(name, pg_type)| Column::from_pg(name, pg_type))
+        .collect();
+    let select = columns
+        .iter()
+        .map(|c| c.select_expr("t"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let sql = dataset.sql(&select);
+
+    let mut encoder = RowEncoder::new(options.format, columns.clone())?;
+    let mut rows = sqlx::query(&sql)
+        .bind(options.since)
+        .bind(options.until)
+        .bind(&options.kind)
+        .bind(&options.developer)
+        .fetch(&db.pool);
+    let mut count = 0u64;
+    while let Some(row) = rows.try_next().await? {
+        encoder.write_row(read_cells(&row, &columns)?)?;
+        count += 1;
+        if encoder.pending() >= FLUSH_BYTES {
+            out.write_all(&encoder.take_output()?).await?;
+        }
+    }
+    out.write_all(&encoder.take_output()?).await?;
+    out.write_all(&encoder.finish()?).await?;
+    out.flush().await?;
+    event!(
+        Level::INFO,
+        "导出 {} 完成, 共 {count} 行",
+        options.file_name()
+    );
+    Ok(count)
+}
@@ -0,0 +1,237 @@
+//! 按命中密度自适应的猜测
+//!
+//! 把 ID 空间按 bucket_size 分桶, 每个桶的权重由已知 app_id 数量和之前的猜测结果决定,
+//! 命中多的桶多猜, 一直猜不中的桶慢慢少猜.
+//! 每个桶的猜测次数, 命中次数和扫描位置保存在 guess_density 表里, 下次继续用
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Mutex,
+};
+
+use anyhow::Result;
+
+use crate::{
+    db::Database,
+    model::AppQuery,
+    sync::guess::{
+        ProbeOutcome,
+        job::{Random, parse_app_id},
+    },
+};
+
+/// 没有任何命中时, 权重的分母里加上的先验次数
+const PRIOR_PROBES: f64 = 10.0;
+
+/// guess_density 表里的一行
+#[derive(Debug, Clone, Default, PartialEq, Eq, sqlx::FromRow)]
+pub struct BucketStats {
+    pub prefix: String,
+    pub bucket: i64,
+    pub probes: i64,
+    pub hits: i64,
+    /// 桶内已经扫描到的位置
+    pub scanned_to: i64,
+}
+
+#[derive(Debug, Default)]
+struct Bucket {
+    /// 已知的 app_id 在桶内的位置
+    known: HashSet<u64>,
+    probes: u64,
+    hits: u64,
+    scanned_to: u64,
+}
+
+impl Bucket {
+    /// 权重: (已知数量 + 命中 + 1) / (猜测次数 + 先验)
+    fn weight(&self) -> f64 {
+        (self.known.len() as f64 + self.hits as f64 + 1.0) / (self.probes as f64 + PRIOR_PROBES)
+    }
+}
+
+struct DensityState {
+    buckets: BTreeMap<(String, u64), Bucket>,
+    dirty: HashSet<(String, u64)>,
+    rng: Random,
+}
+
+/// 命中密度模型
+pub struct DensityModel {
+    pub bucket_size: u64,
+    state: Mutex<DensityState>,
+}
+
+impl DensityModel {
+    /// 用已知 app_id 和之前的猜测结果建立模型
+    ///
+    /// 只考虑有已知 app_id 的桶和它们前后 spread 个桶
+    pub fn new(known: &[String], bucket_size: u64, spread: u64, history: &[BucketStats]) -> Self {
+        let bucket_size = bucket_size.max(1);
+        let mut buckets: BTreeMap<(String, u64), Bucket> = BTreeMap::new();
+        for app_id in known {
+            let Some((prefix, num)) = parse_app_id(app_id) else {
+                continue;
+            };
+            let bucket = num / bucket_size;
+            for neighbor in bucket.saturating_sub(spread)..=bucket.saturating_add(spread) {
+                buckets.entry((prefix.clone(), neighbor)).or_default();
+            }
+            buckets
+                .entry((prefix, bucket))
+                .or_default()
+                .known
+                .insert(num % bucket_size);
+        }
+        for stats in history {
+            if let Some(bucket) = buckets.get_mut(&(stats.prefix.clone(), stats.bucket as u64)) {
+                bucket.probes = stats.probes as u64;
+                bucket.hits = stats.hits as u64;
+                bucket.scanned_to = stats.scanned_to as u64;
+            }
+        }
+        Self {
+            bucket_size,
+            state: Mutex::new(DensityState {
+                buckets,
+                dirty: HashSet::new(),
+                rng: Random::new(),
+            }),
+        }
+    }
+
+    /// 按权重选一个桶, 返回桶里下一个没猜过的 ID
+    ///
+    /// 所有桶都扫完了返回 None
+    pub fn next_query(&self) -> Option<AppQuery> {
+        let mut state = self.state.lock().unwrap();
+        let bucket_size = self.bucket_size;
+        loop {
+            let candidates = state
+                .buckets
+                .iter()
+                .filter(|(_, bucket)| bucket.scanned_to < bucket_size)
+                .map(|(key, bucket)| (key.clone(), bucket.weight()))
+                .collect::<Vec<_>>();
+            let total_weight = candidates.iter().map(|(_, w)| w).sum::<f64>();
+            if candidates.is_empty() || total_weight <= 0.0 {
+                return None;
+            }
+            let mut pick = (state.rng.next() >> 11) as f64 / (1u64 << 53) as f64 * total_weight;
+            let mut chosen = candidates[candidates.len() - 1].0.clone();
+            for (key, weight) in candidates {
+                if pick < weight {
+                    chosen = key;
+                    break;
+                }
+                pick -= weight;
+            }
+
+            let bucket = state.buckets.get_mut(&chosen)?;
+            // 跳过已知的 ID
+            while bucket.scanned_to < bucket_size && bucket.known.contains(&bucket.scanned_to) {
+                bucket.scanned_to += 1;
+            }
+            if bucket.scanned_to >= bucket_size {
+                state.dirty.insert(chosen);
+                continue;
+            }
+            let id = chosen.1 * bucket_size + bucket.scanned_to;
+            bucket.scanned_to += 1;
+            state.dirty.insert(chosen.clone());
+            return Some(AppQuery::app_id(format!("{}{id}", chosen.0)));
+        }
+    }
+
+    /// 记录一次猜测结果
+    ///
+    /// 出错的不算, 因为不知道到底有没有
+    pub fn record(&self, app_query: &AppQuery, outcome: ProbeOutcome) {
+        if outcome == ProbeOutcome::Error {
+            return;
+        }
+        let Some((prefix, num)) = parse_app_id(app_query.name()) else {
+            return;
+        };
+        let key = (prefix, num / self.bucket_size);
+        let mut state = self.state.lock().unwrap();
+        if let Some(bucket) = state.buckets.get_mut(&key) {
+            bucket.probes += 1;
+            if outcome == ProbeOutcome::Hit {
+                bucket.hits += 1;
+            }
+            state.dirty.insert(key);
+        }
+    }
+
+    /// 取出有变化的桶
+    pub fn take_dirty(&self) -> Vec<BucketStats> {
+        let mut state = self.state.lock().unwrap();
+        let dirty = std::mem::take(&mut state.dirty);
+        dirty
+            .into_iter()
+            .filter_map(|key| {
+                let bucket = state.buckets.get(&key)?;
+                Some(BucketStats {
+                    prefix: key.0,
+                    bucket: key.1 as i64,
+                    probes: bucket.probes as i64,
+                    hits: bucket.hits as i64,
+                    scanned_to: bucket.scanned_to as i64,
+                })
+            })
+            .collect()
+    }
+
+    /// 把有变化的桶写回数据库
+    pub async fn flush(&self, db: &Database) -> Result<()> {
+        let dirty = self.take_dirty();
+        if dirty.is_empty() {
+            return Ok(());
+        }
+        db.upsert_guess_density(self.bucket_size, &dirty).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_density_model() {
+        let known = ["C105", "C107"]
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let model = DensityModel::new(&known, 10, 1, &[]);
+
+        let mut seen = HashSet::new();
+        while let Some(query) = model.next_query() {
+            let name = query.name().to_string();
+            assert!(seen.insert(name.clone()), "{name} 被猜了两次");
+            model.record(&query, ProbeOutcome::Miss);
+        }
+        // 桶 9, 10, 11 一共 30 个, 去掉已知的 2 个
+        assert_eq!(seen.len(), 28);
+        assert!(!seen.contains("C105"));
+
+        let dirty = model.take_dirty();
+        assert_eq!(dirty.len(), 3);
+        assert!(dirty.iter().all(|b| b.scanned_to == 10));
+        assert_eq!(dirty.iter().map(|b| b.probes).sum::<i64>(), 28);
+    }
+
+    #[test]
+    fn test_bucket_weight() {
+        let dense = Bucket {
+            known: [1, 2, 3].into_iter().collect(),
+            ..Default::default()
+        };
+        let empty = Bucket {
+            probes: 1000,
+            ..Default::default()
+        };
+        assert!(dense.weight() > Bucket::default().weight());
+        assert!(Bucket::default().weight() > empty.weight());
+    }
+}
@@ -0,0 +1,723 @@
+//! 猜测任务的定义
+//!
+//! 同一份结构既可以从命令行参数解析, 也可以从 toml 任务文件读取
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result, ensure};
+use clap::{Args, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::Database,
+    model::{AppQuery, is_atomic_service},
+    sync::guess::{
+        density::DensityModel,
+        template::{Template, mine_templates},
+    },
+};
+
+fn default_prefix() -> String {
+    "C".to_string()
+}
+
+fn default_radius() -> u64 {
+    1000
+}
+
+fn default_bucket_size() -> u64 {
+    10000
+}
+
+fn default_spread() -> u64 {
+    1
+}
+
+fn default_min_support() -> usize {
+    5
+}
+
+fn default_margin() -> u64 {
+    50
+}
+
+fn default_max_span() -> u64 {
+    100000
+}
+
+fn default_batch() -> usize {
+    1000
+}
+
+fn default_delay_ms() -> u64 {
+    25
+}
+
+fn default_recheck_days() -> u64 {
+    30
+}
+
+/// 一个完整的猜测任务
+///
+/// ```toml
+/// [scan]
+/// mode = "range"
+/// prefix = "C576588020785"
+/// start = 2000000
+/// end = 6390000
+/// width = 7
+///
+/// [options]
+/// batch = 1000
+/// delay_ms = 25
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuessJob {
+    pub scan: GuessMode,
+    #[serde(default)]
+    pub options: GuessOptions,
+}
+
+impl GuessJob {
+    /// 从 toml 任务文件读取
+    pub fn load(path: &str) -> Result<Self> {
+        let content =
+            std::fs::read_to_string(path).with_context(|| format!("无法读取任务文件 {path}"))?;
+        let job: Self =
+            toml::from_str(&content).with_context(|| format!("无法解析任务文件 {path}"))?;
+        job.scan
+            .check()
+            .with_context(|| format!("任务文件 {path} 不正确"))?;
+        Ok(job)
+    }
+}
+
+/// 猜测方式
+#[derive(Debug, Clone, Serialize, Deserialize, Subcommand)]
+#[serde(tag = "mode", rename_all = "kebab-case")]
+pub enum GuessMode {
+    /// 按顺序扫描 prefix + [start, end]
+    Range {
+        #[arg(long)]
+        prefix: String,
+        #[arg(long)]
+        start: u64,
+        #[arg(long)]
+        end: u64,
+        /// 数字部分补零到的宽度, 0 表示不补
+        #[arg(long, default_value_t = 0)]
+        #[serde(default)]
+        width: usize,
+    },
+    /// 在 prefix + [start, end] 里随机取 ID
+    Random {
+        #[arg(long)]
+        prefix: String,
+        #[arg(long)]
+        start: u64,
+        #[arg(long)]
+        end: u64,
+        #[arg(long, default_value_t = 0)]
+        #[serde(default)]
+        width: usize,
+        /// 一共要猜多少个, 不填就一直猜下去
+        #[arg(long)]
+        count: Option<u64>,
+    },
+    /// 扫描数据库里已知 app_id 附近的 ID
+    AroundKnown {
+        /// 只看这个前缀开头的 app_id
+        #[arg(long)]
+        prefix: String,
+        /// 每个已知 ID 前后各扫描多少个
+        #[arg(long, default_value_t = default_radius())]
+        #[serde(default = "default_radius")]
+        radius: u64,
+        /// 合并后的范围, 由 [`GuessMode::resolve`] 填充
+        ///
+        /// 和任务一起保存, 这样恢复的时候不会因为数据库里多了新 ID 而对不上
+        #[arg(skip)]
+        #[serde(default)]
+        ranges: Option<Vec<(String, u64, u64)>>,
+    },
+    /// 扫描数据库里已知元服务 `com.atomicservice.<数字>` 附近的包名
+    Atomic {
+        /// 每个已知元服务前后各扫描多少个
+        #[arg(long, default_value_t = default_radius())]
+        #[serde(default = "default_radius")]
+        radius: u64,
+        /// 合并后的范围, 由 [`GuessMode::resolve`] 填充
+        #[arg(skip)]
+        #[serde(default)]
+        ranges: Option<Vec<(String, u64, u64)>>,
+    },
+    /// 扫描 prefix + [middle - radius, middle + radius)
+    Large {
+        #[arg(long, default_value_t = default_prefix())]
+        #[serde(default = "default_prefix")]
+        prefix: String,
+        #[arg(long)]
+        middle: u64,
+        #[arg(long)]
+        radius: u64,
+    },
+    /// 按已知 app_id 和之前的猜测结果估计命中密度, 多猜密集的区域
+    Adaptive {
+        /// 只看这个前缀开头的 app_id
+        #[arg(long)]
+        prefix: String,
+        /// 每个桶的大小
+        #[arg(long, default_value_t = default_bucket_size())]
+        #[serde(default = "default_bucket_size")]
+        bucket_size: u64,
+        /// 已知 app_id 所在的桶前后各考虑多少个桶
+        #[arg(long, default_value_t = default_spread())]
+        #[serde(default = "default_spread")]
+        spread: u64,
+        /// 一共要猜多少个, 不填就一直猜到所有桶都扫完
+        #[arg(long)]
+        count: Option<u64>,
+    },
+    /// 按模板生成, 模板语法见 [`super::template`]
+    Pattern {
+        /// 例如 `com.appbyme.app{7372..43537}.hm`
+        #[arg(long)]
+        template: String,
+        /// 生成的是包名还是 app_id
+        #[arg(long, value_enum, default_value_t = PatternTarget::PkgName)]
+        #[serde(default)]
+        target: PatternTarget,
+    },
+    /// 从数据库里已有的包名挖出模板, 依次展开
+    Mined {
+        /// 至少有多少个包名符合才生成模板
+        #[arg(long, default_value_t = default_min_support())]
+        #[serde(default = "default_min_support")]
+        min_support: usize,
+        /// 已知数字范围前后各多扫描多少个
+        #[arg(long, default_value_t = default_margin())]
+        #[serde(default = "default_margin")]
+        margin: u64,
+        /// 单个模板数字范围的上限, 超过的不要
+        #[arg(long, default_value_t = default_max_span())]
+        #[serde(default = "default_max_span")]
+        max_span: u64,
+        /// 挖出来的模板, 由 [`GuessMode::resolve`] 填充
+        #[arg(skip)]
+        #[serde(default)]
+        templates: Option<Vec<String>>,
+    },
+}
+
+/// 模板生成的是什么
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum PatternTarget {
+    #[default]
+    PkgName,
+    AppId,
+}
+
+impl PatternTarget {
+    fn query(&self, value: String) -> AppQuery {
+        match self {
+            PatternTarget::PkgName => AppQuery::pkg_name(value),
+            PatternTarget::AppId => AppQuery::app_id(value),
+        }
+    }
+}
+
+/// 通用的执行参数
+#[derive(Debug, Clone, Serialize, Deserialize, Args)]
+pub struct GuessOptions {
+    /// 每处理多少个 ID 保存一次断点
+    #[arg(long, default_value_t = default_batch())]
+    #[serde(default = "default_batch")]
+    pub batch: usize,
+    /// 同时进行的请求数量上限, 不填就是 batch
+    #[arg(long)]
+    #[serde(default)]
+    pub concurrency: Option<usize>,
+    /// 每发出 batch 个请求暂停的毫秒数
+    #[arg(long, default_value_t = default_delay_ms())]
+    #[serde(default = "default_delay_ms")]
+    pub delay_ms: u64,
+    /// 每秒最多发出多少个请求, 不填就不限
+    #[arg(long)]
+    #[serde(default)]
+    pub max_rps: Option<u32>,
+    /// 写到 comment 里的 user 标记, 不填就是 guess_<mode>
+    #[arg(long)]
+    #[serde(default)]
+    pub comment_tag: Option<String>,
+    /// 确认不存在的 ID 多少天之后重新猜, 0 表示不跳过
+    #[arg(long, default_value_t = default_recheck_days())]
+    #[serde(default = "default_recheck_days")]
+    pub recheck_days: u64,
+}
+
+impl Default for GuessOptions {
+    fn default() -> Self {
+        Self {
+            batch: default_batch(),
+            concurrency: None,
+            delay_ms: default_delay_ms(),
+            max_rps: None,
+            comment_tag: None,
+            recheck_days: default_recheck_days(),
+        }
+    }
+}
+
+impl GuessOptions {
+    pub fn concurrency(&self) -> usize {
+        self.concurrency.unwrap_or(self.batch).max(1)
+    }
+}
+
+/// 要猜的 ID 序列
+pub struct QueryPlan {
+    pub queries: Box<dyn Iterator<Item = AppQuery> + Send>,
+    /// 总数, 无限的随机猜测为 None
+    pub total: Option<u64>,
+    /// adaptive 的密度模型, 猜测结果需要反馈给它
+    pub density: Option<Arc<DensityModel>>,
+}
+
+impl QueryPlan {
+    fn new(queries: impl Iterator<Item = AppQuery> + Send + 'static, total: Option<u64>) -> Self {
+        Self {
+            queries: Box::new(queries),
+            total,
+            density: None,
+        }
+    }
+}
+
+impl GuessMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GuessMode::Range { .. } => "range",
+            GuessMode::Random { .. } => "random",
+            GuessMode::AroundKnown { .. } => "around_known",
+            GuessMode::Large { .. } => "large",
+            GuessMode::Pattern { .. } => "pattern",
+            GuessMode::Adaptive { .. } => "adaptive",
+            GuessMode::Mined { .. } => "mined",
+            GuessMode::Atomic { .. } => "atomic",
+        }
+    }
+
+    /// 检查参数, range / random 的范围不能反过来, 大小也不能超过 u64
+    pub fn check(&self) -> Result<()> {
+        match self {
+            GuessMode::Range { start, end, .. } | GuessMode::Random { start, end, .. } => {
+                range_size(*start, *end).map(|_| ())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 把需要查数据库的部分固定下来
+    ///
+    /// around-known / atomic 的范围和 mined 的模板需要
+    pub async fn resolve(&mut self, db: &Database) -> Result<()> {
+        if let GuessMode::Atomic {
+            radius,
+            ranges: ranges @ None,
+        } = self
+        {
+            let known = db
+                .get_all_pkg_names()
+                .await?
+                .into_iter()
+                .filter(|name| is_atomic_service(name))
+                .collect::<Vec<_>>();
+            println!("从数据库获取到 {} 个元服务", known.len());
+            let merged = around_ranges(&known, *radius);
+            println!("合并后得到 {} 个范围", merged.len());
+            *ranges = Some(merged);
+        }
+        if let GuessMode::Mined {
+            min_support,
+            margin,
+            max_span,
+            templates: templates @ None,
+        } = self
+        {
+            let pkg_names = db.get_all_pkg_names().await?;
+            let mined = mine_templates(&pkg_names, *min_support, *margin, *max_span);
+            println!("从 {} 个包名里挖出 {} 个模板", pkg_names.len(), mined.len());
+            for template in &mined {
+                println!("  {} ({} 个已知)", template.template, template.examples);
+            }
+            *templates = Some(mined.into_iter().map(|t| t.template).collect());
+        }
+        if let GuessMode::AroundKnown {
+            prefix,
+            radius,
+            ranges: ranges @ None,
+        } = self
+        {
+            let known = db
+                .get_all_app_ids()
+                .await?
+                .into_iter()
+                .filter(|id| id.starts_with(prefix.as_str()))
+                .collect::<Vec<_>>();
+            println!("从数据库获取到 {} 个 {prefix} 开头的 app_id", known.len());
+            let merged = around_ranges(&known, *radius);
+            println!("合并后得到 {} 个范围", merged.len());
+            *ranges = Some(merged);
+        }
+        Ok(())
+    }
+
+    /// 生成要猜的 ID 序列, 跳过前 offset 个
+    ///
+    /// adaptive 需要从数据库加载密度模型, 其他的都交给 [`Self::fixed_plan`]
+    pub async fn plan(&self, db: &Database, offset: u64) -> Result<QueryPlan> {
+        let GuessMode::Adaptive {
+            prefix,
+            bucket_size,
+            spread,
+            count,
+        } = self
+        else {
+            return self.fixed_plan(offset);
+        };
+
+        let known = db
+            .get_all_app_ids()
+            .await?
+            .into_iter()
+            .filter(|id| id.starts_with(prefix.as_str()))
+            .collect::<Vec<_>>();
+        let history = db.get_guess_density(*bucket_size).await?;
+        let model = Arc::new(DensityModel::new(&known, *bucket_size, *spread, &history));
+        println!(
+            "从数据库获取到 {} 个 {prefix} 开头的 app_id, {} 条历史猜测记录",
+            known.len(),
+            history.len()
+        );
+
+        let generator = model.clone();
+        let queries = std::iter::from_fn(move || generator.next_query());
+        let mut plan = match count {
+            Some(count) => QueryPlan::new(
+                queries.take(count.saturating_sub(offset) as usize),
+                Some(*count),
+            ),
+            None => QueryPlan::new(queries, None),
+        };
+        plan.density = Some(model);
+        Ok(plan)
+    }
+
+    /// 生成固定的 ID 序列, 跳过前 offset 个
+    ///
+    /// 都是惰性生成的, around-known 需要先 [`Self::resolve`]
+    pub fn fixed_plan(&self, offset: u64) -> Result<QueryPlan> {
+        let plan = match self.clone() {
+            GuessMode::Range {
+                prefix,
+                start,
+                end,
+                width,
+            } => {
+                let size = range_size(start, end)?;
+                QueryPlan::new(
+                    (start.saturating_add(offset)..=end)
+                        .map(move |id| AppQuery::app_id(format_id(&prefix, id, width))),
+                    Some(size),
+                )
+            }
+            GuessMode::Random {
+                prefix,
+                start,
+                end,
+                width,
+                count,
+            } => {
+                let size = range_size(start, end)?;
+                let mut rng = Random::new();
+                let queries = std::iter::repeat_with(move || {
+                    AppQuery::app_id(format_id(&prefix, start + rng.next() % size, width))
+                });
+                match count {
+                    Some(count) => QueryPlan::new(
+                        queries.take(count.saturating_sub(offset) as usize),
+                        Some(count),
+                    ),
+                    None => QueryPlan::new(queries, None),
+                }
+            }
+            GuessMode::AroundKnown { ranges, .. } => {
+                let ranges =
+                    ranges.ok_or_else(|| anyhow::anyhow!("around-known 还没有生成范围"))?;
+                ranges_plan(ranges, offset, AppQuery::app_id)
+            }
+            GuessMode::Atomic { ranges, .. } => {
+                let ranges = ranges.ok_or_else(|| anyhow::anyhow!("atomic 还没有生成范围"))?;
+                ranges_plan(ranges, offset, AppQuery::pkg_name)
+            }
+            GuessMode::Large {
+                prefix,
+                middle,
+                radius,
+            } => {
+                let range = middle.saturating_sub(radius)..middle.saturating_add(radius);
+                QueryPlan::new(
+                    (range.start.saturating_add(offset)..range.end)
+                        .map(move |id| AppQuery::app_id(format!("{prefix}{id}"))),
+                    Some(range.end - range.start),
+                )
+            }
+            GuessMode::Pattern { template, target } => {
+                let template = Template::parse(&template)?;
+                let total = template.len();
+                QueryPlan::new(
+                    template
+                        .iter_from(offset)
+                        .map(move |value| target.query(value)),
+                    Some(total),
+                )
+            }
+            GuessMode::Mined { templates, .. } => {
+                let templates = templates
+                    .ok_or_else(|| anyhow::anyhow!("mined 还没有挖出模板"))?
+                    .iter()
+                    .map(|t| Template::parse(t))
+                    .collect::<Result<Vec<_>>>()?;
+                let total = templates.iter().map(Template::len).sum();
+                QueryPlan::new(
+                    templates
+                        .into_iter()
+                        .flat_map(|t| t.iter_from(0))
+                        .skip(offset as usize)
+                        .map(AppQuery::pkg_name),
+                    Some(total),
+                )
+            }
+            GuessMode::Adaptive { .. } => anyhow::bail!("adaptive 需要数据库里的密度模型"),
+        };
+        Ok(plan)
+    }
+}
+
+/// 依次扫描每个 (前缀, start, end) 范围
+fn ranges_plan(
+    ranges: Vec<(String, u64, u64)>,
+    offset: u64,
+    to_query: fn(String) -> AppQuery,
+) -> QueryPlan {
+    let total = ranges.iter().map(|(_, s, e)| e - s + 1).sum();
+    QueryPlan::new(
+        ranges
+            .into_iter()
+            .flat_map(move |(prefix, start, end)| {
+                (start..=end).map(move |id| to_query(format!("{prefix}{id}")))
+            })
+            .skip(offset as usize),
+        Some(total),
+    )
+}
+
+/// 前缀 + 补零后的数字
+/// [start, end] 里一共有多少个 ID
+fn range_size(start: u64, end: u64) -> Result<u64> {
+    ensure!(start <= end, "start {start} 不能大于 end {end}");
+    (end - start)
+        .checked_add(1)
+        .with_context(|| format!("范围 {start}..={end} 太大"))
+}
+
+pub fn format_id(prefix: &str, id: u64, width: usize) -> String {
+    format!("{prefix}{id:0width$}")
+}
+
+/// 拆分 app_id 的前缀和数字部分
+///
+/// "C5765880207856366961" -> ("C", 5765880207856366961)
+pub fn parse_app_id(app_id: &str) -> Option<(String, u64)> {
+    let first_digit_pos = app_id.find(|c: char| c.is_ascii_digit())?;
+    let last_digit_pos = app_id.rfind(|c: char| c.is_ascii_digit())?;
+
+    let prefix = app_id[..first_digit_pos].to_string();
+    let numeric_str = &app_id[first_digit_pos..=last_digit_pos];
+
+    numeric_str.parse().ok().map(|num| (prefix, num))
+}
+
+/// 已知 app_id 前后各 radius 个, 合并重叠的范围
+pub fn around_ranges(known: &[String], radius: u64) -> Vec<(String, u64, u64)> {
+    let mut ranges_by_prefix: HashMap<String, Vec<(u64, u64)>> = HashMap::new();
+    for app_id in known {
+        if let Some((prefix, num)) = parse_app_id(app_id) {
+            ranges_by_prefix
+                .entry(prefix)
+                .or_default()
+                .push((num.saturating_sub(radius), num.saturating_add(radius)));
+        }
+    }
+
+    let mut merged_ranges = Vec::new();
+    for (prefix, mut ranges) in ranges_by_prefix {
+        ranges.sort_by_key(|&(start, _)| start);
+        let mut current = ranges[0];
+        for &range in &ranges[1..] {
+            if range.0 <= current.1.saturating_add(1) {
+                current.1 = current.1.max(range.1);
+            } else {
+                merged_ranges.push((prefix.clone(), current.0, current.1));
+                current = range;
+            }
+        }
+        merged_ranges.push((prefix, current.0, current.1));
+    }
+    merged_ranges.sort();
+    merged_ranges
+}
+
+#[derive(Clone)]
+pub(crate) struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub(crate) fn new() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        Self { state: seed }
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.state = self
+            .state
+            .wrapping_mul(1664525u64)
+            .wrapping_add(1013904223u64);
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_around_ranges() {
+        let known = ["C100", "C150", "C1000", "D5"]
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let ranges = around_ranges(&known, 30);
+        assert_eq!(
+            ranges,
+            vec![
+                ("C".to_string(), 70, 180),
+                ("C".to_string(), 970, 1030),
+                ("D".to_string(), 0, 35),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_offset() {
+        let mode = GuessMode::Range {
+            prefix: "C".to_string(),
+            start: 10,
+            end: 19,
+            width: 3,
+        };
+        let plan = mode.fixed_plan(4).unwrap();
+        assert_eq!(plan.total, Some(10));
+        let names = plan
+            .queries
+            .map(|q| q.name().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names.len(), 6);
+        assert_eq!(names[0], "C014");
+
+        let mode = GuessMode::AroundKnown {
+            prefix: "C".to_string(),
+            radius: 1,
+            ranges: Some(vec![("C".to_string(), 1, 3), ("C".to_string(), 7, 9)]),
+        };
+        let first = mode.fixed_plan(4).unwrap().queries.next().unwrap();
+        assert_eq!(first.name(), "C8");
+
+        let mode = GuessMode::Atomic {
+            radius: 1,
+            ranges: Some(vec![("com.atomicservice.".to_string(), 41, 43)]),
+        };
+        let first = mode.fixed_plan(1).unwrap().queries.next().unwrap();
+        assert_eq!(first.name(), "com.atomicservice.42");
+        assert_eq!(first.app_db_name(), "pkg_name");
+    }
+
+    #[test]
+    fn test_range_check() {
+        let range = |start, end| GuessMode::Range {
+            prefix: "C".to_string(),
+            start,
+            end,
+            width: 0,
+        };
+        assert!(range(5, 5).check().is_ok());
+        assert_eq!(range(5, 5).fixed_plan(0).unwrap().total, Some(1));
+        assert!(range(100, 1).check().is_err());
+        assert!(range(100, 1).fixed_plan(0).is_err());
+        assert!(range(0, u64::MAX).check().is_err());
+        assert_eq!(
+            range(1, u64::MAX).fixed_plan(0).unwrap().total,
+            Some(u64::MAX)
+        );
+
+        let random = GuessMode::Random {
+            prefix: "C".to_string(),
+            start: 10,
+            end: 1,
+            width: 0,
+            count: None,
+        };
+        assert!(random.check().is_err());
+        assert!(random.fixed_plan(0).is_err());
+    }
+
+    #[test]
+    fn test_job_from_toml() {
+        let job: GuessJob = toml::from_str(
+            r#"
+            [scan]
+            mode = "pattern"
+            template = "com.fengyun.app{hex:0..FF}"
+
+            [options]
+            batch = 50
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            job.scan,
+            GuessMode::Pattern {
+                target: PatternTarget::PkgName,
+                ..
+            }
+        ));
+        let plan = job.scan.fixed_plan(254).unwrap();
+        assert_eq!(plan.total, Some(256));
+        let names = plan
+            .queries
+            .map(|q| q.name().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["com.fengyun.appFE", "com.fengyun.appFF"]);
+        assert_eq!(job.options.batch, 50);
+        assert_eq!(job.options.delay_ms, default_delay_ms());
+    }
+}
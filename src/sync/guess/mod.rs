@@ -0,0 +1,528 @@
+//! 猜测应用 ID
+//!
+//! 按 [`GuessJob`] 惰性生成 ID, 经过 [`pipeline`] 并发请求, 猜中的分批写数据库,
+//! 进度保存在 guess_scan 表里
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use colored::Colorize;
+use serde_json::Value as JsonValue;
+use tokio::{
+    sync::{Semaphore, mpsc},
+    time::MissedTickBehavior,
+};
+use tracing::{Level, event};
+
+use crate::{
+    config::Config,
+    db::{AppDataItem, Database},
+    model::AppQuery,
+    sync::{AppNotFound, ParseFailure, query_app_or_quarantine},
+};
+
+pub mod density;
+pub mod job;
+pub mod negative;
+pub mod pipeline;
+pub mod scan;
+pub mod template;
+
+use density::DensityModel;
+pub use job::{GuessJob, GuessMode, GuessOptions, QueryPlan};
+use negative::NegativeCache;
+use pipeline::{FetchedApp, ProbeResult, Probed, Throughput, Watermark};
+use scan::{GuessScan, STATUS_FAILED, STATUS_FINISHED, STATUS_RUNNING};
+
+/// 多久保存一次断点和打印一次进度
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(2);
+/// 攒够这么多命中就写一次数据库
+const WRITE_BATCH: usize = 50;
+
+/// 一次猜测的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeOutcome {
+    /// 猜中了
+    Hit,
+    /// 应用不存在
+    Miss,
+    /// 请求出错, 不知道存不存在
+    Error,
+}
+
+/// 请求一个 ID, 不写数据库
+pub async fn fetch(
+    client: &reqwest::Client,
+    db: &Database,
+    api_url: &str,
+    locale: &str,
+    app_query: &AppQuery,
+) -> Probed {
+    match query_app_or_quarantine(client, db, api_url, app_query, locale).await {
+        Ok(data) => Probed::Hit(Box::new(data)),
+        Err(e) if e.downcast_ref::<AppNotFound>().is_some() => Probed::Miss,
+        Err(e) if e.downcast_ref::<ParseFailure>().is_some() => Probed::Quarantined,
+        Err(_) => Probed::Error,
+    }
+}
+
+/// 输出保存结果
+fn print_saved(name: &str, inserted: (bool, bool, bool)) {
+    println!(
+        "\n{}{}{}",
+        if inserted.0 {
+            format!("已将 {name} 的基本插入数据库\n")
+                .on_green()
+                .to_string()
+        } else {
+            "".to_string()
+        },
+        if inserted.1 {
+            format!("已将 {name} metrics\n").on_green().to_string()
+        } else {
+            "".to_string()
+        },
+        if inserted.2 {
+            format!("已将 {name} 的评分数据插入数据库")
+                .on_green()
+                .to_string()
+        } else {
+            "".to_string()
+        }
+    );
+}
+
+/// 保存猜中的应用
+pub async fn save_hit(db: &Database, app_query: &AppQuery, data: &FetchedApp, comment: JsonValue) {
+    let name = app_query.name();
+    match db
+        .save_app_data(&data.0, data.1.as_ref(), None, Some(comment))
+        .await
+    {
+        Ok(inserted) => print_saved(name, inserted),
+        Err(err) => {
+            println!("{}", format!("保存 {name} 的数据时出错: {}", err).on_red());
+        }
+    }
+}
+
+/// 一次保存一批猜中的应用, 整批失败时退回一个一个保存, 免得一个坏数据连累整批
+pub async fn save_hits(db: &Database, hits: &[(&AppQuery, &FetchedApp)], comment: &JsonValue) {
+    let items: Vec<AppDataItem> = hits
+        .iter()
+        .map(|(_, data)| AppDataItem {
+            data: &data.0,
+            rating: data.1.as_ref(),
+            listed_at: None,
+            comment: Some(comment.clone()),
+        })
+        .collect();
+    match db.save_app_data_batch(&items).await {
+        Ok(results) => {
+            for ((app_query, _), inserted) in hits.iter().zip(results) {
+                print_saved(app_query.name(), inserted);
+            }
+        }
+        Err(err) => {
+            event!(
+                Level::WARN,
+                "批量保存 {} 个猜中的应用失败, 改为逐个保存: {err:#}",
+                hits.len()
+            );
+            for (app_query, data) in hits {
+                save_hit(db, app_query, data, comment.clone()).await;
+            }
+        }
+    }
+}
+
+/// 新建一个猜测任务并保存到数据库
+pub async fn create_scan(db: &Database, mut job: GuessJob) -> Result<GuessScan> {
+    job.scan.resolve(db).await?;
+    let total = job.scan.plan(db, 0).await?.total;
+    let scan = db.insert_guess_scan(&job, total).await?;
+    println!(
+        "已创建猜测任务 {}, 中断之后可以用 --resume {} 继续",
+        scan.id, scan.id
+    );
+    Ok(scan)
+}
+
+/// 执行一个猜测任务, 从上次完成的批次继续
+pub async fn run_scan(
+    client: &reqwest::Client,
+    db: &Database,
+    config: &Config,
+    mut scan: GuessScan,
+) -> Result<()> {
+    db.set_guess_scan_status(scan.id, STATUS_RUNNING).await?;
+    scan.status = STATUS_RUNNING.to_string();
+    let result = run_scan_inner(client, db, config, &mut scan).await;
+    // 还有重试也出错的 ID, 也算出错, 继续的时候会再重试
+    let status = match &result {
+        Ok(true) => STATUS_FINISHED,
+        Ok(false) | Err(_) => STATUS_FAILED,
+    };
+    db.set_guess_scan_status(scan.id, status).await?;
+    result.map(|_| ())
+}
+
+/// 所有 ID 都猜完了返回 true, 重试之后还有出错的返回 false
+async fn run_scan_inner(
+    client: &reqwest::Client,
+    db: &Database,
+    config: &Config,
+    scan: &mut GuessScan,
+) -> Result<bool> {
+    let job = scan.job()?;
+    let QueryPlan {
+        queries,
+        total,
+        density,
+    } = job.scan.plan(db, scan.next_offset as u64).await?;
+    let options = job.options.clone();
+    let batch = options.batch.max(1);
+    let comment = serde_json::json!({
+        "user": format!(
+            "{}-{}",
+            options
+                .comment_tag
+                .clone()
+                .unwrap_or_else(|| format!("guess_{}", job.scan.name())),
+            env!("CARGO_PKG_VERSION")
+        )
+    });
+    let concurrency = options.concurrency();
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+    let negative = if options.recheck_days > 0 {
+        let since = chrono::Local::now() - chrono::Duration::days(options.recheck_days as i64);
+        Arc::new(NegativeCache::load(db, since).await?)
+    } else {
+        Arc::new(NegativeCache::empty())
+    };
+    println!("已加载 {} 个确认不存在的 ID 区间", negative.len());
+    if scan.next_offset > 0 {
+        println!(
+            "从第 {} 个 ID 继续猜测任务 {}, 已命中 {}",
+            scan.next_offset, scan.id, scan.hits
+        );
+    }
+
+    // 请求端: 按顺序生成 ID, 拿到并发许可才发请求, 结果发给写入端之后才释放许可
+    let (tx, mut rx) = mpsc::channel::<ProbeResult>(batch);
+    let producer = {
+        let client = client.clone();
+        let db = db.clone();
+        let api_url = config.api_url().to_string();
+        let locale = config.locale().to_string();
+        let semaphore = semaphore.clone();
+        let negative = negative.clone();
+        let start = scan.next_offset as u64;
+        tokio::spawn(async move {
+            let mut limiter = options.max_rps.filter(|rps| *rps > 0).map(|rps| {
+                let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / rps as f64));
+                interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                interval
+            });
+            let mut sent = 0_usize;
+            for (i, app_query) in queries.enumerate() {
+                let seq = start + i as u64;
+                if negative.contains(&app_query) {
+                    let result = ProbeResult {
+                        seq,
+                        app_query,
+                        probed: Probed::Skipped,
+                    };
+                    if tx.send(result).await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                let Ok(permit) = semaphore.clone().acquire_owned().await else {
+                    break;
+                };
+                if let Some(limiter) = &mut limiter {
+                    limiter.tick().await;
+                }
+                let (client, db, api_url, locale, tx) = (
+                    client.clone(),
+                    db.clone(),
+                    api_url.clone(),
+                    locale.clone(),
+                    tx.clone(),
+                );
+                tokio::spawn(async move {
+                    let probed = fetch(&client, &db, &api_url, &locale, &app_query).await;
+                    let _ = tx
+                        .send(ProbeResult {
+                            seq,
+                            app_query,
+                            probed,
+                        })
+                        .await;
+                    drop(permit);
+                });
+                sent += 1;
+                if sent.is_multiple_of(batch) && options.delay_ms > 0 {
+                    tokio::time::sleep(Duration::from_millis(options.delay_ms)).await;
+                }
+            }
+        })
+    };
+
+    // 写入端: 统计结果, 猜中的攒一批再写, 定时保存断点
+    let started = Instant::now();
+    let base_seconds = scan.active_seconds;
+    let mut watermark = Watermark::new(scan.next_offset as u64);
+    let mut throughput = Throughput::new(Duration::from_secs(10));
+    let mut pending_hits: Vec<(u64, AppQuery, Box<FetchedApp>)> = Vec::new();
+    let mut pending_retry: Vec<AppQuery> = Vec::new();
+    let mut since_checkpoint = 0_usize;
+    let mut ticker = tokio::time::interval(CHECKPOINT_INTERVAL);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        let message = tokio::select! {
+            message = rx.recv() => message,
+            _ = ticker.tick() => {
+                since_checkpoint = 0;
+                let in_flight = concurrency - semaphore.available_permits();
+                write_hits(db, &mut pending_hits, &mut watermark, &comment).await;
+                checkpoint(db, scan, &watermark, &mut pending_retry, base_seconds + started.elapsed().as_secs_f64(), &negative, density.as_deref()).await?;
+                print_progress(scan, total, throughput.rate(), in_flight);
+                continue;
+            }
+        };
+        let Some(ProbeResult {
+            seq,
+            app_query,
+            probed,
+        }) = message
+        else {
+            break;
+        };
+        if !matches!(probed, Probed::Skipped) {
+            scan.requests += 1;
+            throughput.record();
+        }
+        let outcome = match probed {
+            Probed::Skipped => {
+                scan.skipped += 1;
+                watermark.complete(seq);
+                ProbeOutcome::Miss
+            }
+            Probed::Miss => {
+                negative.record_miss(&app_query);
+                watermark.complete(seq);
+                ProbeOutcome::Miss
+            }
+            Probed::Quarantined => {
+                // 应用是存在的, 只是现在解析不了, 等隔离表重新处理
+                scan.errors += 1;
+                watermark.complete(seq);
+                ProbeOutcome::Hit
+            }
+            Probed::Error => {
+                scan.errors += 1;
+                pending_retry.push(app_query.clone());
+                watermark.complete(seq);
+                ProbeOutcome::Error
+            }
+            Probed::Hit(data) => {
+                scan.hits += 1;
+                pending_hits.push((seq, app_query.clone(), data));
+                ProbeOutcome::Hit
+            }
+        };
+        if let Some(density) = &density {
+            density.record(&app_query, outcome);
+        }
+        since_checkpoint += 1;
+        if pending_hits.len() >= WRITE_BATCH {
+            write_hits(db, &mut pending_hits, &mut watermark, &comment).await;
+        }
+        if since_checkpoint >= batch {
+            since_checkpoint = 0;
+            write_hits(db, &mut pending_hits, &mut watermark, &comment).await;
+            checkpoint(
+                db,
+                scan,
+                &watermark,
+                &mut pending_retry,
+                base_seconds + started.elapsed().as_secs_f64(),
+                &negative,
+                density.as_deref(),
+            )
+            .await?;
+        }
+    }
+    producer.await?;
+    write_hits(db, &mut pending_hits, &mut watermark, &comment).await;
+    checkpoint(
+        db,
+        scan,
+        &watermark,
+        &mut pending_retry,
+        base_seconds + started.elapsed().as_secs_f64(),
+        &negative,
+        density.as_deref(),
+    )
+    .await?;
+
+    let remaining = retry_errors(
+        client,
+        db,
+        config,
+        scan.id,
+        &negative,
+        &comment,
+        concurrency,
+    )
+    .await?;
+    if remaining > 0 {
+        println!(
+            "\n猜测任务 {} 还有 {remaining} 个 ID 重试之后依然出错，继续任务时会再重试",
+            scan.id
+        );
+        return Ok(false);
+    }
+    println!(
+        "\n猜测任务 {} 完成，共 {} 个 ID，命中 {} 个，每千次请求命中 {:.2}",
+        scan.id,
+        scan.next_offset,
+        scan.hits,
+        scan.clone().status().hits_per_thousand
+    );
+    Ok(true)
+}
+
+/// 把攒着的命中写进数据库, 写完才算完成
+async fn write_hits(
+    db: &Database,
+    pending: &mut Vec<(u64, AppQuery, Box<FetchedApp>)>,
+    watermark: &mut Watermark,
+    comment: &JsonValue,
+) {
+    let hits: Vec<(&AppQuery, &FetchedApp)> = pending
+        .iter()
+        .map(|(_, app_query, data)| (app_query, data.as_ref()))
+        .collect();
+    save_hits(db, &hits, comment).await;
+    for (seq, _, _) in pending.drain(..) {
+        watermark.complete(seq);
+    }
+}
+
+/// 重试任务里请求出错的 ID, 返回依然出错的数量
+///
+/// 重试的请求不计入任务的请求、命中和出错数量, 这些 ID 第一次请求的时候已经算过了
+async fn retry_errors(
+    client: &reqwest::Client,
+    db: &Database,
+    config: &Config,
+    scan_id: i64,
+    negative: &NegativeCache,
+    comment: &JsonValue,
+    concurrency: usize,
+) -> Result<usize> {
+    let queries = db.get_guess_retry(scan_id).await?;
+    if queries.is_empty() {
+        return Ok(0);
+    }
+    println!("\n重试 {} 个请求出错的 ID", queries.len());
+
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+    let mut join_set = tokio::task::JoinSet::new();
+    for app_query in queries {
+        let (client, db, semaphore) = (client.clone(), db.clone(), semaphore.clone());
+        let api_url = config.api_url().to_string();
+        let locale = config.locale().to_string();
+        join_set.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            let probed = fetch(&client, &db, &api_url, &locale, &app_query).await;
+            (app_query, probed)
+        });
+    }
+
+    let mut done = Vec::new();
+    let mut failed = Vec::new();
+    let mut hits = Vec::new();
+    while let Some(joined) = join_set.join_next().await {
+        let (app_query, probed) = joined?;
+        match probed {
+            Probed::Hit(data) => hits.push((app_query, data)),
+            Probed::Miss => {
+                negative.record_miss(&app_query);
+                done.push(app_query);
+            }
+            Probed::Skipped | Probed::Quarantined => done.push(app_query),
+            Probed::Error => failed.push(app_query),
+        }
+    }
+    let saved: Vec<(&AppQuery, &FetchedApp)> = hits
+        .iter()
+        .map(|(app_query, data)| (app_query, data.as_ref()))
+        .collect();
+    save_hits(db, &saved, comment).await;
+    done.extend(hits.into_iter().map(|(app_query, _)| app_query));
+
+    db.delete_guess_retry(scan_id, &done).await?;
+    db.add_guess_retry(scan_id, &failed).await?;
+    negative.flush(db).await?;
+    Ok(failed.len())
+}
+
+/// 保存断点, 出错的 ID, 不存在的 ID 和密度模型
+///
+/// 出错的 ID 先写进重试列表, 断点才能越过它们
+async fn checkpoint(
+    db: &Database,
+    scan: &mut GuessScan,
+    watermark: &Watermark,
+    pending_retry: &mut Vec<AppQuery>,
+    active_seconds: f64,
+    negative: &NegativeCache,
+    density: Option<&DensityModel>,
+) -> Result<()> {
+    if !pending_retry.is_empty() {
+        db.add_guess_retry(scan.id, pending_retry).await?;
+        pending_retry.clear();
+    }
+    scan.next_offset = watermark.position() as i64;
+    scan.active_seconds = active_seconds;
+    db.update_guess_scan_progress(scan).await?;
+    negative.flush(db).await?;
+    if let Some(density) = density {
+        density.flush(db).await?;
+    }
+    Ok(())
+}
+
+fn print_progress(scan: &GuessScan, total: Option<u64>, rps: f64, in_flight: usize) {
+    let progress = scan.clone().status();
+    match total {
+        Some(total) => {
+            print!(
+                "\r[任务 {} {}/{}] 命中 {}，出错 {}，跳过 {}，{:.1} 请求/秒，进行中 {}，预计剩余 {:?}",
+                scan.id,
+                scan.next_offset,
+                total,
+                scan.hits,
+                scan.errors,
+                scan.skipped,
+                rps,
+                in_flight,
+                Duration::from_secs(progress.eta_seconds.unwrap_or_default() as u64),
+            );
+        }
+        None => {
+            print!(
+                "\r[任务 {}] 已处理 {} 个 ID，命中 {}，出错 {}，跳过 {}，{:.1} 请求/秒，进行中 {}",
+                scan.id, scan.next_offset, scan.hits, scan.errors, scan.skipped, rps, in_flight
+            );
+        }
+    }
+    std::io::Write::flush(&mut std::io::stdout()).unwrap();
+}
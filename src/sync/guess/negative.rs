@@ -0,0 +1,258 @@
+//! 已经确认不存在的 ID 缓存
+//!
+//! 每个前缀维护一个区间集合, 记录猜过但是不存在的 ID 和猜测时间,
+//! 所有猜测方式在请求之前都会先查一下, 没超过复查时间的直接跳过.
+//! 同一天猜的相邻 ID 会合并成一个区间, 保存在 guess_negative 表里
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Mutex,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+
+use crate::{db::Database, model::AppQuery, sync::guess::job::parse_app_id};
+
+/// guess_negative 表里的一行
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct NegativeRange {
+    /// app_id / pkg_name
+    pub query_key: String,
+    pub prefix: String,
+    pub range_start: i64,
+    pub range_end: i64,
+    /// 区间里最早的猜测时间
+    pub probed_at: DateTime<Local>,
+}
+
+/// 缓存的 key: (query_key, 前缀, 数字部分)
+///
+/// 只有 `前缀 + 数字` 能原样还原的才缓存, 避免 `app007` 和 `app7` 混在一起
+pub fn cache_key(app_query: &AppQuery) -> Option<(String, String, u64)> {
+    let name = app_query.name();
+    let (prefix, num) = parse_app_id(name)?;
+    if num > i64::MAX as u64 || format!("{prefix}{num}") != name {
+        return None;
+    }
+    Some((app_query.app_db_name().to_string(), prefix, num))
+}
+
+/// 不重叠的区间集合, start -> (end, 猜测时间)
+#[derive(Debug, Default, Clone)]
+pub struct RangeSet {
+    ranges: BTreeMap<u64, (u64, DateTime<Local>)>,
+}
+
+impl RangeSet {
+    pub fn contains(&self, n: u64) -> bool {
+        self.ranges
+            .range(..=n)
+            .next_back()
+            .is_some_and(|(_, (end, _))| n <= *end)
+    }
+
+    /// 加入一个 ID, 和同一天猜的相邻区间合并
+    ///
+    /// 返回包含这个 ID 的区间
+    pub fn insert(&mut self, n: u64, at: DateTime<Local>) -> (u64, u64, DateTime<Local>) {
+        if let Some((&start, &(end, probed_at))) = self.ranges.range(..=n).next_back()
+            && n <= end
+        {
+            return (start, end, probed_at);
+        }
+
+        let (mut start, mut end, mut probed_at) = (n, n, at);
+        if let Some(left) = n.checked_sub(1)
+            && let Some((&l_start, &(l_end, l_at))) = self.ranges.range(..=left).next_back()
+            && l_end == left
+            && l_at.date_naive() == at.date_naive()
+        {
+            self.ranges.remove(&l_start);
+            start = l_start;
+            probed_at = probed_at.min(l_at);
+        }
+        if let Some(right) = n.checked_add(1)
+            && let Some(&(r_end, r_at)) = self.ranges.get(&right)
+            && r_at.date_naive() == at.date_naive()
+        {
+            self.ranges.remove(&right);
+            end = r_end;
+            probed_at = probed_at.min(r_at);
+        }
+        self.ranges.insert(start, (end, probed_at));
+        (start, end, probed_at)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
+#[derive(Default)]
+struct NegativeState {
+    sets: HashMap<(String, String), RangeSet>,
+    /// 新加入的 ID (query_key, 前缀, 数字部分)
+    dirty: HashSet<(String, String, u64)>,
+}
+
+/// 不存在的 ID 缓存
+pub struct NegativeCache {
+    state: Mutex<NegativeState>,
+}
+
+impl NegativeCache {
+    /// 空的缓存, 不跳过任何 ID, 但是依然会记录
+    pub fn empty() -> Self {
+        Self {
+            state: Mutex::new(NegativeState::default()),
+        }
+    }
+
+    /// 加载 since 之后猜过的区间, 更早的需要重新猜
+    pub async fn load(db: &Database, since: DateTime<Local>) -> Result<Self> {
+        let mut state = NegativeState::default();
+        for range in db.get_guess_negative(since).await? {
+            state
+                .sets
+                .entry((range.query_key, range.prefix))
+                .or_default()
+                .ranges
+                .insert(
+                    range.range_start as u64,
+                    (range.range_end as u64, range.probed_at),
+                );
+        }
+        Ok(Self {
+            state: Mutex::new(state),
+        })
+    }
+
+    /// 区间数量
+    pub fn len(&self) -> usize {
+        self.state
+            .lock()
+            .unwrap()
+            .sets
+            .values()
+            .map(RangeSet::len)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 这个 ID 是否已经确认不存在
+    pub fn contains(&self, app_query: &AppQuery) -> bool {
+        let Some((key, prefix, num)) = cache_key(app_query) else {
+            return false;
+        };
+        self.state
+            .lock()
+            .unwrap()
+            .sets
+            .get(&(key, prefix))
+            .is_some_and(|set| set.contains(num))
+    }
+
+    /// 记录一个不存在的 ID
+    pub fn record_miss(&self, app_query: &AppQuery) {
+        let Some((key, prefix, num)) = cache_key(app_query) else {
+            return;
+        };
+        let mut state = self.state.lock().unwrap();
+        state
+            .sets
+            .entry((key.clone(), prefix.clone()))
+            .or_default()
+            .insert(num, Local::now());
+        state.dirty.insert((key, prefix, num));
+    }
+
+    /// 取出有变化的区间
+    pub fn take_dirty(&self) -> Vec<NegativeRange> {
+        let mut state = self.state.lock().unwrap();
+        let dirty = std::mem::take(&mut state.dirty);
+        let mut ranges = BTreeMap::new();
+        for (key, prefix, num) in dirty {
+            let Some(set) = state.sets.get(&(key.clone(), prefix.clone())) else {
+                continue;
+            };
+            let Some((&start, &(end, probed_at))) = set.ranges.range(..=num).next_back() else {
+                continue;
+            };
+            ranges
+                .entry((key.clone(), prefix.clone(), start))
+                .or_insert(NegativeRange {
+                    query_key: key,
+                    prefix,
+                    range_start: start as i64,
+                    range_end: end as i64,
+                    probed_at,
+                });
+        }
+        ranges.into_values().collect()
+    }
+
+    /// 把有变化的区间写回数据库
+    pub async fn flush(&self, db: &Database) -> Result<()> {
+        let dirty = self.take_dirty();
+        if dirty.is_empty() {
+            return Ok(());
+        }
+        db.replace_guess_negative(&dirty).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_range_set() {
+        let now = Local::now();
+        let mut set = RangeSet::default();
+        set.insert(10, now);
+        set.insert(12, now);
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.insert(11, now), (10, 12, now));
+        assert_eq!(set.len(), 1);
+        assert!(set.contains(11));
+        assert!(!set.contains(13));
+
+        // 不同一天的不合并
+        let old = now - Duration::days(3);
+        set.insert(13, old);
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(13));
+    }
+
+    #[test]
+    fn test_cache_key() {
+        assert_eq!(
+            cache_key(&AppQuery::app_id("C6917559067092904725")),
+            Some(("app_id".to_string(), "C".to_string(), 6917559067092904725))
+        );
+        assert_eq!(cache_key(&AppQuery::pkg_name("com.fengyun.app007")), None);
+        assert_eq!(cache_key(&AppQuery::pkg_name("com.example")), None);
+    }
+
+    #[test]
+    fn test_dirty_after_merge() {
+        let cache = NegativeCache::empty();
+        for id in ["C10", "C12", "C11"] {
+            cache.record_miss(&AppQuery::app_id(id));
+        }
+        let dirty = cache.take_dirty();
+        assert_eq!(dirty.len(), 1);
+        assert_eq!((dirty[0].range_start, dirty[0].range_end), (10, 12));
+        assert!(cache.contains(&AppQuery::app_id("C11")));
+    }
+}
@@ -0,0 +1,163 @@
+//! 流式猜测管线
+//!
+//! ID 生成器 -> 有上限的并发请求 -> 分批写数据库, 中间用有界 channel 连起来.
+//! 请求数到了上限生成器就等着, 写库跟不上请求也会等着,
+//! 所以内存占用不随范围大小增长, 也不会因为等一批里最慢的请求而停顿
+
+use std::{
+    collections::{BTreeSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+use serde_json::Value as JsonValue;
+
+use crate::model::{AppQuery, RawJsonData, RawRatingData};
+
+/// 请求到的应用数据
+pub type FetchedApp = ((RawJsonData, JsonValue), Option<RawRatingData>);
+
+/// 一个 ID 的处理结果
+pub enum Probed {
+    /// 已经确认不存在, 没有请求
+    Skipped,
+    /// 猜中了, 等待写入数据库
+    Hit(Box<FetchedApp>),
+    /// 应用不存在
+    Miss,
+    /// 解析失败, 原始数据已经放进隔离表, 不用再请求
+    Quarantined,
+    /// 请求出错, 不知道存不存在, 记下来之后重试
+    Error,
+}
+
+/// 请求端发给写入端的消息
+pub struct ProbeResult {
+    /// 在 ID 序列里的位置
+    pub seq: u64,
+    pub app_query: AppQuery,
+    pub probed: Probed,
+}
+
+/// 已完成序号的水位线
+///
+/// 请求是乱序完成的, 只有某个位置之前的全部完成了, 才能把它当作断点保存
+#[derive(Debug, Default)]
+pub struct Watermark {
+    next: u64,
+    done: BTreeSet<u64>,
+}
+
+impl Watermark {
+    pub fn new(start: u64) -> Self {
+        Self {
+            next: start,
+            done: BTreeSet::new(),
+        }
+    }
+
+    /// 标记一个序号已完成
+    pub fn complete(&mut self, seq: u64) {
+        if seq < self.next {
+            return;
+        }
+        self.done.insert(seq);
+        while self.done.remove(&self.next) {
+            self.next += 1;
+        }
+    }
+
+    /// 这个位置之前的全部完成了
+    pub fn position(&self) -> u64 {
+        self.next
+    }
+
+    /// 已经完成但是前面还有没完成的数量
+    pub fn out_of_order(&self) -> usize {
+        self.done.len()
+    }
+}
+
+/// 最近一段时间的每秒请求数
+pub struct Throughput {
+    window: Duration,
+    started: Instant,
+    events: VecDeque<Instant>,
+}
+
+impl Throughput {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            started: Instant::now(),
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn record(&mut self) {
+        self.record_at(Instant::now());
+    }
+
+    pub fn record_at(&mut self, at: Instant) {
+        self.events.push_back(at);
+        self.expire(at);
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(first) = self.events.front()
+            && now.duration_since(*first) > self.window
+        {
+            self.events.pop_front();
+        }
+    }
+
+    pub fn rate(&mut self) -> f64 {
+        self.rate_at(Instant::now())
+    }
+
+    /// 刚开始不满一个窗口的时候按实际经过的时间算
+    pub fn rate_at(&mut self, now: Instant) -> f64 {
+        self.expire(now);
+        let elapsed = now
+            .duration_since(self.started)
+            .min(self.window)
+            .as_secs_f64();
+        if elapsed > 0.0 {
+            self.events.len() as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watermark() {
+        let mut mark = Watermark::new(10);
+        mark.complete(12);
+        mark.complete(11);
+        assert_eq!(mark.position(), 10);
+        assert_eq!(mark.out_of_order(), 2);
+        mark.complete(10);
+        assert_eq!(mark.position(), 13);
+        assert_eq!(mark.out_of_order(), 0);
+        // 断点之前的重复完成不影响
+        mark.complete(5);
+        assert_eq!(mark.position(), 13);
+    }
+
+    #[test]
+    fn test_throughput() {
+        let mut stats = Throughput::new(Duration::from_secs(10));
+        let start = stats.started;
+        for i in 0..20 {
+            stats.record_at(start + Duration::from_millis(i * 100));
+        }
+        // 2 秒 20 个
+        assert_eq!(stats.rate_at(start + Duration::from_secs(2)), 10.0);
+        // 过了窗口之后旧的不算
+        assert_eq!(stats.rate_at(start + Duration::from_secs(15)), 0.0);
+    }
+}
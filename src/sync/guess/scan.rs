@@ -0,0 +1,120 @@
+//! 持久化的猜测任务
+//!
+//! 任务定义和进度存在 guess_scan 表里, 每批完成之后更新一次,
+//! 程序挂了之后可以从最后一个完成的批次继续
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::sync::guess::GuessJob;
+
+/// 正在运行
+pub const STATUS_RUNNING: &str = "running";
+/// 已经跑完
+pub const STATUS_FINISHED: &str = "finished";
+/// 出错退出
+pub const STATUS_FAILED: &str = "failed";
+
+/// guess_scan 表
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct GuessScan {
+    pub id: i64,
+    pub mode: String,
+    /// 序列化后的 [`GuessJob`]
+    pub definition: JsonValue,
+    pub status: String,
+    /// 已经完成的 ID 数量, 也就是下次开始的位置
+    pub next_offset: i64,
+    /// ID 总数, 无限的随机猜测为 None
+    pub total: Option<i64>,
+    pub hits: i64,
+    pub requests: i64,
+    pub errors: i64,
+    /// 因为已经确认不存在而跳过的数量
+    pub skipped: i64,
+    /// 实际运行的秒数, 不包含中断的时间
+    pub active_seconds: f64,
+    pub created_at: DateTime<Local>,
+    pub updated_at: DateTime<Local>,
+    pub finished_at: Option<DateTime<Local>>,
+}
+
+/// 带进度和预计剩余时间的任务状态
+#[derive(Debug, Clone, Serialize)]
+pub struct GuessScanStatus {
+    #[serde(flatten)]
+    pub scan: GuessScan,
+    /// 完成百分比
+    pub progress: Option<f64>,
+    /// 错误率
+    pub error_rate: f64,
+    /// 每千次请求命中数
+    pub hits_per_thousand: f64,
+    /// 每秒请求数
+    pub requests_per_second: f64,
+    /// 预计剩余秒数
+    pub eta_seconds: Option<f64>,
+}
+
+impl GuessScan {
+    pub fn job(&self) -> anyhow::Result<GuessJob> {
+        let job: GuessJob = serde_json::from_value(self.definition.clone())?;
+        job.scan.check()?;
+        Ok(job)
+    }
+
+    pub fn status(self) -> GuessScanStatus {
+        let requests_per_second = if self.active_seconds > 0.0 {
+            self.requests as f64 / self.active_seconds
+        } else {
+            0.0
+        };
+        let error_rate = if self.requests > 0 {
+            self.errors as f64 / self.requests as f64
+        } else {
+            0.0
+        };
+        let hits_per_thousand = hits_per_thousand(self.hits, self.requests);
+        let progress = self
+            .total
+            .filter(|total| *total > 0)
+            .map(|total| self.next_offset as f64 / total as f64 * 100.0);
+        let eta_seconds = match self.total {
+            Some(total) if self.next_offset > 0 && self.status == STATUS_RUNNING => Some(
+                (total - self.next_offset).max(0) as f64 * self.active_seconds
+                    / self.next_offset as f64,
+            ),
+            _ => None,
+        };
+        GuessScanStatus {
+            scan: self,
+            progress,
+            error_rate,
+            hits_per_thousand,
+            requests_per_second,
+            eta_seconds,
+        }
+    }
+}
+
+/// 每种猜测方式的命中率汇总
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct GuessStrategyStats {
+    pub mode: String,
+    pub scans: i64,
+    pub requests: i64,
+    pub hits: i64,
+    pub errors: i64,
+    #[sqlx(skip)]
+    pub hits_per_thousand: f64,
+}
+
+/// 每千次请求命中数
+pub fn hits_per_thousand(hits: i64, requests: i64) -> f64 {
+    if requests > 0 {
+        hits as f64 * 1000.0 / requests as f64
+    } else {
+        0.0
+    }
+}
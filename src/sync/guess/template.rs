@@ -0,0 +1,380 @@
+//! 包名模板
+//!
+//! 模板里 `{...}` 是一个槽位, 其他部分原样保留, 多个槽位按笛卡尔积展开:
+//!
+//! - `{7372..43537}`: 十进制范围, 起点有前导零时按起点长度补零, 例如 `{001..999}`
+//! - `{hex:0..FFFF}`: 十六进制范围, 大小写跟着终点走, 补零规则同上
+//! - `{letters:a..zz}`: 字母序列 a, b, ..., z, aa, ab, ...
+//! - `{words:path/to/words.txt}`: 词表文件, 一行一个
+//! - `{hm|harmony|ohos}`: 直接写出来的候选词
+//!
+//! 例如 `com.appbyme.app{7372..43537}.hm`, `xkkj.uni.UNI{hex:0..FFFFF}`
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+
+use crate::utils::{i32_to_letters, letters_to_i32};
+
+/// 一个槽位
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Slot {
+    Decimal {
+        start: u64,
+        end: u64,
+        width: usize,
+    },
+    Hex {
+        start: u64,
+        end: u64,
+        width: usize,
+        upper: bool,
+    },
+    Letters {
+        start: u64,
+        end: u64,
+    },
+    Words(Vec<String>),
+}
+
+impl Slot {
+    fn parse(spec: &str) -> Result<Self> {
+        let slot = Self::parse_spec(spec)?;
+        if let Slot::Decimal { start, end, .. }
+        | Slot::Hex { start, end, .. }
+        | Slot::Letters { start, end } = &slot
+        {
+            range_len(*start, *end).with_context(|| format!("槽位 {{{spec}}} 无效"))?;
+        }
+        Ok(slot)
+    }
+
+    fn parse_spec(spec: &str) -> Result<Self> {
+        let width_of = |s: &str| {
+            if s.len() > 1 && s.starts_with('0') {
+                s.len()
+            } else {
+                0
+            }
+        };
+        if let Some(path) = spec.strip_prefix("words:") {
+            let content =
+                std::fs::read_to_string(path).with_context(|| format!("无法读取词表 {path}"))?;
+            let words = content
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(String::from)
+                .collect::<Vec<_>>();
+            anyhow::ensure!(!words.is_empty(), "词表 {path} 是空的");
+            return Ok(Slot::Words(words));
+        }
+        if let Some(range) = spec.strip_prefix("hex:") {
+            let (start, end) = split_range(range)?;
+            return Ok(Slot::Hex {
+                start: u64::from_str_radix(start, 16)
+                    .with_context(|| format!("{start} 不是十六进制数"))?,
+                end: u64::from_str_radix(end, 16)
+                    .with_context(|| format!("{end} 不是十六进制数"))?,
+                width: width_of(start),
+                upper: !end.chars().any(|c| c.is_ascii_lowercase()),
+            });
+        }
+        if let Some(range) = spec.strip_prefix("letters:") {
+            let (start, end) = split_range(range)?;
+            let parse = |s: &str| {
+                letters_to_i32(s)
+                    .map(|n| n as u64)
+                    .ok_or_else(|| anyhow::anyhow!("{s} 不是小写字母序列"))
+            };
+            return Ok(Slot::Letters {
+                start: parse(start)?,
+                end: parse(end)?,
+            });
+        }
+        if spec.contains("..") {
+            let (start, end) = split_range(spec)?;
+            return Ok(Slot::Decimal {
+                start: start
+                    .parse()
+                    .with_context(|| format!("{start} 不是十进制数"))?,
+                end: end.parse().with_context(|| format!("{end} 不是十进制数"))?,
+                width: width_of(start),
+            });
+        }
+        Ok(Slot::Words(spec.split('|').map(String::from).collect()))
+    }
+
+    /// 槽位里有多少个值, 范围在解析的时候已经检查过
+    pub fn len(&self) -> u64 {
+        match self {
+            Slot::Decimal { start, end, .. }
+            | Slot::Hex { start, end, .. }
+            | Slot::Letters { start, end } => end - start + 1,
+            Slot::Words(words) => words.len() as u64,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 第 index 个值
+    pub fn render(&self, index: u64) -> String {
+        match self {
+            Slot::Decimal { start, width, .. } => format!("{:0width$}", start + index),
+            Slot::Hex {
+                start,
+                width,
+                upper: true,
+                ..
+            } => format!("{:0width$X}", start + index),
+            Slot::Hex { start, width, .. } => format!("{:0width$x}", start + index),
+            Slot::Letters { start, .. } => i32_to_letters((start + index) as i32),
+            Slot::Words(words) => words[index as usize].clone(),
+        }
+    }
+}
+
+/// 范围里值的数量, 起点大于终点或者数量超过 u64 的范围时出错
+fn range_len(start: u64, end: u64) -> Result<u64> {
+    anyhow::ensure!(start <= end, "起点 {start} 大于终点 {end}");
+    (end - start)
+        .checked_add(1)
+        .ok_or_else(|| anyhow::anyhow!("{start}..{end} 的范围太大"))
+}
+
+fn split_range(spec: &str) -> Result<(&str, &str)> {
+    spec.split_once("..")
+        .map(|(s, e)| (s.trim(), e.trim()))
+        .ok_or_else(|| anyhow::anyhow!("{spec} 不是 start..end 的格式"))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Slot(Slot),
+}
+
+/// 解析后的模板
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                parts.push(Part::Literal(rest[..open].to_string()));
+            }
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| anyhow::anyhow!("模板 {template} 里的 {{ 没有闭合"))?;
+            parts.push(Part::Slot(Slot::parse(&rest[open + 1..open + close])?));
+            rest = &rest[open + close + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        anyhow::ensure!(
+            parts.iter().any(|p| matches!(p, Part::Slot(_))),
+            "模板 {template} 里没有槽位"
+        );
+        Ok(Self { parts })
+    }
+
+    fn slots(&self) -> impl DoubleEndedIterator<Item = &Slot> {
+        self.parts.iter().filter_map(|p| match p {
+            Part::Slot(slot) => Some(slot),
+            Part::Literal(_) => None,
+        })
+    }
+
+    /// 展开后一共有多少个
+    pub fn len(&self) -> u64 {
+        self.slots()
+            .fold(1_u64, |acc, slot| acc.saturating_mul(slot.len()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 第 index 个展开结果, 最后一个槽位变化最快
+    pub fn render(&self, index: u64) -> String {
+        let mut values = Vec::new();
+        let mut rest = index;
+        for slot in self.slots().rev() {
+            let len = slot.len().max(1);
+            values.push(slot.render(rest % len));
+            rest /= len;
+        }
+        let mut values = values.into_iter().rev();
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(s) => s.clone(),
+                Part::Slot(_) => values.next().unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// 从第 offset 个开始展开
+    pub fn iter_from(self, offset: u64) -> impl Iterator<Item = String> + Send {
+        (offset..self.len()).map(move |i| self.render(i))
+    }
+}
+
+/// 从已有包名里挖出来的模板
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinedTemplate {
+    pub template: String,
+    /// 符合这个模板的已有包名数量
+    pub examples: usize,
+}
+
+/// 从已有包名里挖模板
+///
+/// 把包名里最后一段数字换成槽位, 前后缀相同的包名归为一组,
+/// 至少有 min_support 个包名的组生成模板 `前缀{min - margin..max + margin}后缀`,
+/// 范围超过 max_span 的组不要
+pub fn mine_templates(
+    pkg_names: &[String],
+    min_support: usize,
+    margin: u64,
+    max_span: u64,
+) -> Vec<MinedTemplate> {
+    // (前缀, 后缀, 补零宽度) -> 数字
+    let mut groups: BTreeMap<(String, String, usize), Vec<u64>> = BTreeMap::new();
+    for pkg_name in pkg_names {
+        let Some(end) = pkg_name.rfind(|c: char| c.is_ascii_digit()) else {
+            continue;
+        };
+        let start = pkg_name[..=end]
+            .rfind(|c: char| !c.is_ascii_digit())
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let (prefix, digits, suffix) = (
+            &pkg_name[..start],
+            &pkg_name[start..=end],
+            &pkg_name[end + 1..],
+        );
+        // 前缀至少要有一个点, 不然范围太大了
+        if !prefix.contains('.') {
+            continue;
+        }
+        let Ok(num) = digits.parse::<u64>() else {
+            continue;
+        };
+        let width = if digits.len() > 1 && digits.starts_with('0') {
+            digits.len()
+        } else {
+            0
+        };
+        groups
+            .entry((prefix.to_string(), suffix.to_string(), width))
+            .or_default()
+            .push(num);
+    }
+
+    let mut templates = groups
+        .into_iter()
+        .filter(|(_, nums)| nums.len() >= min_support)
+        .filter_map(|((prefix, suffix, width), nums)| {
+            let min = nums.iter().min()?.saturating_sub(margin);
+            let max = nums.iter().max()?.saturating_add(margin);
+            if max - min > max_span {
+                return None;
+            }
+            let (min, max) = if width > 0 {
+                (format!("{min:0width$}"), format!("{max:0width$}"))
+            } else {
+                (min.to_string(), max.to_string())
+            };
+            Some(MinedTemplate {
+                template: format!("{prefix}{{{min}..{max}}}{suffix}"),
+                examples: nums.len(),
+            })
+        })
+        .collect::<Vec<_>>();
+    templates.sort_by(|a, b| {
+        b.examples
+            .cmp(&a.examples)
+            .then_with(|| a.template.cmp(&b.template))
+    });
+    templates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_render() {
+        let t = Template::parse("com.appbyme.app{98..101}.hm").unwrap();
+        assert_eq!(t.len(), 4);
+        assert_eq!(t.render(0), "com.appbyme.app98.hm");
+        assert_eq!(t.render(3), "com.appbyme.app101.hm");
+
+        let t = Template::parse("xkkj.uni.UNI{hex:0E..10}").unwrap();
+        assert_eq!(
+            t.iter_from(0).collect::<Vec<_>>(),
+            vec!["xkkj.uni.UNI0E", "xkkj.uni.UNI0F", "xkkj.uni.UNI10"]
+        );
+
+        let t = Template::parse("com.{a|b}.app{letters:y..ab}").unwrap();
+        assert_eq!(t.len(), 8);
+        assert_eq!(t.render(0), "com.a.appy");
+        assert_eq!(t.render(3), "com.a.appab");
+        assert_eq!(t.render(4), "com.b.appy");
+        assert_eq!(t.iter_from(7).collect::<Vec<_>>(), vec!["com.b.appab"]);
+
+        let t = Template::parse("com.app{007..009}").unwrap();
+        assert_eq!(t.render(0), "com.app007");
+
+        assert!(Template::parse("com.app").is_err());
+        assert!(Template::parse("com.app{1..").is_err());
+        assert!(Template::parse("com.app{100..1}").is_err());
+        assert!(Template::parse("com.app{hex:FF..0}").is_err());
+        assert!(Template::parse("com.app{letters:b..a}").is_err());
+        assert!(Template::parse("com.app{0..18446744073709551615}").is_err());
+        assert_eq!(
+            Template::parse("com.app{1..18446744073709551615}")
+                .unwrap()
+                .len(),
+            u64::MAX
+        );
+    }
+
+    #[test]
+    fn test_mine_templates() {
+        let names = [
+            "com.appbyme.app100.hm",
+            "com.appbyme.app120.hm",
+            "com.appbyme.app130.hm",
+            "com.other.app1",
+            "com.pad.a007",
+            "com.pad.a009",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect::<Vec<_>>();
+        let mined = mine_templates(&names, 2, 5, 1000);
+        assert_eq!(
+            mined,
+            vec![
+                MinedTemplate {
+                    template: "com.appbyme.app{95..135}.hm".to_string(),
+                    examples: 3
+                },
+                MinedTemplate {
+                    template: "com.pad.a{002..014}".to_string(),
+                    examples: 2
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,370 @@
+//! 导入 "移动端应用市场上新记录整理" 表格
+//!
+//! 表头是 `应用名称/游戏名称, 上架平台, 应用类型/游戏类型, 上架时间, 开发者, 备注, 访问链接`,
+//! 应用和游戏两张表只有表头不一样, 所以按列的位置读取.
+//! 访问链接里的 id 拿去同步, 上架时间写到 listed_at, 备注写到 comment
+
+use std::{collections::HashSet, path::Path, sync::Arc};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use colored::Colorize;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use tokio::sync::Semaphore;
+use tracing::{Level, event};
+
+use crate::{
+    config::Config,
+    db::{AppDataItem, Database},
+    model::AppQuery,
+    sync::{
+        AppNotFound,
+        guess::pipeline::FetchedApp,
+        query_app_or_quarantine,
+        resolve::{AppInput, parse_app_input},
+    },
+};
+
+/// 表格里的一行
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListingRecord {
+    /// 在文件里的行号, 表头是第 1 行
+    pub line: u64,
+    pub name: String,
+    pub platform: String,
+    pub kind: String,
+    pub listed_at: Option<DateTime<Local>>,
+    pub developer: String,
+    pub note: String,
+    pub link: String,
+}
+
+impl ListingRecord {
+    /// 访问链接对应的查询
+    pub fn query(&self) -> Option<AppQuery> {
+        parse_detail_link(&self.link)
+    }
+}
+
+/// 没能导入的行
+#[derive(Debug, Clone, Serialize)]
+pub struct UnmatchedRow {
+    pub file: String,
+    pub line: u64,
+    pub name: String,
+    pub link: String,
+    pub reason: String,
+}
+
+/// 导入结果
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub total: usize,
+    /// 导入前数据库里没有的应用
+    pub new_apps: usize,
+    /// 已经有了, 数据没变的也算
+    pub synced: usize,
+    pub unmatched: Vec<UnmatchedRow>,
+}
+
+/// 从 `https://appgallery.huawei.com/app/detail?id=xxx&channelId=SHARE` 里取出 id
+///
+/// 短链接不请求, 当作没有 id
+pub fn parse_detail_link(link: &str) -> Option<AppQuery> {
+    match parse_app_input(link)? {
+        AppInput::Query(query) => Some(query),
+        AppInput::ShortLink(_) => None,
+    }
+}
+
+/// `2025/10/01` 或者 `2025-10-01`, 当作本地时间的零点
+pub fn parse_listing_date(value: &str) -> Option<DateTime<Local>> {
+    let value = value.trim();
+    let date = NaiveDate::parse_from_str(value, "%Y/%m/%d")
+        .or_else(|_| NaiveDate::parse_from_str(value, "%Y-%m-%d"))
+        .ok()?;
+    Local
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+        .earliest()
+}
+
+/// 用备注生成 comment
+///
+/// `群友 雄鹰 提供` 这种写成 user, 其他的 (比如 `09/02新鲜应用列表上新`) 写成 note
+pub fn listing_comment(note: &str, source: &str) -> JsonValue {
+    let note = note.trim();
+    let mut comment = serde_json::json!({ "source": source });
+    if let Some(user) = note.strip_suffix("提供") {
+        comment["user"] = user.trim().into();
+    } else if !note.is_empty() {
+        comment["note"] = note.into();
+    }
+    comment
+}
+
+/// 读取表格
+pub fn read_listing_csv(reader: impl std::io::Read) -> Result<Vec<ListingRecord>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .from_reader(reader);
+    let mut records = Vec::new();
+    for row in reader.records() {
+        let row = row?;
+        let line = row.position().map(|p| p.line()).unwrap_or_default();
+        let field = |i: usize| row.get(i).unwrap_or_default().trim().to_string();
+        records.push(ListingRecord {
+            line,
+            name: field(0),
+            platform: field(1),
+            kind: field(2),
+            listed_at: parse_listing_date(&field(3)),
+            developer: field(4),
+            note: field(5),
+            link: field(6),
+        });
+    }
+    Ok(records)
+}
+
+/// 一次写数据库的应用数量
+const SAVE_BATCH: usize = 100;
+
+/// 保存前数据库里还没有的应用数量, 同一批里重复的只算一次
+fn count_new_apps<'a>(
+    app_ids: impl IntoIterator<Item = &'a str>,
+    existing: &HashSet<String>,
+) -> usize {
+    let mut seen = HashSet::new();
+    app_ids
+        .into_iter()
+        .filter(|app_id| !existing.contains(*app_id) && seen.insert(*app_id))
+        .count()
+}
+
+/// 数据库里已经有的应用
+async fn existing_app_ids(db: &Database, app_ids: &[String]) -> Result<HashSet<String>> {
+    let mut conn = db.pool.acquire().await?;
+    let hashes = Database::get_content_hashes(&mut conn, app_ids).await?;
+    Ok(hashes
+        .into_iter()
+        .filter(|hashes| hashes.app_exists)
+        .map(|hashes| hashes.app_id)
+        .collect())
+}
+
+/// 把请求到的数据一起写进数据库
+async fn save_fetched(
+    db: &Database,
+    source: &str,
+    fetched: &mut Vec<(ListingRecord, FetchedApp)>,
+    report: &mut ImportReport,
+    unmatched: &impl Fn(&ListingRecord, String) -> UnmatchedRow,
+) {
+    if fetched.is_empty() {
+        return;
+    }
+    let items: Vec<AppDataItem> = fetched
+        .iter()
+        .map(|(record, data)| AppDataItem {
+            data: &data.0,
+            rating: data.1.as_ref(),
+            listed_at: record.listed_at,
+            comment: Some(listing_comment(&record.note, source)),
+        })
+        .collect();
+    // new_info 在已有应用的数据变了的时候也是 true, 所以保存前先查一下哪些应用已经有了
+    let app_ids: Vec<String> = fetched
+        .iter()
+        .map(|(_, data)| data.0.0.app_id.clone())
+        .collect();
+    let existing = match existing_app_ids(db, &app_ids).await {
+        Ok(existing) => Some(existing),
+        Err(e) => {
+            event!(Level::WARN, "查询已有的应用失败, 不统计新应用: {e:#}");
+            None
+        }
+    };
+    match db.save_app_data_batch(&items).await {
+        Ok(_) => {
+            if let Some(existing) = &existing {
+                report.new_apps += count_new_apps(app_ids.iter().map(String::as_str), existing);
+            }
+            for (record, data) in fetched.iter() {
+                report.synced += 1;
+                // 已经有的应用, 数据没变的时候不会写 listed_at, 这里补上更早的上架时间
+                if let Some(listed_at) = record.listed_at
+                    && let Err(e) = db.lower_listed_at(&data.0.0.app_id, listed_at).await
+                {
+                    event!(Level::WARN, "更新 {} 的上架时间失败: {e}", data.0.0.app_id);
+                }
+            }
+        }
+        Err(e) => {
+            let reason = format!("保存失败: {e:#}");
+            println!("{}", reason.on_red());
+            for (record, _) in fetched.iter() {
+                report.unmatched.push(unmatched(record, reason.clone()));
+            }
+        }
+    }
+    fetched.clear();
+}
+
+/// 导入一个表格文件
+///
+/// dry_run 的时候只检查链接和日期, 不请求也不写数据库
+pub async fn import_listing_file(
+    client: &reqwest::Client,
+    db: &Database,
+    config: &Config,
+    path: &Path,
+    concurrency: usize,
+    dry_run: bool,
+) -> Result<ImportReport> {
+    let file = std::fs::File::open(path).with_context(|| format!("无法读取 {}", path.display()))?;
+    let records = read_listing_csv(file).with_context(|| format!("无法解析 {}", path.display()))?;
+    let file_name = path.display().to_string();
+    let source = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| file_name.clone());
+    println!("从 {file_name} 读取到 {} 行", records.len());
+
+    let mut report = ImportReport {
+        total: records.len(),
+        ..Default::default()
+    };
+    let unmatched = |record: &ListingRecord, reason: String| UnmatchedRow {
+        file: file_name.clone(),
+        line: record.line,
+        name: record.name.clone(),
+        link: record.link.clone(),
+        reason,
+    };
+
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut join_set = tokio::task::JoinSet::new();
+    for record in records {
+        let Some(query) = record.query() else {
+            report
+                .unmatched
+                .push(unmatched(&record, "访问链接里没有 id".to_string()));
+            continue;
+        };
+        if record.listed_at.is_none() {
+            event!(
+                Level::WARN,
+                "{file_name} 第 {} 行的上架时间无法解析, 不设置 listed_at",
+                record.line
+            );
+        }
+        if dry_run {
+            report.synced += 1;
+            continue;
+        }
+        let client = client.clone();
+        let db = db.clone();
+        let api_url = config.api_url().to_string();
+        let locale = config.locale().to_string();
+        let semaphore = semaphore.clone();
+        join_set.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            let result = query_app_or_quarantine(&client, &db, &api_url, &query, &locale).await;
+            (record, result)
+        });
+    }
+
+    // 请求是并发的, 拿到的数据攒够一批再一起写数据库
+    let mut fetched = Vec::new();
+    while let Some(joined) = join_set.join_next().await {
+        let (record, result) = joined?;
+        match result {
+            Ok(data) => fetched.push((record, data)),
+            Err(e) => {
+                let reason = if e.downcast_ref::<AppNotFound>().is_some() {
+                    "应用市场里找不到".to_string()
+                } else {
+                    format!("{e:#}")
+                };
+                println!(
+                    "{}",
+                    format!(
+                        "{file_name} 第 {} 行 {}: {reason}",
+                        record.line, record.name
+                    )
+                    .on_red()
+                );
+                report.unmatched.push(unmatched(&record, reason));
+            }
+        }
+        if fetched.len() >= SAVE_BATCH {
+            save_fetched(db, &source, &mut fetched, &mut report, &unmatched).await;
+        }
+    }
+    save_fetched(db, &source, &mut fetched, &mut report, &unmatched).await;
+    report.unmatched.sort_by_key(|row| row.line);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_detail_link() {
+        let query = parse_detail_link(
+            "https://appgallery.huawei.com/app/detail?id=com.muniao.hmapp&channelId=SHARE&source=appshare",
+        )
+        .unwrap();
+        assert_eq!(query.app_db_name(), "pkg_name");
+        assert_eq!(query.name(), "com.muniao.hmapp");
+
+        let query =
+            parse_detail_link("https://appgallery.huawei.com/app/detail?id=C1164531384803416384")
+                .unwrap();
+        assert_eq!(query.app_db_name(), "app_id");
+
+        assert!(parse_detail_link("").is_none());
+        assert!(parse_detail_link("https://appgallery.huawei.com/app/detail?id=").is_none());
+    }
+
+    #[test]
+    fn test_read_listing_csv() {
+        let data = "游戏名称,上架平台,游戏类型,上架时间,开发者,备注,访问链接\n\
+            木鸟民宿,手机(平板可下载),旅游,2025/09/30,北京爱游易科技有限公司,MeoW友 f09c50** 提供,https://appgallery.huawei.com/app/detail?id=com.muniao.hmapp&channelId=SHARE\n\
+            MathLearning,手机(平板可下载),工具,2025/08/05,\"Application & Managed Services, S.A. de C.V\",08/06新鲜应用列表上新,https://appgallery.huawei.com/app/detail?id=com.muranbord.burst_hue\n\
+            小黑课堂,手机(平板可下载),教育,2025/06/21,武汉小黑课堂科技有限公司,已下架,\n";
+        let records = read_listing_csv(data.as_bytes()).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].line, 2);
+        assert_eq!(
+            records[0].listed_at.unwrap().date_naive(),
+            NaiveDate::from_ymd_opt(2025, 9, 30).unwrap()
+        );
+        assert_eq!(
+            records[1].developer,
+            "Application & Managed Services, S.A. de C.V"
+        );
+        assert!(records[2].query().is_none());
+
+        assert_eq!(
+            listing_comment(&records[0].note, "上新记录"),
+            serde_json::json!({"source": "上新记录", "user": "MeoW友 f09c50**"})
+        );
+        assert_eq!(
+            listing_comment(&records[1].note, "上新记录"),
+            serde_json::json!({"source": "上新记录", "note": "08/06新鲜应用列表上新"})
+        );
+    }
+
+    #[test]
+    fn test_count_new_apps() {
+        let existing = HashSet::from(["C1".to_string()]);
+        // C1 已经有了, 数据变了也不算新应用; C2 在同一批里出现两次只算一次
+        assert_eq!(count_new_apps(["C1", "C2", "C2", "C3"], &existing), 2);
+        assert_eq!(count_new_apps(["C1"], &existing), 0);
+        assert_eq!(count_new_apps([], &existing), 0);
+    }
+}
@@ -0,0 +1,321 @@
+//! 导入外部整理的历史指标
+//!
+//! 外部数据一般只有时间和下载量 (比如 `import_data/what.csv`),
+//! 版本 / 大小之类的字段用时间上最近的一次实际抓取的数据补上,
+//! 写入时 `imported_from` 记录来源, 方便图表和增长计算区分导入的和实际抓取的数据
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::Path,
+};
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use tracing::{Level, event};
+
+use crate::{
+    db::Database,
+    model::{AppMetric, AppQuery},
+    utils::parse_datetime,
+};
+
+/// 时间列可能的列名
+const TIME_COLUMNS: &[&str] = &["data", "date", "time", "created_at"];
+
+/// 外部数据里的一个点
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricPoint {
+    pub at: DateTime<Local>,
+    pub download_count: i64,
+    pub info_score: Option<f64>,
+    pub info_rate_count: Option<i64>,
+}
+
+/// 补全后准备写入的数据
+#[derive(Debug, Default)]
+pub struct BackfillPlan {
+    pub metrics: Vec<AppMetric>,
+    /// 同一时间已经导入过的
+    pub duplicated: usize,
+    /// 不早于最新一次实际数据的, 不导入, 免得变成应用的最新指标
+    pub too_late: usize,
+}
+
+/// 导入结果
+#[derive(Debug, Default, Serialize)]
+pub struct MetricsReport {
+    pub total: usize,
+    pub imported: usize,
+    pub duplicated: usize,
+    pub too_late: usize,
+}
+
+/// 从一行的各个字段生成数据点, 缺少时间或者下载量的返回错误
+fn point_from_fields(fields: &HashMap<String, String>) -> Result<MetricPoint> {
+    let get = |key: &str| fields.get(key).map(|v| v.trim()).filter(|v| !v.is_empty());
+    let time = TIME_COLUMNS
+        .iter()
+        .find_map(|key| get(key))
+        .with_context(|| format!("缺少时间列 ({})", TIME_COLUMNS.join("/")))?;
+    let at = parse_datetime(time).with_context(|| format!("无法解析时间 {time}"))?;
+    let download_count = get("download_count")
+        .context("缺少 download_count")?
+        .parse::<f64>()
+        .context("download_count 不是数字")? as i64;
+    let info_score = get("info_score").and_then(|v| v.parse().ok());
+    let info_rate_count =
+        get("info_rate_count").and_then(|v| v.parse::<f64>().ok().map(|v| v as i64));
+    Ok(MetricPoint {
+        at,
+        download_count,
+        info_score,
+        info_rate_count,
+    })
+}
+
+/// 读取 csv, 第一行是表头
+pub fn read_metrics_csv(reader: impl std::io::Read) -> Result<Vec<MetricPoint>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .from_reader(reader);
+    let headers: Vec<String> = reader
+        .headers()?
+        .iter()
+        .map(|h| h.trim().to_lowercase())
+        .collect();
+    let mut points = Vec::new();
+    for row in reader.records() {
+        let row = row?;
+        let line = row.position().map(|p| p.line()).unwrap_or_default();
+        let fields = headers
+            .iter()
+            .cloned()
+            .zip(row.iter().map(str::to_string))
+            .collect();
+        points.push(point_from_fields(&fields).with_context(|| format!("第 {line} 行"))?);
+    }
+    Ok(points)
+}
+
+/// 读取 json, 是一个对象数组, 键和 csv 的表头一样
+pub fn read_metrics_json(reader: impl std::io::Read) -> Result<Vec<MetricPoint>> {
+    let rows: Vec<serde_json::Map<String, JsonValue>> = serde_json::from_reader(reader)?;
+    rows.iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let fields = row
+                .iter()
+                .map(|(k, v)| {
+                    let v = match v {
+                        JsonValue::String(s) => s.clone(),
+                        v => v.to_string(),
+                    };
+                    (k.to_lowercase(), v)
+                })
+                .collect();
+            point_from_fields(&fields).with_context(|| format!("第 {} 个元素", i + 1))
+        })
+        .collect()
+}
+
+/// 按扩展名读取, `.json` 的当作 json, 其他的当作 csv
+pub fn read_metrics_file(path: &Path) -> Result<Vec<MetricPoint>> {
+    let file = std::fs::File::open(path).with_context(|| format!("无法读取 {}", path.display()))?;
+    let is_json = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    let points = if is_json {
+        read_metrics_json(file)
+    } else {
+        read_metrics_csv(file)
+    };
+    points.with_context(|| format!("无法解析 {}", path.display()))
+}
+
+/// 用实际抓取的数据补全外部数据
+///
+/// `history` 是这个应用已有的全部 metric, 按时间排序.
+/// 每个点用时间上最近的一次实际数据补全其他字段, 一样近的用更早的那次
+pub fn backfill_metrics(
+    points: &[MetricPoint],
+    history: &[AppMetric],
+    source: &str,
+) -> Result<BackfillPlan> {
+    let observed: Vec<&AppMetric> = history
+        .iter()
+        .filter(|m| m.imported_from.is_none())
+        .collect();
+    let Some(latest) = observed.last() else {
+        bail!("这个应用还没有实际抓取的数据, 无法补全");
+    };
+    let imported: BTreeSet<DateTime<Local>> = history
+        .iter()
+        .filter(|m| m.imported_from.is_some())
+        .map(|m| m.created_at)
+        .collect();
+
+    let mut plan = BackfillPlan::default();
+    let mut seen = BTreeSet::new();
+    for point in points {
+        if point.at >= latest.created_at {
+            plan.too_late += 1;
+            continue;
+        }
+        if imported.contains(&point.at) || !seen.insert(point.at) {
+            plan.duplicated += 1;
+            continue;
+        }
+        let nearest = observed
+            .iter()
+            .min_by_key(|m| (m.created_at - point.at).abs())
+            .expect("observed 不为空");
+        let mut metric = (*nearest).clone();
+        metric.id = 0;
+        metric.download_count = point.download_count;
+        if let Some(score) = point.info_score {
+            metric.info_score = score;
+        }
+        if let Some(count) = point.info_rate_count {
+            metric.info_rate_count = count;
+        }
+        metric.imported_from = Some(source.to_string());
+        metric.created_at = point.at;
+        plan.metrics.push(metric);
+    }
+    plan.metrics.sort_by_key(|m| m.created_at);
+    Ok(plan)
+}
+
+/// 导入一个文件里的数据到指定应用
+///
+/// dry_run 的时候只读取和补全, 不写数据库
+pub async fn import_metrics_file(
+    db: &Database,
+    app: &AppQuery,
+    path: &Path,
+    source: &str,
+    dry_run: bool,
+) -> Result<MetricsReport> {
+    let points = read_metrics_file(path)?;
+    println!("从 {} 读取到 {} 个点", path.display(), points.len());
+    let AppQuery::AppId(app_id) = db
+        .app_query_to_app_id(app)
+        .await
+        .with_context(|| format!("找不到应用 {app}"))?
+    else {
+        bail!("找不到应用 {app}");
+    };
+    let history = db.get_app_metric_history(&app_id).await?;
+    let plan = backfill_metrics(&points, &history, source)?;
+    if plan.too_late > 0 {
+        event!(
+            Level::WARN,
+            "{} 个点不早于 {app_id} 最新一次实际抓取的数据, 已跳过",
+            plan.too_late
+        );
+    }
+    if !dry_run && !plan.metrics.is_empty() {
+        db.insert_imported_metrics(&plan.metrics).await?;
+    }
+    Ok(MetricsReport {
+        total: points.len(),
+        imported: plan.metrics.len(),
+        duplicated: plan.duplicated,
+        too_late: plan.too_late,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observed(version: &str, at: &str, download_count: i64) -> AppMetric {
+        AppMetric {
+            id: 1,
+            app_id: "C1164531384803416384".to_string(),
+            version: version.to_string(),
+            version_code: 1,
+            size_bytes: 1,
+            sha256: String::new(),
+            info_score: 4.5,
+            info_rate_count: 350,
+            download_count,
+            price: "0".to_string(),
+            release_date: 0,
+            new_features: String::new(),
+            upgrade_msg: String::new(),
+            target_sdk: 18,
+            minsdk: 13,
+            compile_sdk_version: 50100,
+            min_hmos_api_level: 50001,
+            api_release_type: "Release".to_string(),
+            imported_from: None,
+            created_at: parse_datetime(at).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_read_metrics() {
+        let data = "data,download_count\n\
+            2024-12-06 12:0:0.0 +08:00,1020000\n\
+            2024-12-08 12:0:0.0 +08:00,1140000\n";
+        let points = read_metrics_csv(data.as_bytes()).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(
+            points[0].at,
+            DateTime::parse_from_rfc3339("2024-12-06T12:00:00+08:00").unwrap()
+        );
+        assert_eq!(points[1].download_count, 1140000);
+
+        let data = r#"[{"date": "2024-12-06T12:00:00+08:00", "download_count": 1020000, "info_score": "4.5"}]"#;
+        let points = read_metrics_json(data.as_bytes()).unwrap();
+        assert_eq!(points[0].download_count, 1020000);
+        assert_eq!(points[0].info_score, Some(4.5));
+
+        assert!(read_metrics_csv("data,download_count\nxx,1\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_backfill_metrics() {
+        let history = vec![
+            observed("1.0", "2024-12-01T00:00:00+08:00", 900000),
+            observed("2.0", "2024-12-10T00:00:00+08:00", 1200000),
+        ];
+        let point = |at: &str, download_count| MetricPoint {
+            at: parse_datetime(at).unwrap(),
+            download_count,
+            info_score: None,
+            info_rate_count: None,
+        };
+        let points = vec![
+            point("2024-12-08T00:00:00+08:00", 1140000),
+            point("2024-12-02T00:00:00+08:00", 1020000),
+            point("2024-12-02T00:00:00+08:00", 1020000),
+            point("2024-12-11T00:00:00+08:00", 1300000),
+        ];
+        let plan = backfill_metrics(&points, &history, "what").unwrap();
+        assert_eq!(plan.duplicated, 1);
+        assert_eq!(plan.too_late, 1);
+        assert_eq!(plan.metrics.len(), 2);
+        assert_eq!(plan.metrics[0].version, "1.0");
+        assert_eq!(plan.metrics[0].download_count, 1020000);
+        assert_eq!(plan.metrics[1].version, "2.0");
+        assert_eq!(plan.metrics[1].imported_from.as_deref(), Some("what"));
+        // imported_from 不影响内容哈希
+        let mut expected = history[1].clone();
+        expected.download_count = 1140000;
+        assert_eq!(plan.metrics[1].content_hash(), expected.content_hash());
+
+        // 再导入一次的都算重复
+        let mut history = history;
+        history.extend(plan.metrics);
+        let plan = backfill_metrics(&points, &history, "what").unwrap();
+        assert!(plan.metrics.is_empty());
+        assert_eq!(plan.duplicated, 3);
+
+        assert!(backfill_metrics(&points, &[], "what").is_err());
+    }
+}
@@ -0,0 +1,7 @@
+//! 从外部整理的数据导入
+//!
+//! - [`listing`]: "移动端应用市场上新记录整理" 表格
+//! - [`metrics`]: "历史下载量" 之类的时间序列
+
+pub mod listing;
+pub mod metrics;
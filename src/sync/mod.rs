@@ -9,17 +9,37 @@ use tracing::{Level, event};
 
 use crate::{
     db::Database,
-    model::{AppQuery, RawJsonData, RawRatingData},
+    model::{AppQuery, RawJsonData, RawRatingData, is_atomic_service},
 };
 
 /// token 更新间隔
 pub const TOKEN_UPDATE_INTERVAL: Duration = Duration::from_secs(600);
 
 pub mod code;
+pub mod drift;
+pub mod export;
+pub mod guess;
+pub mod import;
+pub mod quarantine;
+pub mod resolve;
+pub mod source;
 pub mod substance;
 
+pub use quarantine::{ParseFailure, query_app_or_quarantine};
 pub use substance::{SubstanceData, get_app_from_substance};
 
+/// appinfo 返回空响应体, 也就是这个应用不存在
+#[derive(Debug, Clone)]
+pub struct AppNotFound(pub AppQuery);
+
+impl std::fmt::Display for AppNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HTTP响应体为空, {} 不存在", self.0)
+    }
+}
+
+impl std::error::Error for AppNotFound {}
+
 /// UA
 pub static USER_AGENT: LazyLock<String> = LazyLock::new(|| {
     format!(
@@ -200,7 +220,7 @@ pub async fn sync_app(
     listed_at: Option<DateTime<Local>>,
     comment: Option<serde_json::Value>,
 ) -> Result<(bool, bool, bool)> {
-    let app_data = query_app(client, api_url, app_query, locale).await?;
+    let app_data = query_app_or_quarantine(client, db, api_url, app_query, locale).await?;
 
     event!(
         Level::INFO,
@@ -241,16 +261,45 @@ pub async fn query_app(
 ) -> Result<((RawJsonData, JsonValue), Option<RawRatingData>)> {
     let data = get_app_data(client, api_url, app_query, locale)
         .await
-        .map_err(|e| anyhow::anyhow!("获取包 {} 的数据失败: {:#}", app_query, e))?;
+        .with_context(|| format!("获取包 {} 的数据失败", app_query))?;
 
-    let (raw_data, data) = (
-        data.clone(),
-        serde_json::from_value::<RawJsonData>(data)
-            .with_context(|| format!("不是，怎么又解析失败了 {app_query}"))
-            .unwrap(),
-    );
+    parse_app_data(client, api_url, app_query, data).await
+}
+
+/// 解析应用基本信息, 并获取评分信息
+///
+/// # 参数
+/// - `client`: HTTP客户端
+/// - `api_url`: API地址
+/// - `app_query`: 应用查询条件（包名或应用ID）
+/// - `raw_data`: appinfo 接口返回的原始数据
+///
+/// # 返回值
+/// - 解析失败的时候返回 [`ParseFailure`], 里面带着原始数据
+pub async fn parse_app_data(
+    client: &reqwest::Client,
+    api_url: &str,
+    app_query: &AppQuery,
+    raw_data: JsonValue,
+) -> Result<((RawJsonData, JsonValue), Option<RawRatingData>)> {
+    let data = match serde_json::from_value::<RawJsonData>(raw_data.clone()) {
+        Ok(data) => data,
+        Err(e) => {
+            event!(
+                Level::ERROR,
+                "{}",
+                format!("不是，怎么又解析失败了 {app_query}: {e}").on_red()
+            );
+            return Err(ParseFailure {
+                app_query: app_query.clone(),
+                payload: raw_data,
+                error: e.to_string(),
+            }
+            .into());
+        }
+    };
 
-    let star = if !data.pkg_name.starts_with("com.atomicservice") {
+    let star = if !is_atomic_service(&data.pkg_name) {
         let star_result = get_app_rating(client, api_url, &data.app_id).await;
         match star_result {
             Ok(star_data) => Some(star_data),
@@ -324,7 +373,7 @@ pub async fn get_app_data(
     // 检查响应体是否为空
     let content_length = response.content_length().unwrap_or(0);
     if content_length == 0 {
-        return Err(anyhow::anyhow!("HTTP响应体为空"));
+        return Err(AppNotFound(app_query.clone()).into());
     }
     let mut raw = response.json::<serde_json::Value>().await?;
     let raw_obj = raw.as_object_mut().unwrap();
@@ -355,6 +404,10 @@ pub async fn get_app_data(
             raw_obj.insert(key, serde_json::Value::String(value.replace('\0', "")));
         }
     }
+    let app_id = raw["appId"].as_str().unwrap_or(app_query.name());
+    drift::GLOBAL_DRIFT_DETECTOR
+        .observe(drift::SOURCE_APP_INFO, app_id, &raw)
+        .await;
     Ok(raw)
 }
 
@@ -378,8 +431,9 @@ pub async fn get_app_rating(
     api_url: &str,
     app_id: impl ToString,
 ) -> Result<RawRatingData> {
+    let app_id = app_id.to_string();
     let body = serde_json::json!({
-        "pageId": format!("webAgAppDetail|{}", app_id.to_string()),
+        "pageId": format!("webAgAppDetail|{}", app_id),
         "pageNum": 1,
         "pageSize": 100,
         "zone": ""
@@ -429,7 +483,12 @@ pub async fn get_app_rating(
         }
         let star_data = comment_card[0]["data"].get(0).expect("data not found");
         if let Some(star_str) = star_data.get("starInfo") {
-            serde_json::from_str(star_str.as_str().expect("starInfo not str"))?
+            let star_value: JsonValue =
+                serde_json::from_str(star_str.as_str().expect("starInfo not str"))?;
+            drift::GLOBAL_DRIFT_DETECTOR
+                .observe(drift::SOURCE_PAGE_DETAIL, &app_id, &star_value)
+                .await;
+            serde_json::from_value(star_value)?
         } else {
             return Err(anyhow::anyhow!("starInfo not found"));
         }
@@ -0,0 +1,85 @@
+//! 解析失败的数据隔离
+//!
+//! appinfo 返回的数据解析不了的时候, 不再直接 panic,
+//! 而是把原始数据存到 app_quarantine 表里, 等修好 `RawJsonData` 之后再重新处理
+
+use std::fmt::Display;
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tracing::{Level, event};
+
+use crate::{
+    db::Database,
+    model::{AppQuery, RawJsonData, RawRatingData},
+    sync::query_app,
+};
+
+/// 解析失败, 带着原始数据
+#[derive(Debug, Clone)]
+pub struct ParseFailure {
+    pub app_query: AppQuery,
+    pub payload: JsonValue,
+    pub error: String,
+}
+
+impl Display for ParseFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "解析 {} 的数据失败: {}", self.app_query, self.error)
+    }
+}
+
+impl std::error::Error for ParseFailure {}
+
+/// app_quarantine 表
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct QuarantineEntry {
+    pub id: i64,
+    /// app_id / pkg_name
+    pub query_key: String,
+    pub query_value: String,
+    pub raw_json_data: JsonValue,
+    pub error: String,
+    pub attempts: i32,
+    pub resolved_at: Option<DateTime<Local>>,
+    pub created_at: DateTime<Local>,
+    pub updated_at: DateTime<Local>,
+}
+
+impl QuarantineEntry {
+    /// 还原成查询条件
+    pub fn app_query(&self) -> Option<AppQuery> {
+        AppQuery::from_db_name(&self.query_key, &self.query_value)
+    }
+}
+
+/// 查询应用数据, 解析失败的时候把原始数据放进隔离表
+///
+/// 其他行为和 [`query_app`] 一样, 解析失败依然会返回错误
+pub async fn query_app_or_quarantine(
+    client: &reqwest::Client,
+    db: &Database,
+    api_url: &str,
+    app_query: &AppQuery,
+    locale: &str,
+) -> Result<((RawJsonData, JsonValue), Option<RawRatingData>)> {
+    match query_app(client, api_url, app_query, locale).await {
+        Ok(data) => Ok(data),
+        Err(e) => {
+            if let Some(failure) = e.downcast_ref::<ParseFailure>() {
+                match db.insert_quarantine(failure).await {
+                    Ok(_) => event!(Level::WARN, "已将 {} 的原始数据放入隔离表", app_query),
+                    Err(db_err) => event!(
+                        Level::ERROR,
+                        "保存 {} 的原始数据到隔离表失败: {:#}",
+                        app_query,
+                        db_err
+                    ),
+                }
+            }
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! 外部来源
+//!
+//! 第三方网站 (比如 nextmax) 整理的应用列表, 用来发现还没入库的应用.
+//! 每个来源实现 [`ExternalSource`], 由 worker 按 `[sources]` 配置定时抓取,
+//! 抓到的应用记录在 app_source 表里, 第一次把应用带进数据库的来源记为 discovered
+
+use std::{future::Future, pin::Pin, sync::Arc, time::Instant};
+
+use anyhow::Result;
+use colored::Colorize;
+use serde_json::Value as JsonValue;
+use tokio::sync::Semaphore;
+use tracing::{Level, event};
+
+use crate::{
+    config::Config,
+    db::Database,
+    model::AppQuery,
+    sync::{AppNotFound, sync_app},
+};
+
+pub mod nextmax;
+
+/// 来源里的一个应用
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub query: AppQuery,
+    /// 在来源里的 ID
+    pub external_id: Option<String>,
+    /// 来源提供的其他信息, 比如名称 / 图标
+    pub metadata: JsonValue,
+}
+
+pub type SourceFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<Candidate>>> + Send + 'a>>;
+
+/// 外部来源
+pub trait ExternalSource: Send + Sync {
+    /// 来源名称, 写到 app_source.source
+    fn name(&self) -> &'static str;
+
+    /// 抓取来源里的全部应用
+    fn fetch<'a>(&'a self, client: &'a reqwest::Client) -> SourceFuture<'a>;
+}
+
+/// 按名称创建来源, 不认识的名称会被忽略
+pub fn build_sources(names: &[String]) -> Vec<Box<dyn ExternalSource>> {
+    names
+        .iter()
+        .filter_map(|name| match name.as_str() {
+            nextmax::NAME => Some(Box::new(nextmax::Nextmax::default()) as Box<dyn ExternalSource>),
+            _ => {
+                event!(Level::WARN, "未知的外部来源 {name}, 已忽略");
+                None
+            }
+        })
+        .collect()
+}
+
+/// 一次抓取的结果
+#[derive(Debug, Default)]
+pub struct SourceReport {
+    pub total: usize,
+    /// 之前没有入库, 这次新同步的
+    pub discovered: usize,
+    /// 已经入库的
+    pub known: usize,
+    pub failed: usize,
+}
+
+/// 同步来源里的应用并记录来源
+///
+/// 已经入库的只更新 last_seen_at, 没入库的先同步, 成功后记为 discovered
+pub async fn ingest_candidates(
+    client: &reqwest::Client,
+    db: &Database,
+    config: &Config,
+    source: &'static str,
+    candidates: Vec<Candidate>,
+    concurrency: usize,
+) -> SourceReport {
+    let mut report = SourceReport {
+        total: candidates.len(),
+        ..Default::default()
+    };
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut join_set = tokio::task::JoinSet::new();
+    for candidate in candidates {
+        let client = client.clone();
+        let db = db.clone();
+        let api_url = config.api_url().to_string();
+        let locale = config.locale().to_string();
+        let semaphore = semaphore.clone();
+        join_set.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            let existed = db.app_exists(&candidate.query).await;
+            if !existed {
+                sync_app(
+                    &client,
+                    &db,
+                    &api_url,
+                    &candidate.query,
+                    &locale,
+                    None,
+                    Some(serde_json::json!({ "source": source })),
+                )
+                .await?;
+            }
+            let AppQuery::AppId(app_id) = db.app_query_to_app_id(&candidate.query).await? else {
+                unreachable!("app_query_to_app_id 只返回 app_id")
+            };
+            db.upsert_app_source(
+                &app_id,
+                source,
+                candidate.external_id.as_deref(),
+                &candidate.metadata,
+                !existed,
+            )
+            .await?;
+            anyhow::Ok((candidate, existed))
+        });
+    }
+
+    while let Some(result) = join_set.join_next().await {
+        match result {
+            Ok(Ok((_, true))) => report.known += 1,
+            Ok(Ok((candidate, false))) => {
+                report.discovered += 1;
+                event!(
+                    Level::INFO,
+                    "{source} 发现了新应用 {} {}",
+                    candidate.query,
+                    candidate.metadata["name"].as_str().unwrap_or_default()
+                );
+            }
+            Ok(Err(e)) => {
+                report.failed += 1;
+                if e.downcast_ref::<AppNotFound>().is_some() {
+                    event!(Level::INFO, "{source} 里的应用在应用市场里找不到: {e}");
+                } else {
+                    event!(Level::WARN, "同步 {source} 里的应用失败: {e:#}");
+                }
+            }
+            Err(e) => {
+                report.failed += 1;
+                event!(Level::WARN, "同步 {source} 里的应用失败: {e}");
+            }
+        }
+    }
+    report
+}
+
+/// 抓取一个来源并同步
+pub async fn run_source(
+    client: &reqwest::Client,
+    db: &Database,
+    config: &Config,
+    source: &dyn ExternalSource,
+) -> Result<SourceReport> {
+    let start = Instant::now();
+    let candidates = source.fetch(client).await?;
+    event!(
+        Level::INFO,
+        "从 {} 抓取到 {} 个应用, 耗时 {:.2?}",
+        source.name(),
+        candidates.len(),
+        start.elapsed()
+    );
+    let report = ingest_candidates(client, db, config, source.name(), candidates, 4).await;
+    println!(
+        "{}",
+        format!(
+            "{}: 共 {} 个, 新发现 {} 个, 已入库 {} 个, 失败 {} 个, 耗时 {:.2?}",
+            source.name(),
+            report.total,
+            report.discovered,
+            report.known,
+            report.failed,
+            start.elapsed()
+        )
+        .bright_green()
+    );
+    Ok(report)
+}
+
+/// worker 里的定时抓取
+pub struct SourceScheduler {
+    sources: Vec<Box<dyn ExternalSource>>,
+    interval: std::time::Duration,
+    last_run: Vec<Option<Instant>>,
+}
+
+impl SourceScheduler {
+    pub fn new(config: &Config) -> Self {
+        let sources = build_sources(config.enabled_sources());
+        let last_run = vec![None; sources.len()];
+        Self {
+            sources,
+            interval: std::time::Duration::from_secs(config.sources_interval()),
+            last_run,
+        }
+    }
+
+    /// 抓取到时间了的来源, 单个来源失败不影响其他来源
+    pub async fn run_due(&mut self, client: &reqwest::Client, db: &Database, config: &Config) {
+        for (source, last_run) in self.sources.iter().zip(self.last_run.iter_mut()) {
+            if last_run.is_some_and(|at| at.elapsed() < self.interval) {
+                continue;
+            }
+            *last_run = Some(Instant::now());
+            if let Err(e) = run_source(client, db, config, source.as_ref()).await {
+                event!(Level::WARN, "抓取外部来源 {} 失败: {e:#}", source.name());
+            }
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! nextmax.cn
+//!
+//! `/all_apps` 是全部应用的卡片, 卡片只有站内 ID 和名称,
+//! 应用市场的链接要再打开 `/app/{id}` 才有
+
+use std::{collections::HashSet, sync::Arc};
+
+use anyhow::{Context, Result};
+use scraper::{Html, Selector};
+use tokio::sync::Semaphore;
+use tracing::{Level, event};
+
+use super::{Candidate, ExternalSource, SourceFuture};
+use crate::{model::AppQuery, sync::import::listing::parse_detail_link};
+
+pub const NAME: &str = "nextmax";
+
+/// `/all_apps` 里的一张卡片
+#[derive(Debug, Clone, PartialEq)]
+pub struct NextmaxCard {
+    /// 站内 ID
+    pub id: String,
+    pub name: String,
+    pub icon: Option<String>,
+}
+
+pub struct Nextmax {
+    pub base_url: String,
+    /// 同时打开详情页的数量
+    pub concurrency: usize,
+}
+
+impl Default for Nextmax {
+    fn default() -> Self {
+        Self {
+            base_url: "https://nextmax.cn".to_string(),
+            concurrency: 4,
+        }
+    }
+}
+
+/// 解析 `/all_apps`
+///
+/// 卡片是 `<div class="app-card" onclick="window.location.href='/app/123'">`,
+/// 里面的 `<img alt="名称" data-src="图标">`
+pub fn parse_all_apps(html: &str) -> Vec<NextmaxCard> {
+    let document = Html::parse_document(html);
+    let card_selector = Selector::parse(".app-card").unwrap();
+    let img_selector = Selector::parse("img").unwrap();
+    let mut seen = HashSet::new();
+    document
+        .select(&card_selector)
+        .filter_map(|card| {
+            let onclick = card.attr("onclick")?;
+            let (_, rest) = onclick.split_once("/app/")?;
+            let id: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+            if id.is_empty() {
+                return None;
+            }
+            let img = card.select(&img_selector).next();
+            let name = img
+                .and_then(|img| img.attr("alt"))
+                .map(|alt| alt.trim().to_string())
+                .unwrap_or_default();
+            let icon = img
+                .and_then(|img| img.attr("data-src").or_else(|| img.attr("src")))
+                .filter(|src| src.starts_with("http"))
+                .map(str::to_string);
+            Some(NextmaxCard { id, name, icon })
+        })
+        .filter(|card| seen.insert(card.id.clone()))
+        .collect()
+}
+
+/// 解析 `/app/{id}`, 找到应用市场的链接
+pub fn parse_app_detail(html: &str) -> Option<AppQuery> {
+    let document = Html::parse_document(html);
+    let link_selector = Selector::parse(r#"a[href*="appgallery.huawei.com/app/detail"]"#).unwrap();
+    document
+        .select(&link_selector)
+        .find_map(|link| parse_detail_link(link.attr("href")?))
+}
+
+async fn get_text(client: &reqwest::Client, url: &str) -> Result<String> {
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .with_context(|| format!("请求 {url} 失败"))?
+        .error_for_status()?;
+    Ok(response.text().await?)
+}
+
+impl Nextmax {
+    async fn fetch_all(&self, client: &reqwest::Client) -> Result<Vec<Candidate>> {
+        let body = get_text(client, &format!("{}/all_apps", self.base_url)).await?;
+        let cards = parse_all_apps(&body);
+        event!(Level::INFO, "nextmax 共有 {} 张应用卡片", cards.len());
+
+        let semaphore = Arc::new(Semaphore::new(self.concurrency.max(1)));
+        let mut join_set = tokio::task::JoinSet::new();
+        for card in cards {
+            let client = client.clone();
+            let url = format!("{}/app/{}", self.base_url, card.id);
+            let semaphore = semaphore.clone();
+            join_set.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let body = get_text(&client, &url).await;
+                (card, body)
+            });
+        }
+
+        let mut candidates = Vec::new();
+        while let Some(result) = join_set.join_next().await {
+            let (card, body) = result?;
+            let body = match body {
+                Ok(body) => body,
+                Err(e) => {
+                    event!(Level::WARN, "打开 nextmax 应用 {} 失败: {e:#}", card.id);
+                    continue;
+                }
+            };
+            let Some(query) = parse_app_detail(&body) else {
+                event!(
+                    Level::DEBUG,
+                    "nextmax 应用 {} {} 没有应用市场链接",
+                    card.id,
+                    card.name
+                );
+                continue;
+            };
+            candidates.push(Candidate {
+                query,
+                external_id: Some(card.id),
+                metadata: serde_json::json!({
+                    "name": card.name,
+                    "icon": card.icon,
+                }),
+            });
+        }
+        Ok(candidates)
+    }
+}
+
+impl ExternalSource for Nextmax {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn fetch<'a>(&'a self, client: &'a reqwest::Client) -> SourceFuture<'a> {
+        Box::pin(self.fetch_all(client))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_all_apps() {
+        let html = r#"<html><body><div class="grid">
+            <div class="app-card" onclick="window.location.href='/app/42'">
+                <img data-src="https://appimg-drcn.dbankcdn.com/application/icon144/ff24bbc8ef444929bf0188f621bdebba.png" alt="滴滴出行" src="data:image/gif;base64,R0lGOD">
+                <div class="app-name">滴滴出行</div>
+            </div>
+            <div class="app-card featured" onclick="window.location.href='/app/7'"><img alt=" QQ音乐 "></div>
+            <div class="app-card" onclick="window.location.href='/app/42'"></div>
+            <div class="app-card" onclick="alert(1)"></div>
+        </div></body></html>"#;
+        let cards = parse_all_apps(html);
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].id, "42");
+        assert_eq!(cards[0].name, "滴滴出行");
+        assert!(cards[0].icon.as_deref().unwrap().ends_with(".png"));
+        assert_eq!(cards[1].id, "7");
+        assert_eq!(cards[1].name, "QQ音乐");
+        assert_eq!(cards[1].icon, None);
+    }
+
+    #[test]
+    fn test_parse_app_detail() {
+        let html = r#"<div><a href="https://nextmax.cn/">首页</a>
+            <a class="btn" href="https://appgallery.huawei.com/app/detail?id=com.tencent.hm.qqmusic&amp;channelId=SHARE">去下载</a></div>"#;
+        let query = parse_app_detail(html).unwrap();
+        assert_eq!(query.name(), "com.tencent.hm.qqmusic");
+        assert!(parse_app_detail("<div></div>").is_none());
+    }
+}
@@ -2,6 +2,10 @@
 
 use std::borrow::Cow;
 
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
 use tracing::Level;
 
 pub fn init_log() {
@@ -81,12 +85,103 @@ pub fn has_invalid_utf8_chars(s: &str) -> bool {
             .any(|c| c.is_control() && c != '\n' && c != '\r' && c != '\t')
 }
 
+/// 计算 JSON 内容的哈希 (sha256, 小写十六进制)
+///
+/// serde_json 的 Map 是 BTreeMap, 序列化出来的 key 是排好序的
+/// 所以同样的内容不管 key 原来是什么顺序, 得到的哈希都一样
+pub fn json_content_hash(value: &JsonValue) -> String {
+    let canonical = serde_json::to_string(value).unwrap_or_default();
+    format!("{:x}", Sha256::digest(canonical.as_bytes()))
+}
+
+/// 计算结构体的内容哈希, 忽略 `skip` 里列出的字段
+///
+/// 用来排除 id / created_at 这类每次都会变的字段
+pub fn content_hash_without<T: Serialize>(data: &T, skip: &[&str]) -> String {
+    let mut value = serde_json::to_value(data).unwrap_or_default();
+    if let Some(obj) = value.as_object_mut() {
+        for key in skip {
+            obj.remove(*key);
+        }
+    }
+    json_content_hash(&value)
+}
+
+/// 解析用户给的时间
+///
+/// 支持 RFC3339, `2024-12-06 12:0:0.0 +08:00` 这种带时区的, 以及不带时区的时间和日期 (当作本地时间)
+pub fn parse_datetime(value: &str) -> Option<DateTime<Local>> {
+    let value = value.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.with_timezone(&Local));
+    }
+    for fmt in ["%Y-%m-%d %H:%M:%S%.f %:z", "%Y-%m-%d %H:%M:%S%.f %z"] {
+        if let Ok(time) = DateTime::<FixedOffset>::parse_from_str(value, fmt) {
+            return Some(time.with_timezone(&Local));
+        }
+    }
+    let naive = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f")
+        .ok()
+        .or_else(|| {
+            ["%Y-%m-%d", "%Y/%m/%d"]
+                .iter()
+                .find_map(|fmt| NaiveDate::parse_from_str(value, fmt).ok())
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })?;
+    Local.from_local_datetime(&naive).earliest()
+}
+
+/// 把数字转成字母序列
+///
+/// 0 -> a, 25 -> z, 26 -> aa, 27 -> ab ...
+pub fn i32_to_letters(mut n: i32) -> String {
+    let mut s = String::new();
+    while n >= 0 {
+        let c = (b'a' + (n % 26) as u8) as char;
+        s.insert(0, c);
+        n = n / 26 - 1;
+    }
+    s
+}
+
+/// [`i32_to_letters`] 的逆运算, "a" -> 0, "aa" -> 26
+///
+/// 不是小写字母序列返回 None
+pub fn letters_to_i32(s: &str) -> Option<i32> {
+    if s.is_empty() {
+        return None;
+    }
+    s.bytes().try_fold(-1_i32, |acc, c| {
+        if !c.is_ascii_lowercase() {
+            return None;
+        }
+        acc.checked_add(1)?
+            .checked_mul(26)?
+            .checked_add((c - b'a') as i32)
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use crate::utils::{
-        ensure_valid_utf8, has_invalid_utf8_chars, remove_null_bytes, sanitize_utf8_string,
+        content_hash_without, ensure_valid_utf8, has_invalid_utf8_chars, i32_to_letters,
+        json_content_hash, letters_to_i32, remove_null_bytes, sanitize_utf8_string,
     };
 
+    #[test]
+    fn test_i32_to_letters() {
+        assert_eq!(i32_to_letters(0), "a");
+        assert_eq!(i32_to_letters(25), "z");
+        assert_eq!(i32_to_letters(26), "aa");
+        assert_eq!(i32_to_letters(27), "ab");
+        assert_eq!(i32_to_letters(701), "zz");
+        assert_eq!(i32_to_letters(702), "aaa");
+        for n in [0, 25, 26, 701, 702, 12345] {
+            assert_eq!(letters_to_i32(&i32_to_letters(n)), Some(n));
+        }
+        assert_eq!(letters_to_i32("A1"), None);
+    }
+
     #[test]
     fn test_sanitize_utf8_string() {
         // 测试空字符串
@@ -128,4 +223,21 @@ mod tests {
         assert!(has_invalid_utf8_chars("文本\0"));
         assert!(has_invalid_utf8_chars("文本\x01"));
     }
+
+    #[test]
+    fn test_json_content_hash() {
+        let a: serde_json::Value = serde_json::from_str(r#"{"b": 1, "a": [1, 2]}"#).unwrap();
+        let b: serde_json::Value = serde_json::from_str(r#"{"a": [1, 2], "b": 1}"#).unwrap();
+        let c: serde_json::Value = serde_json::from_str(r#"{"a": [2, 1], "b": 1}"#).unwrap();
+        assert_eq!(json_content_hash(&a), json_content_hash(&b));
+        assert_ne!(json_content_hash(&a), json_content_hash(&c));
+        assert_eq!(json_content_hash(&a).len(), 64);
+
+        // 忽略的字段不影响哈希
+        let d: serde_json::Value = serde_json::from_str(r#"{"a": [1, 2], "b": 2}"#).unwrap();
+        assert_eq!(
+            content_hash_without(&a, &["b"]),
+            content_hash_without(&d, &["b"])
+        );
+    }
 }